@@ -1,11 +1,26 @@
 use formula_rs_wasm::{
-    parse::{ast::to_ast, dependencies::get_dependencies, parse::Formula, to_operator::ToOperator},
-    vm::{context::RuntimeContext, error::ExecuteError, runner::Runner, value::Value},
+    editor::monaco::trace_result,
+    migrate::{migrate, verify::verify},
+    parse::{
+        ast::{to_ast, Range},
+        parse::Formula,
+    },
+    share::program::{CompileOptions, Program},
+    vm::{
+        context::RuntimeContext,
+        debugger::Debugger,
+        disassembler::{disassemble, instruction},
+        error::ExecuteError,
+        runner::Runner,
+        trace::Trace,
+        value::Value,
+    },
 };
 use hashbrown::{HashMap, HashSet};
 use num::{FromPrimitive, Rational64};
 use std::{
-    fs, io,
+    fs,
+    io::{self, BufRead, Write},
     sync::{Arc, Mutex},
     time::{Instant, SystemTime},
 };
@@ -27,9 +42,27 @@ pub struct FormulaItem {
 }
 
 fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    if args.get(1).map(String::as_str) == Some("migrate") {
+        migrate_command(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("disasm") {
+        disasm_command(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("debug") {
+        debug_command(&args[2..]);
+        return;
+    }
+
     let start = Instant::now();
 
-    let (byte_code_map, has_formula_issue_types) = get_formulas("prod");
+    // --no-optimize 关闭编译优化，用来对照优化前后的执行结果
+    let options = CompileOptions {
+        optimize: !args.iter().any(|arg| arg == "--no-optimize"),
+    };
+    let (byte_code_map, has_formula_issue_types) = get_formulas("prod", &options);
     let issues = get_issues(&has_formula_issue_types, "prod_full");
 
     let ok_number = Arc::new(Mutex::new(0)); // @1
@@ -100,7 +133,283 @@ fn main() {
     // println!("err_set: {:#?}", *err_count_map.lock().unwrap());
 }
 
-fn get_formulas(env: &str) -> (HashMap<(i64, String), Vec<u8>>, HashSet<i64>) {
+// formula migrate <formulas.txt> [--verify <issues.json>]
+// 每行一个旧公式，输出改写后的公式；--verify 时在样本 issue 上比较改写前后的结果
+fn migrate_command(args: &[String]) {
+    let usage = "usage: formula migrate <formulas.txt> [--verify <issues.json>]";
+    let (path, issues_path) = match args {
+        [path] => (path, None),
+        [path, flag, issues] if flag == "--verify" => (path, Some(issues)),
+        _ => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
+    };
+
+    let contents = fs::read_to_string(path).expect("Should have been able to read the file");
+    let issues = issues_path.map(|issues_path| {
+        let contents =
+            fs::read_to_string(issues_path).expect("Should have been able to read the file");
+        match serde_json::from_str::<JsonValue>(&contents).unwrap() {
+            JsonValue::Array(issues) => issues,
+            issue => vec![issue],
+        }
+    });
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+    let today = now - (now % 86400000);
+
+    let mut failed = 0;
+    for (line, expr) in contents.lines().enumerate() {
+        if expr.trim().is_empty() {
+            continue;
+        }
+
+        let migrated = match migrate(expr) {
+            Ok(migrated) => migrated,
+            Err(e) => {
+                eprintln!(
+                    "line {}: {:?} {}",
+                    line + 1,
+                    e.type_,
+                    e.message.unwrap_or_default()
+                );
+                failed += 1;
+                continue;
+            }
+        };
+        println!("{}", migrated);
+
+        if let Some(issues) = &issues {
+            for mismatch in verify(expr, &migrated, issues, now, today) {
+                eprintln!("line {}: {}", line + 1, mismatch.describe());
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+// formula disasm [--no-optimize] <expression>
+// formula disasm --bytes <program.bin>
+// 打印公式编译后的指令，或者读取保存的字节码
+fn disasm_command(args: &[String]) {
+    let usage =
+        "usage: formula disasm [--no-optimize] <expression> | formula disasm --bytes <program.bin>";
+    let output = match args {
+        [flag, path] if flag == "--bytes" => {
+            let bytes = fs::read(path).expect("Should have been able to read the file");
+            match Program::from_bytes(&bytes) {
+                Ok(program) => disassemble(&program, None),
+                Err(e) => {
+                    eprintln!("{:?} {}", e.type_, e.message.unwrap_or_default());
+                    std::process::exit(1);
+                }
+            }
+        }
+        [flag, expr] if flag == "--no-optimize" => disasm_expression(expr, false),
+        [expr] => disasm_expression(expr, true),
+        _ => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
+    };
+    println!("{}", output);
+}
+
+fn disasm_expression(expr: &str, optimize: bool) -> String {
+    let formula = match Formula::parse(expr) {
+        Ok(formula) => formula,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let (_, ast) = to_ast(formula.paris);
+    let program = Program::compile(&ast, None, &CompileOptions { optimize });
+    disassemble(&program, Some(expr))
+}
+
+// formula debug [--no-optimize] [--json] <expression> <issue.json>
+// 在 Issue 上回放公式的执行过程，--json 输出完整的执行记录，否则从标准输入读取调试命令
+fn debug_command(args: &[String]) {
+    let usage = "usage: formula debug [--no-optimize] [--json] <expression> <issue.json>";
+    let flags = args
+        .iter()
+        .filter(|arg| arg.starts_with("--"))
+        .collect::<Vec<_>>();
+    let (expr, path) = match args
+        .iter()
+        .filter(|arg| !arg.starts_with("--"))
+        .collect::<Vec<_>>()[..]
+    {
+        [expr, path]
+            if flags
+                .iter()
+                .all(|flag| *flag == "--no-optimize" || *flag == "--json") =>
+        {
+            (expr, path)
+        }
+        _ => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
+    };
+
+    let formula = match Formula::parse(expr) {
+        Ok(formula) => formula,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let (_, ast) = to_ast(formula.paris);
+    let options = CompileOptions {
+        optimize: !flags.iter().any(|flag| *flag == "--no-optimize"),
+    };
+    let program = Arc::new(Program::compile(&ast, None, &options));
+
+    let contents = fs::read_to_string(path).expect("Should have been able to read the file");
+    let issue: JsonValue = serde_json::from_str(&contents).unwrap();
+    let mut ctx = RuntimeContext::new();
+    ctx.inject_functions();
+    mock_time(&mut ctx, &issue);
+    load_dependencies(&program, &mut ctx, &issue);
+    let trace = Trace::record(&program, &mut ctx);
+
+    if flags.iter().any(|flag| *flag == "--json") {
+        println!("{}", trace_result(expr, &trace));
+        return;
+    }
+    debug_session(expr, Debugger::new(trace));
+}
+
+// 调试命令：
+// s 执行下一条指令，c 执行到下一个断点，r 回到开始
+// b <start>..<end> 或 b <源码> 设置断点，d <start>..<end> 删除断点
+// stack 查看值栈，heap 查看变量，q 退出
+fn debug_session(expr: &str, mut debugger: Debugger) {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(debug) ");
+        io::stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => return,
+        };
+        let (command, argument) = match line.trim().split_once(' ') {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line.trim(), ""),
+        };
+
+        match command {
+            "s" | "step" => match debugger.step().is_some() {
+                true => print_step(expr, &debugger),
+                false => print_result(&debugger),
+            },
+            "c" | "continue" => match debugger.resume().is_some() {
+                true => print_step(expr, &debugger),
+                false => print_result(&debugger),
+            },
+            "r" | "restart" => debugger.restart(),
+            "b" | "break" => match breakpoint(expr, argument) {
+                Some(range) => {
+                    println!("breakpoint {}..{}", range.0, range.1);
+                    debugger.add_breakpoint(range);
+                }
+                None => println!("no such source: {}", argument),
+            },
+            "d" | "delete" => match breakpoint(expr, argument) {
+                Some(range) if debugger.remove_breakpoint(&range) => {}
+                _ => println!("no such breakpoint: {}", argument),
+            },
+            "stack" => {
+                for (index, value) in debugger.stack().iter().enumerate().rev() {
+                    println!("{:>4}  {}", index, value);
+                }
+            }
+            "heap" => {
+                for (name, value) in debugger.heap() {
+                    println!("{} = {}", name, value);
+                }
+            }
+            "q" | "quit" => return,
+            "" => {}
+            _ => println!("unknown command: {}", command),
+        }
+    }
+}
+
+fn breakpoint(expr: &str, argument: &str) -> Option<Range> {
+    if let Some((start, end)) = argument.split_once("..") {
+        if let (Ok(start), Ok(end)) = (start.parse(), end.parse()) {
+            return Some(Range(start, end));
+        }
+    }
+    match argument {
+        "" => None,
+        text => expr
+            .find(text)
+            .map(|start| Range(start, start + text.len())),
+    }
+}
+
+fn print_step(expr: &str, debugger: &Debugger) {
+    let step = match debugger.current() {
+        Some(step) => step,
+        None => return,
+    };
+    let source = step
+        .range
+        .as_ref()
+        .map(|range| {
+            let text = expr.get(range.0..range.1).unwrap_or_default();
+            format!("{}..{}  {}", range.0, range.1, text.replace('\n', " "))
+        })
+        .unwrap_or_default();
+    println!(
+        "{}{:>4}  {:<40} {}",
+        "  ".repeat(step.depth),
+        step.pc,
+        instruction(&debugger.trace().program, step.pc, &step.operator),
+        source.trim_end()
+    );
+    match &step.error {
+        Some(error) => println!(
+            "      error {:?} {}",
+            error.type_,
+            error.message.clone().unwrap_or_default()
+        ),
+        None => {
+            if let Some(value) = step.stack.last() {
+                println!("      => {}", value);
+            }
+        }
+    }
+}
+
+fn print_result(debugger: &Debugger) {
+    match debugger.result() {
+        Ok(value) => println!("finished: {}", value),
+        Err(error) => println!(
+            "finished with error {:?} {}",
+            error.type_,
+            error.message.clone().unwrap_or_default()
+        ),
+    }
+}
+
+fn get_formulas(
+    env: &str,
+    options: &CompileOptions,
+) -> (HashMap<(i64, String), Arc<Program>>, HashSet<i64>) {
     let contents = fs::read_to_string(format!("data/{}_formula.json", env))
         .expect("Should have been able to read the file");
 
@@ -112,7 +421,9 @@ fn get_formulas(env: &str) -> (HashMap<(i64, String), Vec<u8>>, HashSet<i64>) {
     arr.iter()
         // .filter(|formula| formula.field_code == "customfield_48809227")
         .for_each(|formula| {
-            let byte_code = compile(&formula.expression);
+            // 和线上一样先保存成字节码，执行前读取一次，之后每个 Issue 共用
+            let byte_code =
+                Arc::new(Program::from_bytes(&compile(&formula.expression, options)).unwrap());
             has_formula_issue_types.insert(formula.issue_type_id);
             // key is issue_type_id + field_code
             byte_code_map.insert(
@@ -142,35 +453,34 @@ fn get_issues(has_formula_issue_types: &HashSet<i64>, env: &str) -> Vec<JsonValu
     issues_value
 }
 
-fn compile(expr: &str) -> Vec<u8> {
+fn compile(expr: &str, options: &CompileOptions) -> Vec<u8> {
     let formula = Formula::parse(expr).unwrap();
     let (_, ast) = to_ast(formula.paris);
-    let operators = ast.to_operator();
-    let encoded: Vec<u8> = bincode::serialize(&operators).unwrap();
-    encoded
+    Program::compile(&ast, None, options).to_bytes()
 }
 
 fn run(
-    byte_code: &Vec<u8>,
+    program: &Arc<Program>,
     runner: &Runner,
     ctx: &mut RuntimeContext,
     issue: &JsonValue,
 ) -> Result<Value, ExecuteError> {
-    let operators = bincode::deserialize(&byte_code[..]).unwrap();
-    let dependencies = get_dependencies(&operators);
+    load_dependencies(program, ctx, issue);
+
+    let result = runner.run_program(program, ctx);
 
-    for dependency in dependencies {
-        if ctx.has(&dependency) {
+    result
+}
+
+fn load_dependencies(program: &Program, ctx: &mut RuntimeContext, issue: &JsonValue) {
+    for dependency in &program.dependencies {
+        if ctx.has(dependency) {
             continue;
         }
-        let value = Value::from_json(&issue[&dependency]);
+        let value = Value::from_json(&issue[dependency]);
         // println!("dependency: {}", &dependency);
-        ctx.set(dependency, value);
+        ctx.set(dependency.clone(), value);
     }
-
-    let result = runner.run(operators, ctx);
-
-    result
 }
 
 fn mock_time(ctx: &mut RuntimeContext, issue: &JsonValue) {
@@ -0,0 +1,207 @@
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use super::{call_contexts, in_comment, in_string, word_range};
+use crate::{
+    parse::{
+        ast::{ExpressionKind, Range},
+        diagnostic::Language,
+        recover::parse_tolerant,
+    },
+    share::function::FUNCTIONS,
+    types::{
+        infer::{element_fields, field_of},
+        schema::{Schema, SchemaField},
+        types::FormulaValueType,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompletionKind {
+    Field,    // schema 中的字段
+    Property, // 对象或数组元素的属性
+    Function,
+    Keyword,
+}
+
+impl CompletionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompletionKind::Field => "field",
+            CompletionKind::Property => "property",
+            CompletionKind::Function => "function",
+            CompletionKind::Keyword => "keyword",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: CompletionKind,
+    pub detail: String, // 字段类型或者函数签名
+    pub documentation: Option<String>,
+    pub range: Range, // 选中后替换掉的文本，也就是已经输入的前缀
+}
+
+const KEYWORDS: [&str; 3] = ["type", "func", "let"];
+
+pub fn complete(text: &str, offset: usize, schema: &Schema, lang: Language) -> Vec<CompletionItem> {
+    let offset = offset.min(text.len());
+    if !text.is_char_boundary(offset) || in_string(text, offset) || in_comment(text, offset) {
+        return Vec::new();
+    }
+    // let 绑定的名字和字段一起补全
+    let (body, _) = parse_tolerant(text, lang);
+    let scope = body.scope(schema);
+    let schema = &scope;
+
+    let nodes = body.nodes_at(offset);
+    let node = nodes.last().copied();
+    let range = word_range(node, offset);
+    let prefix = &text[range.0..range.1];
+    let calls = call_contexts(text, &nodes, offset).collect::<Vec<_>>();
+
+    // subtask.| 只补全 subtask 元素的属性
+    if let Some(object) = node.and_then(|node| member_object(node, offset, lang)) {
+        // where(subtask, $.|) 中 $ 是第一个参数的元素
+        let mut members = schema.clone();
+        let element = calls
+            .iter()
+            .find(|call| call.argument_index > 0)
+            .and_then(|call| element_fields(call.first_argument()?, schema));
+        if let Some(fields) = element {
+            members.set(
+                "$".to_string(),
+                SchemaField {
+                    type_: FormulaValueType::Object,
+                    fields: Some(fields),
+                },
+            );
+        }
+        return match field_of(object.as_ref(), &members).and_then(|field| field.fields) {
+            Some(fields) => field_items(&fields, CompletionKind::Property, prefix, &range),
+            None => Vec::new(),
+        };
+    }
+
+    let mut items = Vec::new();
+
+    // 旧的过滤写法 COUNT(subtask; status=2)，从第二个参数开始可以直接使用数组元素的属性
+    if let Some(call) = calls.first() {
+        if call.argument_index > 0 {
+            if let Some(fields) = call
+                .first_argument()
+                .and_then(|first_argument| element_fields(first_argument, schema))
+            {
+                items.extend(field_items(
+                    &fields,
+                    CompletionKind::Property,
+                    prefix,
+                    &range,
+                ));
+            }
+        }
+    }
+
+    items.extend(field_items(schema, CompletionKind::Field, prefix, &range));
+
+    for function in FUNCTIONS.iter() {
+        if matches_prefix(function.name, prefix) {
+            items.push(CompletionItem {
+                label: function.name.to_string(),
+                kind: CompletionKind::Function,
+                detail: function.signature(),
+                documentation: Some(
+                    match lang {
+                        Language::En => function.doc,
+                        Language::Zh => function.doc_zh,
+                    }
+                    .to_string(),
+                ),
+                range: range.clone(),
+            });
+        }
+    }
+
+    for keyword in KEYWORDS {
+        if !prefix.is_empty() && matches_prefix(keyword, prefix) {
+            items.push(CompletionItem {
+                label: keyword.to_string(),
+                kind: CompletionKind::Keyword,
+                detail: "keyword".to_string(),
+                documentation: None,
+                range: range.clone(),
+            });
+        }
+    }
+
+    items
+}
+
+fn field_items(
+    schema: &Schema,
+    kind: CompletionKind,
+    prefix: &str,
+    range: &Range,
+) -> Vec<CompletionItem> {
+    let mut fields = schema
+        .fields
+        .iter()
+        .filter(|(name, _)| matches_prefix(name, prefix))
+        .collect::<Vec<(&String, &SchemaField)>>();
+    fields.sort_by(|a, b| a.0.cmp(b.0));
+
+    fields
+        .into_iter()
+        .map(|(name, field)| CompletionItem {
+            label: name.clone(),
+            kind,
+            detail: field.type_.to_string(),
+            documentation: None,
+            range: range.clone(),
+        })
+        .collect()
+}
+
+fn matches_prefix(label: &str, prefix: &str) -> bool {
+    label.to_lowercase().starts_with(&prefix.to_lowercase())
+}
+
+// 属性访问中点前面的对象，`subtask.|` 解析失败时去掉末尾的点重新解析
+fn member_object(
+    node: &ExpressionKind,
+    offset: usize,
+    lang: Language,
+) -> Option<Cow<'_, ExpressionKind>> {
+    match node {
+        ExpressionKind::PropertyAccessExpressionKind(_, access)
+            if access.property.0 .0 <= offset =>
+        {
+            Some(Cow::Borrowed(&*access.object.1))
+        }
+        ExpressionKind::ErrorKind(range, error) if range.1 == offset => {
+            let object = error.raw.strip_suffix('.')?;
+            let object = object.strip_suffix('?').unwrap_or(object);
+            let (mut body, _) = parse_tolerant(object, lang);
+            if body.body.len() != 1 {
+                return None;
+            }
+            let object = body.body.remove(0).1.expression.1;
+            Some(Cow::Owned(last_operand(object)))
+        }
+        _ => None,
+    }
+}
+
+// `1 + subtask.|` 中点前面的对象是最后一个操作数
+fn last_operand(expression: ExpressionKind) -> ExpressionKind {
+    match expression {
+        ExpressionKind::BinaryExpressionKind(_, binary) => last_operand(*binary.right.1),
+        ExpressionKind::UnaryExpressionKind(_, unary) => last_operand(*unary.argument.1),
+        expression => expression,
+    }
+}
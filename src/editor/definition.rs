@@ -0,0 +1,99 @@
+use alloc::vec::Vec;
+
+use crate::parse::{
+    ast::{ExpressionKind, FormulaBody, Range},
+    diagnostic::Language,
+    recover::parse_tolerant,
+    type_ast::TypeItemKind,
+};
+
+// 跳转到 type / func / let 声明，返回声明中名字的位置
+pub fn definition(text: &str, offset: usize) -> Option<Range> {
+    let (body, _) = parse_tolerant(text, Language::En);
+    let name = name_at(&body, offset)?;
+
+    match find_declaration(&body, name, offset)? {
+        ExpressionKind::TypeDefineKind(_, type_define) => Some(type_define.ident.0.clone()),
+        ExpressionKind::FuncDefineKind(_, func_define) => Some(func_define.ident.0.clone()),
+        ExpressionKind::LetBindingKind(_, binding) => Some(binding.ident.0.clone()),
+        _ => None,
+    }
+}
+
+// let 可以重复绑定同一个名字，返回光标处可见的最近的一个，绑定的值中使用的是前面的绑定
+pub(crate) fn find_declaration<'a>(
+    body: &'a FormulaBody,
+    name: &str,
+    offset: usize,
+) -> Option<&'a ExpressionKind> {
+    let declarations = body
+        .body
+        .iter()
+        .map(|(_, statement)| &statement.expression.1)
+        .filter(|expression| match expression {
+            ExpressionKind::TypeDefineKind(_, type_define) => type_define.ident.1.name == name,
+            ExpressionKind::FuncDefineKind(_, func_define) => func_define.ident.1.name == name,
+            ExpressionKind::LetBindingKind(_, binding) => binding.ident.1.name == name,
+            _ => false,
+        })
+        .collect::<Vec<_>>();
+    declarations
+        .iter()
+        .rev()
+        .find(|expression| match expression {
+            ExpressionKind::LetBindingKind(range, binding) => {
+                binding.ident.0.contains(offset) || range.1 <= offset
+            }
+            expression => expression.range().0 <= offset,
+        })
+        .or(declarations.first())
+        .copied()
+}
+
+// 光标下的标识符或者类型名
+fn name_at(body: &FormulaBody, offset: usize) -> Option<&str> {
+    match body.nodes_at(offset).last()? {
+        ExpressionKind::IdentifierKind(_, identifier) => Some(identifier.name.as_str()),
+        ExpressionKind::LetBindingKind(_, binding) if binding.ident.0.contains(offset) => {
+            Some(binding.ident.1.name.as_str())
+        }
+        ExpressionKind::TypeDefineKind(_, type_define) => {
+            if type_define.ident.0.contains(offset) {
+                return Some(type_define.ident.1.name.as_str());
+            }
+            type_name_at(&type_define.type_item, offset)
+        }
+        ExpressionKind::FuncDefineKind(_, func_define) => {
+            if func_define.ident.0.contains(offset) {
+                return Some(func_define.ident.1.name.as_str());
+            }
+            func_define
+                .arguments
+                .iter()
+                .chain(core::iter::once(&func_define.return_type))
+                .find_map(|item| type_name_at(item, offset))
+        }
+        _ => None,
+    }
+}
+
+fn type_name_at(item: &(Range, TypeItemKind), offset: usize) -> Option<&str> {
+    if !item.0.contains(offset) {
+        return None;
+    }
+    match &item.1 {
+        TypeItemKind::NamedTypeKind(named_type) => {
+            if named_type.ident.0.contains(offset) {
+                return Some(named_type.ident.1.name.as_str());
+            }
+            named_type
+                .parameters
+                .iter()
+                .find_map(|parameter| type_name_at(parameter, offset))
+        }
+        TypeItemKind::RecordTypeKind(record_type) => record_type
+            .fields
+            .iter()
+            .find_map(|(_, field)| type_name_at(&field.value, offset)),
+    }
+}
@@ -0,0 +1,72 @@
+use alloc::{string::String, vec::Vec};
+
+use crate::{
+    parse::{
+        ast::{ExpressionKind, Range},
+        diagnostic::{Diagnostic, DiagnosticCode, Language},
+        recover::parse_tolerant,
+    },
+    types::{
+        error::{TypeError, TypeErrorType},
+        infer::InferType,
+        schema::Schema,
+    },
+};
+
+// 语法错误和类型错误，语法有错的语句不再做类型检查
+// let 绑定的名字在后面的语句中可以使用
+pub fn diagnostics(text: &str, schema: &Schema, lang: Language) -> Vec<Diagnostic> {
+    let (body, mut diagnostics) = parse_tolerant(text, lang);
+    let mut scope = schema.clone();
+
+    for (_, statement) in body.body.iter() {
+        let expression = &statement.expression.1;
+        let has_error = expression
+            .walk()
+            .iter()
+            .any(|node| matches!(node, ExpressionKind::ErrorKind(_, _)));
+        if has_error {
+            continue;
+        }
+
+        let result = match expression {
+            ExpressionKind::LetBindingKind(_, binding) => binding.bind(&mut scope),
+            expression => expression.infer_type(&scope).map(|_| ()),
+        };
+        if let Err(error) = result {
+            let message = error
+                .message
+                .clone()
+                .unwrap_or_else(|| alloc::format!("{:?}", error.type_));
+            diagnostics.push(Diagnostic::new(
+                locate(expression, &error, &scope),
+                DiagnosticCode::TypeError,
+                lang,
+                &message,
+                Vec::<String>::new(),
+            ));
+        }
+    }
+
+    diagnostics
+}
+
+// 错误会一层层向外传递，找到最里面产生同一个错误的节点
+fn locate(node: &ExpressionKind, error: &TypeError, schema: &Schema) -> Range {
+    for child in node.children() {
+        if child.infer_type(schema).err().as_ref() == Some(error) {
+            return locate(child, error, schema);
+        }
+    }
+
+    match (node, &error.type_) {
+        (ExpressionKind::CallExpressionKind(_, call), TypeErrorType::FunctionNotFound) => {
+            call.callee.0.clone()
+        }
+        (
+            ExpressionKind::PropertyAccessExpressionKind(_, access),
+            TypeErrorType::PropertyNotFound,
+        ) => access.property.0.clone(),
+        _ => node.range().clone(),
+    }
+}
@@ -0,0 +1,125 @@
+use alloc::{format, string::String, vec::Vec};
+
+use super::definition::find_declaration;
+use crate::{
+    parse::{
+        ast::{ExpressionKind, Range},
+        beautify::Beautify,
+        diagnostic::Language,
+        recover::parse_tolerant,
+        type_ast::{FuncDefine, TypeDefine},
+    },
+    share::function::{find_function, FunctionInfo},
+    types::{
+        infer::{element_fields, InferType},
+        schema::Schema,
+    },
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hover {
+    pub range: Range,
+    pub contents: String, // Markdown
+}
+
+// 悬停提示：字段、属性、表达式显示类型，函数显示签名和文档
+pub fn hover(text: &str, offset: usize, schema: &Schema, lang: Language) -> Option<Hover> {
+    let (body, _) = parse_tolerant(text, lang);
+    let scope = body.scope(schema);
+    let schema = &scope;
+    let nodes = body.nodes_at(offset);
+    let node = *nodes.last()?;
+    let range = node.range().clone();
+
+    let contents = match node {
+        ExpressionKind::IdentifierKind(_, identifier) => {
+            let name = identifier.name.as_str();
+            let is_callee = match nodes.iter().rev().nth(1) {
+                Some(ExpressionKind::CallExpressionKind(_, call)) => call.callee.0 == range,
+                _ => false,
+            };
+
+            match (find_function(name), find_declaration(&body, name, offset)) {
+                (Some(function), _) if is_callee || !schema.has(name) => {
+                    function_contents(function, lang)
+                }
+                (_, Some(ExpressionKind::FuncDefineKind(_, func))) => code(&func_signature(func)),
+                (_, Some(ExpressionKind::TypeDefineKind(_, type_define))) => {
+                    code(&type_signature(type_define))
+                }
+                _ => match node.infer_type(schema) {
+                    Ok(type_) => code(&format!("{}: {}", name, type_)),
+                    // 旧的过滤写法 COUNT(subtask; status=2) 中的 status 是数组元素的属性
+                    Err(_) => {
+                        let fields = enclosing_record(&nodes, schema)?;
+                        let field = fields.get(name)?;
+                        code(&format!("{}: {}", name, field.type_))
+                    }
+                },
+            }
+        }
+        ExpressionKind::PropertyAccessExpressionKind(_, access) => {
+            let type_ = node.infer_type(schema).ok()?;
+            return Some(Hover {
+                range: access.property.0.clone(),
+                contents: code(&format!("{}: {}", access.property.1.name, type_)),
+            });
+        }
+        ExpressionKind::LetBindingKind(_, binding) if binding.ident.0.contains(offset) => {
+            let type_ = binding.value.1.infer_type(schema).ok()?;
+            return Some(Hover {
+                range: binding.ident.0.clone(),
+                contents: code(&format!("{}: {}", binding.ident.1.name, type_)),
+            });
+        }
+        ExpressionKind::TypeDefineKind(_, type_define) => code(&type_signature(type_define)),
+        ExpressionKind::FuncDefineKind(_, func) => code(&func_signature(func)),
+        ExpressionKind::ErrorKind(_, _) => return None,
+        _ => code(&format!("{}", node.infer_type(schema).ok()?)),
+    };
+
+    Some(Hover { range, contents })
+}
+
+fn code(text: &str) -> String {
+    format!("```formula\n{}\n```", text)
+}
+
+fn function_contents(function: &FunctionInfo, lang: Language) -> String {
+    let doc = match lang {
+        Language::En => function.doc,
+        Language::Zh => function.doc_zh,
+    };
+    format!("{}\n\n{}", code(&function.signature()), doc)
+}
+
+fn func_signature(func: &FuncDefine) -> String {
+    format!(
+        "func {}({}) -> {}",
+        func.ident.1.name,
+        func.arguments
+            .iter()
+            .map(|argument| argument.1.beautify(0))
+            .collect::<Vec<_>>()
+            .join(", "),
+        func.return_type.1.beautify(0)
+    )
+}
+
+fn type_signature(type_define: &TypeDefine) -> String {
+    format!(
+        "type {} = {}",
+        type_define.ident.1.name,
+        type_define.type_item.1.beautify(0)
+    )
+}
+
+// 最近一层函数调用的第一个参数对应的数组元素属性
+fn enclosing_record(nodes: &[&ExpressionKind], schema: &Schema) -> Option<Schema> {
+    nodes.iter().rev().find_map(|node| match node {
+        ExpressionKind::CallExpressionKind(_, call) => {
+            element_fields(&call.arguments.first()?.1, schema)
+        }
+        _ => None,
+    })
+}
@@ -0,0 +1,113 @@
+// 编辑器服务：补全、悬停提示、参数提示、跳转到定义、诊断
+// LSP 服务（src/lsp）和 wasm 接口共用这里的实现，除 monaco 模块外所有位置都是字节偏移
+pub mod completion;
+pub mod definition;
+pub mod diagnostics;
+pub mod hover;
+pub mod monaco;
+pub mod position;
+pub mod signature;
+pub mod tokens;
+
+use crate::{
+    parse::ast::{CallExpression, ExpressionKind, Range},
+    share::string::{open_comment, Quotes},
+};
+
+// 光标是否在字符串里面
+pub(crate) fn in_string(text: &str, offset: usize) -> bool {
+    let mut quotes = Quotes::default();
+    for c in text[..offset].chars() {
+        quotes.scan(c);
+    }
+    quotes.in_string()
+}
+
+// 光标是否在注释里面
+pub(crate) fn in_comment(text: &str, offset: usize) -> bool {
+    open_comment(&text[..offset]).is_some()
+}
+
+// 光标所在的函数调用，例如 `SUM(subtask.estimatePoint, |` 中的 SUM
+pub(crate) struct CallContext<'a> {
+    pub call: &'a CallExpression,
+    pub argument_index: usize,
+}
+
+impl CallContext<'_> {
+    pub fn callee(&self) -> Option<&str> {
+        match &*self.call.callee.1 {
+            ExpressionKind::IdentifierKind(_, identifier) => Some(identifier.name.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn first_argument(&self) -> Option<&ExpressionKind> {
+        self.call.arguments.first().map(|(_, argument)| &**argument)
+    }
+}
+
+// 从内到外返回光标在参数列表中的函数调用，nodes 是 nodes_at(offset) 的结果
+// 光标在函数名上或者右括号之后的调用不算
+pub(crate) fn call_contexts<'a>(
+    text: &'a str,
+    nodes: &'a [&'a ExpressionKind],
+    offset: usize,
+) -> impl Iterator<Item = CallContext<'a>> {
+    nodes.iter().rev().filter_map(move |node| match node {
+        ExpressionKind::CallExpressionKind(range, call)
+            if in_arguments(text, range, call, offset) =>
+        {
+            Some(CallContext {
+                call,
+                argument_index: argument_index(text, call, offset),
+            })
+        }
+        _ => None,
+    })
+}
+
+fn in_arguments(text: &str, range: &Range, call: &CallExpression, offset: usize) -> bool {
+    // 容错解析出来的没有右括号的调用，范围的结尾是最后一个参数的结尾或者空白
+    let closed = text[..range.1].ends_with(')')
+        && call
+            .arguments
+            .last()
+            .is_none_or(|(last, _)| last.1 < range.1);
+    offset > call.callee.0 .1 && (offset < range.1 || !closed)
+}
+
+fn argument_index(text: &str, call: &CallExpression, offset: usize) -> usize {
+    let before = call
+        .arguments
+        .iter()
+        .take_while(|(range, _)| range.1 < offset)
+        .count();
+    if matches!(call.arguments.get(before), Some((range, _)) if range.0 <= offset) {
+        return before;
+    }
+    // `f(1 |` 还在第一个参数上，`f(1, |` 是第二个参数
+    match before.checked_sub(1) {
+        Some(last) if !text[call.arguments[last].0 .1..offset].contains([',', ';']) => last,
+        _ => before,
+    }
+}
+
+// 光标前正在输入的名字，也就是补全时替换掉的范围，不在标识符或属性名上时为空
+pub(crate) fn word_range(node: Option<&ExpressionKind>, offset: usize) -> Range {
+    let word = match node {
+        Some(ExpressionKind::IdentifierKind(range, identifier)) => {
+            Some((range.0, identifier.name.len()))
+        }
+        Some(ExpressionKind::PropertyAccessExpressionKind(_, access))
+            if access.property.0.contains(offset) =>
+        {
+            Some((access.property.0 .0, access.property.1.name.len()))
+        }
+        _ => None,
+    };
+    match word {
+        Some((start, len)) if start <= offset && offset <= start + len => Range(start, offset),
+        _ => Range(offset, offset),
+    }
+}
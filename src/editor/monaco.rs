@@ -0,0 +1,178 @@
+use alloc::{format, vec::Vec};
+use serde_json::{json, Value as JsonValue};
+
+use super::{
+    completion::{complete, CompletionKind},
+    hover::hover,
+    position::{utf16_len, utf16_to_byte_offset, LineIndex},
+    signature::signature_help,
+    tokens::{tokens, TOKEN_KINDS},
+};
+use crate::{
+    parse::{ast::Range, diagnostic::Language},
+    types::schema::Schema,
+    vm::{disassembler::instruction, error::ExecuteError, trace::Trace, value::Value},
+};
+
+// 转换成 Monaco 可以直接使用的 JSON，offset 是 model.getOffsetAt() 返回的 UTF-16 偏移
+
+// IRange，行号和列号都从 1 开始
+pub fn to_range(index: &LineIndex, range: &Range) -> JsonValue {
+    let start = index.position(range.0);
+    let end = index.position(range.1);
+    json!({
+        "startLineNumber": start.line + 1,
+        "startColumn": start.character + 1,
+        "endLineNumber": end.line + 1,
+        "endColumn": end.character + 1,
+    })
+}
+
+// 执行结果，出错时 range 是出错的子表达式，可以用来设置 IMarkerData
+pub fn execute_result(text: &str, result: &Result<Value, ExecuteError>) -> JsonValue {
+    match result {
+        Ok(value) => json!({ "ok": true, "value": format!("{}", value), "error": null }),
+        Err(error) => json!({
+            "ok": false,
+            "value": null,
+            "error": {
+                "type": format!("{:?}", error.type_),
+                "message": error.message,
+                "range": error.range.as_ref().map(|range| to_range(&LineIndex::new(text), range)),
+            },
+        }),
+    }
+}
+
+// 执行过程，在 execute_result 的基础上增加读取的字段和每条指令执行后的值栈
+// 值都转换成和 value 相同的文本，source 是指令对应的源码
+pub fn trace_result(text: &str, trace: &Trace) -> JsonValue {
+    let index = LineIndex::new(text);
+    let mut result = execute_result(text, &trace.result);
+    result["inputs"] = trace
+        .inputs
+        .iter()
+        .map(|(name, value)| (name.clone(), json!(format!("{}", value))))
+        .collect::<serde_json::Map<_, _>>()
+        .into();
+    result["steps"] = trace
+        .steps
+        .iter()
+        .map(|step| {
+            json!({
+                "depth": step.depth,
+                "pc": step.pc,
+                "instruction": instruction(&trace.program, step.pc, &step.operator),
+                "range": step.range.as_ref().map(|range| to_range(&index, range)),
+                "source": step.range.as_ref().and_then(|range| text.get(range.0..range.1)),
+                "stack": step.stack.iter().map(|value| format!("{}", value)).collect::<Vec<_>>(),
+                "item": step.item.as_ref().map(|value| format!("{}", value)),
+                "store": step.store.as_ref().map(|(name, value)| json!({
+                    "name": name,
+                    "value": format!("{}", value),
+                })),
+                "error": step.error.as_ref().map(|error| json!({
+                    "type": format!("{:?}", error.type_),
+                    "message": error.message,
+                })),
+            })
+        })
+        .collect();
+    result
+}
+
+// monaco.languages.CompletionItemKind
+fn completion_kind(kind: CompletionKind) -> u8 {
+    match kind {
+        CompletionKind::Function => 1,
+        CompletionKind::Field => 3,
+        CompletionKind::Property => 9,
+        CompletionKind::Keyword => 17,
+    }
+}
+
+// CompletionList
+pub fn completion_list(text: &str, offset: usize, schema: &Schema, lang: Language) -> JsonValue {
+    let index = LineIndex::new(text);
+    let offset = utf16_to_byte_offset(text, offset);
+    let suggestions = complete(text, offset, schema, lang)
+        .into_iter()
+        .map(|item| {
+            json!({
+                "label": item.label,
+                "kind": completion_kind(item.kind),
+                "detail": item.detail,
+                "documentation": item.documentation,
+                "insertText": item.label,
+                "range": to_range(&index, &item.range),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({ "suggestions": suggestions })
+}
+
+// Hover，没有提示时返回 null
+pub fn hover_result(text: &str, offset: usize, schema: &Schema, lang: Language) -> JsonValue {
+    let index = LineIndex::new(text);
+    match hover(text, utf16_to_byte_offset(text, offset), schema, lang) {
+        Some(hover) => json!({
+            "contents": [{ "value": hover.contents }],
+            "range": to_range(&index, &hover.range),
+        }),
+        None => JsonValue::Null,
+    }
+}
+
+// SignatureHelp，JS 侧再包装成 { value, dispose }
+pub fn signature_help_result(text: &str, offset: usize, lang: Language) -> JsonValue {
+    match signature_help(text, utf16_to_byte_offset(text, offset), lang) {
+        Some(help) => json!({
+            "signatures": [{
+                "label": help.label,
+                "documentation": help.documentation,
+                "parameters": help.parameters.iter().map(|parameter| json!({
+                    "label": parameter.label,
+                    "documentation": parameter.documentation,
+                })).collect::<Vec<_>>(),
+            }],
+            "activeSignature": 0,
+            "activeParameter": help.active_parameter,
+        }),
+        None => JsonValue::Null,
+    }
+}
+
+// SemanticTokens: legend 用于 getLegend()，data 按 LSP 的相对编码，每个 token 5 个数字
+// 跨行的 token（多行字符串）只高亮第一行
+pub fn semantic_tokens(text: &str) -> JsonValue {
+    let index = LineIndex::new(text);
+    let mut data = Vec::new();
+    let (mut last_line, mut last_character) = (0, 0);
+
+    for token in tokens(text) {
+        let start = index.position(token.range.0);
+        let token_text = &text[token.range.0..token.range.1];
+        let length = utf16_len(token_text.split('\n').next().unwrap_or(""));
+        if length == 0 {
+            continue;
+        }
+
+        let delta_line = start.line - last_line;
+        let delta_character = match delta_line {
+            0 => start.character - last_character,
+            _ => start.character,
+        };
+        data.extend([delta_line, delta_character, length, token.kind.index(), 0]);
+        last_line = start.line;
+        last_character = start.character;
+    }
+
+    json!({
+        "legend": {
+            "tokenTypes": TOKEN_KINDS.iter().map(|kind| kind.as_str()).collect::<Vec<_>>(),
+            "tokenModifiers": [],
+        },
+        "data": data,
+    })
+}
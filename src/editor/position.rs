@@ -0,0 +1,88 @@
+use alloc::vec::Vec;
+
+// 公式内部使用字节偏移，编辑器（LSP、Monaco）使用行号 + UTF-16 列号，这里负责两者之间的转换
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize, // UTF-16 编码单元
+}
+
+pub struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let mut line_starts = alloc::vec![0];
+        for (i, c) in text.char_indices() {
+            if c == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
+        Self { text, line_starts }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        let start = self.line_starts[line];
+        Position {
+            line,
+            character: utf16_len(&self.text[start..floor_char_boundary(self.text, offset)]),
+        }
+    }
+
+    // 超出行尾的列号会被截断到行尾
+    pub fn offset(&self, position: Position) -> usize {
+        let start = match self.line_starts.get(position.line) {
+            Some(start) => *start,
+            None => return self.text.len(),
+        };
+        let end = self
+            .line_starts
+            .get(position.line + 1)
+            .map(|next| next - 1)
+            .unwrap_or(self.text.len());
+
+        let mut character = 0;
+        for (i, c) in self.text[start..end].char_indices() {
+            if character >= position.character {
+                return start + i;
+            }
+            character += c.len_utf16();
+        }
+        end
+    }
+}
+
+pub fn utf16_len(text: &str) -> usize {
+    text.chars().map(|c| c.len_utf16()).sum()
+}
+
+// UTF-16 偏移（Monaco 的 model offset）转换成字节偏移
+pub fn utf16_to_byte_offset(text: &str, offset: usize) -> usize {
+    let mut utf16 = 0;
+    for (i, c) in text.char_indices() {
+        if utf16 >= offset {
+            return i;
+        }
+        utf16 += c.len_utf16();
+    }
+    text.len()
+}
+
+pub fn byte_to_utf16_offset(text: &str, offset: usize) -> usize {
+    utf16_len(&text[..floor_char_boundary(text, offset)])
+}
+
+fn floor_char_boundary(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
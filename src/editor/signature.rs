@@ -0,0 +1,107 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use super::{call_contexts, definition::find_declaration, in_comment, in_string};
+use crate::{
+    parse::{
+        ast::ExpressionKind, beautify::Beautify, diagnostic::Language, recover::parse_tolerant,
+    },
+    share::function::find_function,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterInformation {
+    pub label: String,
+    pub documentation: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignatureHelp {
+    pub label: String,
+    pub documentation: Option<String>,
+    pub parameters: Vec<ParameterInformation>,
+    pub active_parameter: usize,
+}
+
+// 输入函数参数时，提示函数签名以及当前是第几个参数
+pub fn signature_help(text: &str, offset: usize, lang: Language) -> Option<SignatureHelp> {
+    let offset = offset.min(text.len());
+    if !text.is_char_boundary(offset) || in_string(text, offset) || in_comment(text, offset) {
+        return None;
+    }
+    let (body, _) = parse_tolerant(text, lang);
+    let nodes = body.nodes_at(offset);
+    let call = call_contexts(text, &nodes, offset).next()?;
+    let callee = call.callee()?;
+
+    if let Some(function) = find_function(callee) {
+        let parameters = function
+            .parameters
+            .iter()
+            .map(|parameter| ParameterInformation {
+                label: format!("{}: {}", parameter.name, parameter.type_),
+                documentation: Some(
+                    match lang {
+                        Language::En => parameter.doc,
+                        Language::Zh => parameter.doc_zh,
+                    }
+                    .to_string(),
+                ),
+            })
+            .collect::<Vec<_>>();
+        let active_parameter = match function.parameter_at(call.argument_index) {
+            Some(parameter) => function
+                .parameters
+                .iter()
+                .position(|p| p == parameter)
+                .unwrap_or(0),
+            None => call.argument_index,
+        };
+
+        return Some(SignatureHelp {
+            label: function.signature(),
+            documentation: Some(
+                match lang {
+                    Language::En => function.doc,
+                    Language::Zh => function.doc_zh,
+                }
+                .to_string(),
+            ),
+            parameters,
+            active_parameter,
+        });
+    }
+
+    // 公式中用 func 声明的函数
+    match find_declaration(&body, callee, offset)? {
+        ExpressionKind::FuncDefineKind(_, func) => {
+            let parameters = func
+                .arguments
+                .iter()
+                .map(|(_, argument)| ParameterInformation {
+                    label: argument.beautify(0),
+                    documentation: None,
+                })
+                .collect::<Vec<_>>();
+            Some(SignatureHelp {
+                label: format!(
+                    "{}({}) -> {}",
+                    func.ident.1.name,
+                    parameters
+                        .iter()
+                        .map(|parameter| parameter.label.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    func.return_type.1.beautify(0)
+                ),
+                documentation: None,
+                parameters,
+                active_parameter: call.argument_index,
+            })
+        }
+        _ => None,
+    }
+}
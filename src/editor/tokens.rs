@@ -0,0 +1,159 @@
+use alloc::vec::Vec;
+
+use crate::parse::{
+    ast::{ExpressionKind, Range, TemplatePart},
+    diagnostic::Language,
+    recover::parse_tolerant,
+    type_ast::TypeItemKind,
+};
+
+// 语义高亮的 token 类型，顺序就是 Monaco legend 中 tokenTypes 的顺序
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TokenKind {
+    Keyword,
+    Type,
+    Function,
+    Variable,
+    Property,
+    Number,
+    String,
+    Operator,
+}
+
+pub const TOKEN_KINDS: [TokenKind; 8] = [
+    TokenKind::Keyword,
+    TokenKind::Type,
+    TokenKind::Function,
+    TokenKind::Variable,
+    TokenKind::Property,
+    TokenKind::Number,
+    TokenKind::String,
+    TokenKind::Operator,
+];
+
+impl TokenKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenKind::Keyword => "keyword",
+            TokenKind::Type => "type",
+            TokenKind::Function => "function",
+            TokenKind::Variable => "variable",
+            TokenKind::Property => "property",
+            TokenKind::Number => "number",
+            TokenKind::String => "string",
+            TokenKind::Operator => "operator",
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        TOKEN_KINDS.iter().position(|kind| kind == self).unwrap()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub range: Range,
+    pub kind: TokenKind,
+}
+
+// 按位置排序的语义 token，解析失败的部分不产生 token
+pub fn tokens(text: &str) -> Vec<Token> {
+    let (body, _) = parse_tolerant(text, Language::En);
+    let mut tokens = Vec::new();
+
+    for (_, statement) in body.body.iter() {
+        for node in statement.walk() {
+            node_tokens(node, &mut tokens);
+        }
+    }
+
+    // 排序是稳定的，同一个位置保留先加入的 token
+    tokens.sort_by_key(|token| token.range.0);
+    tokens.dedup_by(|token, previous| token.range == previous.range);
+    tokens
+}
+
+fn push(tokens: &mut Vec<Token>, range: &Range, kind: TokenKind) {
+    if range.0 < range.1 {
+        tokens.push(Token {
+            range: range.clone(),
+            kind,
+        });
+    }
+}
+
+fn node_tokens(node: &ExpressionKind, tokens: &mut Vec<Token>) {
+    match node {
+        ExpressionKind::IdentifierKind(range, _) => push(tokens, range, TokenKind::Variable),
+        ExpressionKind::NumberLiteralKind(range, _) => push(tokens, range, TokenKind::Number),
+        ExpressionKind::StringLiteralKind(range, _) => push(tokens, range, TokenKind::String),
+        ExpressionKind::DateLiteralKind(range, _) => push(tokens, range, TokenKind::Number),
+        // 反引号和文本是字符串，${} 中的表达式由子节点产生 token
+        ExpressionKind::TemplateLiteralKind(range, template) => {
+            push(tokens, &Range(range.0, range.0 + 1), TokenKind::String);
+            for part in template.parts.iter() {
+                if let TemplatePart::Text(range, _) = part {
+                    push(tokens, range, TokenKind::String);
+                }
+            }
+            push(tokens, &Range(range.1 - 1, range.1), TokenKind::String);
+        }
+        ExpressionKind::BoolLiteralKind(range, _) | ExpressionKind::NullLiteralKind(range, _) => {
+            push(tokens, range, TokenKind::Keyword)
+        }
+        ExpressionKind::UnaryExpressionKind(_, unary) => {
+            push(tokens, &unary.operator.0, TokenKind::Operator)
+        }
+        ExpressionKind::BinaryExpressionKind(_, binary) => {
+            push(tokens, &binary.operator.0, TokenKind::Operator)
+        }
+        ExpressionKind::PropertyAccessExpressionKind(_, access) => {
+            push(tokens, &access.property.0, TokenKind::Property)
+        }
+        // 先于 callee 遍历到，callee 作为变量的 token 会在去重时去掉
+        ExpressionKind::CallExpressionKind(_, call) => {
+            if let ExpressionKind::IdentifierKind(range, _) = &*call.callee.1 {
+                push(tokens, range, TokenKind::Function);
+            }
+        }
+        ExpressionKind::TypeDefineKind(range, type_define) => {
+            push(tokens, &Range(range.0, range.0 + 4), TokenKind::Keyword);
+            push(tokens, &type_define.ident.0, TokenKind::Type);
+            type_item_tokens(&type_define.type_item, tokens);
+        }
+        ExpressionKind::LetBindingKind(range, binding) => {
+            push(tokens, &Range(range.0, range.0 + 3), TokenKind::Keyword);
+            push(tokens, &binding.ident.0, TokenKind::Variable);
+        }
+        ExpressionKind::FuncDefineKind(range, func_define) => {
+            push(tokens, &Range(range.0, range.0 + 4), TokenKind::Keyword);
+            push(tokens, &func_define.ident.0, TokenKind::Function);
+            for argument in func_define.arguments.iter() {
+                type_item_tokens(argument, tokens);
+            }
+            type_item_tokens(&func_define.return_type, tokens);
+        }
+        // 括号没有对应的 token，只标记其中的子表达式
+        ExpressionKind::IndexExpressionKind(_, _)
+        | ExpressionKind::SliceExpressionKind(_, _)
+        | ExpressionKind::ArrayLiteralKind(_, _)
+        | ExpressionKind::ErrorKind(_, _) => {}
+    }
+}
+
+fn type_item_tokens(item: &(Range, TypeItemKind), tokens: &mut Vec<Token>) {
+    match &item.1 {
+        TypeItemKind::NamedTypeKind(named_type) => {
+            push(tokens, &named_type.ident.0, TokenKind::Type);
+            for parameter in named_type.parameters.iter() {
+                type_item_tokens(parameter, tokens);
+            }
+        }
+        TypeItemKind::RecordTypeKind(record_type) => {
+            for (_, field) in record_type.fields.iter() {
+                push(tokens, &field.key.0, TokenKind::Property);
+                type_item_tokens(&field.value, tokens);
+            }
+        }
+    }
+}
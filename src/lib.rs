@@ -8,19 +8,22 @@ use alloc::string::{String, ToString};
 #[macro_use]
 extern crate pest_derive;
 
+pub mod editor;
 pub mod execute;
+pub mod migrate;
 pub mod parse;
 pub mod share;
 pub mod types;
 mod utils;
 pub mod vm;
 
-use alloc::vec::Vec;
+use alloc::{sync::Arc, vec::Vec};
 use parse::ast::to_ast;
-use parse::dependencies::get_dependencies;
 use parse::parse::Formula;
-use parse::to_operator::ToOperator;
-use serde_json::Value as JsonValue;
+use serde_json::{json, Value as JsonValue};
+use share::program::{CompileOptions, Program};
+use types::infer::InferType;
+use types::schema::Schema;
 use vm::context::RuntimeContext;
 use vm::runner::Runner;
 
@@ -41,31 +44,110 @@ pub fn ping() -> String {
     String::from("Pang")
 }
 
+// 返回带版本和校验和的字节码，传入 schema（格式和 check_formula 相同）时记录结果类型
+// optimize 默认为 true，调试时传 false 保留编译器直接生成的指令
 #[wasm_bindgen]
-pub fn compile(expr: String) -> Vec<u8> {
+pub fn compile(expr: String, schema: Option<String>, optimize: Option<bool>) -> Vec<u8> {
     let formula = Formula::parse(expr.as_str()).unwrap();
     let (_, ast) = to_ast(formula.paris);
-    let operators = ast.to_operator();
-    let encoded: Vec<u8> = bincode::serialize(&operators).unwrap();
-    encoded
+    let result_type = schema.and_then(|schema| ast.infer_type(&editor_schema(Some(schema))).ok());
+    let options = CompileOptions {
+        optimize: optimize.unwrap_or(true),
+    };
+    Program::compile(&ast, result_type, &options).to_bytes()
+}
+
+// 反汇编公式，每行是指令、执行后栈的深度和对应的源码，optimize 和 compile 相同
+#[wasm_bindgen]
+pub fn disassemble(expr: String, optimize: Option<bool>) -> String {
+    let formula = Formula::parse(expr.as_str()).unwrap();
+    let (_, ast) = to_ast(formula.paris);
+    let options = CompileOptions {
+        optimize: optimize.unwrap_or(true),
+    };
+    let program = Program::compile(&ast, None, &options);
+    vm::disassembler::disassemble(&program, Some(expr.as_str()))
+}
+
+// 反汇编 compile 保存的字节码，字节码中没有公式的文本，只显示源码位置
+#[wasm_bindgen(js_name = disassembleBytes)]
+pub fn disassemble_bytes(bytes: Vec<u8>) -> String {
+    match Program::from_bytes(&bytes) {
+        Ok(program) => vm::disassembler::disassemble(&program, None),
+        Err(error) => format!("; {:?} {}", error.type_, error.message.unwrap_or_default()),
+    }
 }
 
 #[wasm_bindgen]
 pub fn run(expr: String, data: String, now: i64, today: i64) -> String {
     let formula = Formula::parse(expr.as_str()).unwrap();
     let (_, ast) = to_ast(formula.paris);
-    let operators = ast.to_operator();
+    format!("{}", execute(&ast, &data, now, today).unwrap())
+}
+
+// 和 run 相同，返回 JSON：{ "ok": bool, "value": "...", "error": { "type", "message", "range" } }
+// range 是出错的子表达式在 Monaco 中的 IRange，编辑器可以直接标记，没有位置时为 null
+#[wasm_bindgen]
+pub fn evaluate(expr: String, data: String, now: i64, today: i64) -> String {
+    match Formula::parse(expr.as_str()) {
+        Ok(formula) => {
+            let (_, ast) = to_ast(formula.paris);
+            let result = execute(&ast, &data, now, today);
+            editor::monaco::execute_result(expr.as_str(), &result)
+        }
+        Err(e) => json!({
+            "ok": false,
+            "value": null,
+            "error": { "type": "SyntaxError", "message": e.to_string(), "range": null },
+        }),
+    }
+    .to_string()
+}
+
+// 记录公式在 Issue 上的执行过程，用于排查线上公式的结果，返回 JSON：
+// { "ok", "value", "error", "inputs": { 字段: 值 }, "steps": [{ "depth", "pc", "instruction", "range", "source", "stack", "item", "store", "error" }] }
+// 在前端单步调试时按顺序回放 steps，range 和 evaluate 相同是 Monaco 的 IRange
+#[wasm_bindgen]
+pub fn trace(expr: String, data: String, now: i64, today: i64) -> String {
+    match Formula::parse(expr.as_str()) {
+        Ok(formula) => {
+            let (_, ast) = to_ast(formula.paris);
+            let program = Arc::new(Program::compile(&ast, None, &CompileOptions::default()));
+            let mut ctx = issue_context(&program, &data, now, today);
+            let trace = vm::trace::Trace::record(&program, &mut ctx);
+            editor::monaco::trace_result(expr.as_str(), &trace)
+        }
+        Err(e) => json!({
+            "ok": false,
+            "value": null,
+            "error": { "type": "SyntaxError", "message": e.to_string(), "range": null },
+            "inputs": {},
+            "steps": [],
+        }),
+    }
+    .to_string()
+}
 
-    let dependencies = get_dependencies(&operators);
+fn execute(
+    ast: &parse::ast::FormulaBody,
+    data: &str,
+    now: i64,
+    today: i64,
+) -> Result<Value, vm::error::ExecuteError> {
+    let program = Arc::new(Program::compile(ast, None, &CompileOptions::default()));
+    let mut ctx = issue_context(&program, data, now, today);
+    Runner.run_program(&program, &mut ctx)
+}
 
-    let issue: JsonValue = serde_json::from_str(&data).unwrap();
+// 执行 program 前从 Issue 中读取依赖的字段
+fn issue_context(program: &Program, data: &str, now: i64, today: i64) -> RuntimeContext {
+    let issue: JsonValue = serde_json::from_str(data).unwrap();
 
-    let runner = Runner;
     let mut ctx = RuntimeContext::new();
     ctx.inject_functions();
     mock_time(&mut ctx, &issue, now, today);
 
-    for dependency in dependencies {
+    for dependency in program.dependencies.iter().cloned() {
         if ctx.has(&dependency) {
             continue;
         }
@@ -73,13 +155,103 @@ pub fn run(expr: String, data: String, now: i64, today: i64) -> String {
         // println!("dependency: {}", &dependency);
         ctx.set(dependency, value);
     }
+    ctx
+}
+
+// 保存公式前检查结果类型是否和字段类型匹配，返回 JSON:
+// { "ok": bool, "resultType": "Duration", "error": { "type", "message" }, "suggestions": [] }
+#[wasm_bindgen]
+pub fn check_formula(expr: String, target_type: String, schema: String) -> String {
+    let schema = match serde_json::from_str::<JsonValue>(&schema) {
+        Ok(json) => Schema::from_json(&json),
+        Err(e) => Err(types::error::TypeError::invalid_schema(e.to_string())),
+    };
+
+    let result = schema.and_then(|schema| {
+        types::check::check_formula(expr.as_str(), target_type.as_str(), &schema)
+    });
+
+    match result {
+        Ok(result_type) => json!({
+            "ok": true,
+            "resultType": result_type.to_string(),
+            "error": null,
+            "suggestions": [],
+        }),
+        Err(error) => json!({
+            "ok": false,
+            "resultType": match &error.type_ {
+                types::error::TypeErrorType::TargetMismatch(result_type, _) => Some(result_type.to_string()),
+                _ => None,
+            },
+            "error": {
+                "type": format!("{:?}", error.type_),
+                "message": error.message,
+            },
+            "suggestions": error.suggestions,
+        }),
+    }
+    .to_string()
+}
+
+// 编辑器服务，offset 是 Monaco model.getOffsetAt() 返回的 UTF-16 偏移，返回的 JSON 可以直接交给 Monaco
+// schema 格式和 check_formula 相同，language 为 "en" 或 "zh"，默认英文
+#[wasm_bindgen]
+pub fn complete(expr: String, offset: usize, schema: String, language: Option<String>) -> String {
+    editor::monaco::completion_list(
+        expr.as_str(),
+        offset,
+        &editor_schema(Some(schema)),
+        editor_language(language),
+    )
+    .to_string()
+}
 
-    let result = runner.run(operators, &mut ctx);
+#[wasm_bindgen]
+pub fn hover(
+    expr: String,
+    offset: usize,
+    schema: Option<String>,
+    language: Option<String>,
+) -> String {
+    editor::monaco::hover_result(
+        expr.as_str(),
+        offset,
+        &editor_schema(schema),
+        editor_language(language),
+    )
+    .to_string()
+}
+
+#[wasm_bindgen(js_name = signatureHelp)]
+pub fn signature_help(expr: String, offset: usize, language: Option<String>) -> String {
+    editor::monaco::signature_help_result(expr.as_str(), offset, editor_language(language))
+        .to_string()
+}
+
+#[wasm_bindgen]
+pub fn tokens(expr: String) -> String {
+    editor::monaco::semantic_tokens(expr.as_str()).to_string()
+}
 
-    format!("{}", result.unwrap())
+// 编辑器中 schema 不合法时按空 schema 处理，不影响函数的补全和提示
+fn editor_schema(schema: Option<String>) -> Schema {
+    let mut schema = schema
+        .and_then(|schema| serde_json::from_str::<JsonValue>(&schema).ok())
+        .and_then(|json| Schema::from_json(&json).ok())
+        .unwrap_or_default();
+    schema.inject_builtins();
+    schema
+}
+
+fn editor_language(language: Option<String>) -> parse::diagnostic::Language {
+    match language {
+        Some(language) => parse::diagnostic::Language::from_name(&language),
+        None => parse::diagnostic::Language::En,
+    }
 }
 
-fn mock_time(ctx: &mut RuntimeContext, issue: &JsonValue, now: i64, today: i64) {
+pub(crate) fn mock_time(ctx: &mut RuntimeContext, issue: &JsonValue, now: i64, today: i64) {
     ctx.set(
         "GET_TODAY".to_string(),
         Value::Number((today as i64).into()),
@@ -0,0 +1,324 @@
+// 公式编辑器的 Language Server，通过 stdin / stdout 使用 JSON-RPC 通信
+// 启动参数 initializationOptions: { "schema": { ... }, "language": "zh" }
+use formula_rs_wasm::{
+    editor::{
+        completion::{complete, CompletionKind},
+        definition::definition,
+        diagnostics::diagnostics,
+        hover::hover,
+        position::{LineIndex, Position},
+        signature::signature_help,
+    },
+    parse::{ast::Range, diagnostic::Language},
+    types::schema::Schema,
+};
+use hashbrown::HashMap;
+use serde_json::{json, Value as JsonValue};
+use std::io::{self, BufRead, Write};
+
+struct Server {
+    documents: HashMap<String, String>,
+    schema: Schema,
+    language: Language,
+    shutdown: bool,
+}
+
+fn main() {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+
+    let mut server = Server {
+        documents: HashMap::new(),
+        schema: builtin_schema(Schema::new()),
+        language: Language::En,
+        shutdown: false,
+    };
+
+    while let Some(message) = read_message(&mut input) {
+        // 格式不正确的消息回复 Parse error，继续读取后面的消息
+        let message = match message {
+            Ok(message) => message,
+            Err(error) => {
+                let response = json!({
+                    "jsonrpc": "2.0",
+                    "id": JsonValue::Null,
+                    "error": { "code": -32700, "message": format!("Parse error: {}", error) },
+                });
+                write_message(&mut output, &response);
+                continue;
+            }
+        };
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+
+        if method == "exit" {
+            std::process::exit(if server.shutdown { 0 } else { 1 });
+        }
+
+        let notifications = server.notify(method, params);
+        for notification in notifications {
+            write_message(&mut output, &notification);
+        }
+
+        // 没有 id 的是通知，不需要回复
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => continue,
+        };
+        let response = match server.request(method, params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        write_message(&mut output, &response);
+    }
+}
+
+impl Server {
+    fn request(&mut self, method: &str, params: &JsonValue) -> Result<JsonValue, (i64, String)> {
+        match method {
+            "initialize" => {
+                self.configure(&params["initializationOptions"]);
+                Ok(json!({
+                    "capabilities": {
+                        "textDocumentSync": 1,
+                        "completionProvider": { "triggerCharacters": ["."] },
+                        "hoverProvider": true,
+                        "signatureHelpProvider": { "triggerCharacters": ["(", ",", ";"] },
+                        "definitionProvider": true,
+                    },
+                    "serverInfo": { "name": "formula-lsp", "version": env!("CARGO_PKG_VERSION") },
+                }))
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(JsonValue::Null)
+            }
+            "textDocument/completion" => {
+                let (text, offset) = self.locate(params)?;
+                let index = LineIndex::new(text);
+                let items = complete(text, offset, &self.schema, self.language)
+                    .into_iter()
+                    .map(|item| {
+                        json!({
+                            "label": item.label,
+                            "kind": completion_kind(item.kind),
+                            "detail": item.detail,
+                            "documentation": item.documentation,
+                            "textEdit": {
+                                "range": to_lsp_range(&index, &item.range),
+                                "newText": item.label,
+                            },
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                Ok(json!({ "isIncomplete": false, "items": items }))
+            }
+            "textDocument/hover" => {
+                let (text, offset) = self.locate(params)?;
+                let index = LineIndex::new(text);
+                Ok(match hover(text, offset, &self.schema, self.language) {
+                    Some(hover) => json!({
+                        "contents": { "kind": "markdown", "value": hover.contents },
+                        "range": to_lsp_range(&index, &hover.range),
+                    }),
+                    None => JsonValue::Null,
+                })
+            }
+            "textDocument/signatureHelp" => {
+                let (text, offset) = self.locate(params)?;
+                Ok(match signature_help(text, offset, self.language) {
+                    Some(help) => json!({
+                        "signatures": [{
+                            "label": help.label,
+                            "documentation": help.documentation,
+                            "parameters": help.parameters.iter().map(|parameter| json!({
+                                "label": parameter.label,
+                                "documentation": parameter.documentation,
+                            })).collect::<Vec<_>>(),
+                        }],
+                        "activeSignature": 0,
+                        "activeParameter": help.active_parameter,
+                    }),
+                    None => JsonValue::Null,
+                })
+            }
+            "textDocument/definition" => {
+                let (text, offset) = self.locate(params)?;
+                let index = LineIndex::new(text);
+                Ok(match definition(text, offset) {
+                    Some(range) => json!({
+                        "uri": params["textDocument"]["uri"],
+                        "range": to_lsp_range(&index, &range),
+                    }),
+                    None => JsonValue::Null,
+                })
+            }
+            _ => Err((-32601, format!("method not found: {}", method))),
+        }
+    }
+
+    // 处理通知，返回需要推送给客户端的消息
+    fn notify(&mut self, method: &str, params: &JsonValue) -> Vec<JsonValue> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.documents.insert(uri.clone(), text.to_string());
+                vec![self.publish_diagnostics(&uri)]
+            }
+            "textDocument/didChange" => {
+                // 只支持全量同步，取最后一次修改的完整内容
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.documents.insert(uri.clone(), text.to_string());
+                }
+                vec![self.publish_diagnostics(&uri)]
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": [] },
+                })]
+            }
+            "workspace/didChangeConfiguration" => {
+                self.configure(&params["settings"]);
+                let uris = self.documents.keys().cloned().collect::<Vec<_>>();
+                uris.iter()
+                    .map(|uri| self.publish_diagnostics(uri))
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    fn configure(&mut self, options: &JsonValue) {
+        if let Some(language) = options["language"].as_str() {
+            self.language = Language::from_name(language);
+        }
+        if !options["schema"].is_null() {
+            match Schema::from_json(&options["schema"]) {
+                Ok(schema) => self.schema = builtin_schema(schema),
+                Err(error) => eprintln!("formula-lsp: invalid schema: {:?}", error),
+            }
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: &str) -> JsonValue {
+        let text = self
+            .documents
+            .get(uri)
+            .map(|text| text.as_str())
+            .unwrap_or("");
+        let index = LineIndex::new(text);
+        let items = diagnostics(text, &self.schema, self.language)
+            .into_iter()
+            .map(|diagnostic| {
+                let message = match &diagnostic.hint {
+                    Some(hint) => format!("{}\n{}", diagnostic.message, hint),
+                    None => diagnostic.message.clone(),
+                };
+                json!({
+                    "range": to_lsp_range(&index, &diagnostic.range),
+                    "severity": 1,
+                    "code": diagnostic.code.as_str(),
+                    "source": "formula",
+                    "message": message,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": items },
+        })
+    }
+
+    // 请求中的文档内容和光标位置（字节偏移）
+    fn locate(&self, params: &JsonValue) -> Result<(&str, usize), (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let text = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| (-32602, format!("unknown document: {}", uri)))?;
+        let position = Position {
+            line: params["position"]["line"].as_u64().unwrap_or(0) as usize,
+            character: params["position"]["character"].as_u64().unwrap_or(0) as usize,
+        };
+        Ok((text, LineIndex::new(text).offset(position)))
+    }
+}
+
+fn builtin_schema(mut schema: Schema) -> Schema {
+    schema.inject_builtins();
+    schema
+}
+
+fn completion_kind(kind: CompletionKind) -> u8 {
+    match kind {
+        CompletionKind::Function => 3,
+        CompletionKind::Field => 5,
+        CompletionKind::Property => 10,
+        CompletionKind::Keyword => 14,
+    }
+}
+
+fn to_lsp_range(index: &LineIndex, range: &Range) -> JsonValue {
+    let start = index.position(range.0);
+    let end = index.position(range.1);
+    json!({
+        "start": { "line": start.line, "character": start.character },
+        "end": { "line": end.line, "character": end.character },
+    })
+}
+
+// Content-Length: 123\r\n\r\n{...}
+// 输入结束时返回 None，消息头或者内容不正确时返回 Err
+fn read_message(input: &mut impl BufRead) -> Option<Result<JsonValue, String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(error) => return Some(Err(error.to_string())),
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = match length {
+        Some(length) => length,
+        None => return Some(Err("missing or invalid Content-Length".to_string())),
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).ok()?;
+    Some(serde_json::from_slice(&body).map_err(|error| error.to_string()))
+}
+
+fn write_message(output: &mut impl Write, message: &JsonValue) {
+    let body = message.to_string();
+    let _ = write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = output.flush();
+}
@@ -0,0 +1,337 @@
+pub mod verify;
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::parse::{ast::*, format::Format, parse::Formula};
+
+// 把旧的 SUM(subtask.estimatePoint;status=2) 写法改写成
+// sum(where(subtask, $.status == 2), $.estimatePoint)
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum MigrateErrorType {
+    ParseError,
+    UnsupportedArgument, // 过滤条件和无法改写的参数混用
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MigrateError {
+    pub type_: MigrateErrorType,
+    pub message: Option<String>,
+}
+
+impl MigrateError {
+    pub fn new(type_: MigrateErrorType) -> Self {
+        Self {
+            type_,
+            message: None,
+        }
+    }
+
+    pub fn with_message(self, msg: String) -> Self {
+        Self {
+            type_: self.type_,
+            message: Some(msg),
+        }
+    }
+
+    pub fn parse_error(msg: String) -> Self {
+        Self::new(MigrateErrorType::ParseError).with_message(msg)
+    }
+
+    pub fn unsupported_argument(callee: &str, argument: String) -> Self {
+        Self::new(MigrateErrorType::UnsupportedArgument).with_message(format!(
+            "{}: unsupported argument `{}`, expected a field path followed by filters like status=2",
+            callee, argument
+        ))
+    }
+}
+
+// 返回改写后的公式，不含旧写法的公式只会被重新格式化
+pub fn migrate(expr: &str) -> Result<String, MigrateError> {
+    let formula = Formula::parse(expr).map_err(|e| MigrateError::parse_error(e.to_string()))?;
+    let (_, ast) = to_ast(formula.paris);
+
+    let mut body = Vec::new();
+    for (range, statement) in ast.body {
+        let ExpressionAstItem(expression_range, expression) = statement.expression;
+        body.push((
+            range,
+            ExpressionStatement {
+                expression: ExpressionAstItem(expression_range, migrate_expression(expression)?),
+            },
+        ));
+    }
+
+    Ok(FormulaBody { body }.format())
+}
+
+fn migrate_expression(expr: ExpressionKind) -> Result<ExpressionKind, MigrateError> {
+    Ok(match expr {
+        ExpressionKind::CallExpressionKind(range, call) => {
+            if call.is_legacy_function() {
+                migrate_legacy_call(call)?
+            } else {
+                ExpressionKind::CallExpressionKind(
+                    range,
+                    CallExpression {
+                        callee: migrate_item(call.callee)?,
+                        arguments: call
+                            .arguments
+                            .into_iter()
+                            .map(migrate_item)
+                            .collect::<Result<Vec<_>, _>>()?,
+                    },
+                )
+            }
+        }
+        ExpressionKind::UnaryExpressionKind(range, unary) => ExpressionKind::UnaryExpressionKind(
+            range,
+            UnaryExpression {
+                prefix: unary.prefix,
+                operator: unary.operator,
+                argument: migrate_item(unary.argument)?,
+            },
+        ),
+        ExpressionKind::BinaryExpressionKind(range, binary) => {
+            ExpressionKind::BinaryExpressionKind(
+                range,
+                BinaryExpression {
+                    left: migrate_item(binary.left)?,
+                    operator: binary.operator,
+                    right: migrate_item(binary.right)?,
+                },
+            )
+        }
+        ExpressionKind::PropertyAccessExpressionKind(range, access) => {
+            ExpressionKind::PropertyAccessExpressionKind(
+                range,
+                PropertyAccessExpression {
+                    object: migrate_item(access.object)?,
+                    property: access.property,
+                    optional: access.optional,
+                },
+            )
+        }
+        ExpressionKind::IndexExpressionKind(range, index) => ExpressionKind::IndexExpressionKind(
+            range,
+            IndexExpression {
+                object: migrate_item(index.object)?,
+                index: migrate_item(index.index)?,
+            },
+        ),
+        ExpressionKind::SliceExpressionKind(range, slice) => ExpressionKind::SliceExpressionKind(
+            range,
+            SliceExpression {
+                object: migrate_item(slice.object)?,
+                start: slice.start.map(migrate_item).transpose()?,
+                end: slice.end.map(migrate_item).transpose()?,
+            },
+        ),
+        ExpressionKind::ArrayLiteralKind(range, array) => ExpressionKind::ArrayLiteralKind(
+            range,
+            ArrayLiteral {
+                elements: array
+                    .elements
+                    .into_iter()
+                    .map(migrate_item)
+                    .collect::<Result<Vec<_>, _>>()?,
+            },
+        ),
+        ExpressionKind::TemplateLiteralKind(range, template) => {
+            ExpressionKind::TemplateLiteralKind(
+                range,
+                TemplateLiteral {
+                    parts: template
+                        .parts
+                        .into_iter()
+                        .map(|part| match part {
+                            TemplatePart::Expression(range, expr) => {
+                                let (range, expr) = migrate_item((range, expr))?;
+                                Ok(TemplatePart::Expression(range, expr))
+                            }
+                            text => Ok(text),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?,
+                },
+            )
+        }
+        ExpressionKind::LetBindingKind(range, binding) => ExpressionKind::LetBindingKind(
+            range,
+            LetBinding {
+                ident: binding.ident,
+                value: migrate_item(binding.value)?,
+            },
+        ),
+        expr => expr,
+    })
+}
+
+fn migrate_item(
+    (range, expr): (Range, Box<ExpressionKind>),
+) -> Result<(Range, Box<ExpressionKind>), MigrateError> {
+    Ok((range, Box::new(migrate_expression(*expr)?)))
+}
+
+// 旧写法中过滤条件作用在路径的第一个字段上，例如 subtask.estimatePoint;status=2
+// 先过滤 subtask，再取 estimatePoint，多个过滤条件依次生效
+fn migrate_legacy_call(call: CallExpression) -> Result<ExpressionKind, MigrateError> {
+    let name = match &*call.callee.1 {
+        ExpressionKind::IdentifierKind(_, identifier) => identifier.name.to_lowercase(),
+        _ => unreachable!(),
+    };
+
+    // 第一个参数是字段时后面只能是过滤条件，例如 COUNT(subtask;2=status) 不能忽略过滤条件改写成 count
+    let unsupported = match call.arguments.split_first() {
+        Some(((_, first), rest)) if field_path(first).is_some() => rest
+            .iter()
+            .find(|(_, argument)| !argument.is_legacy_filter()),
+        _ => None,
+    };
+    if let Some((_, argument)) = unsupported {
+        return Err(MigrateError::unsupported_argument(
+            &name.to_uppercase(),
+            argument.format(),
+        ));
+    }
+
+    let (filters, values): (Vec<_>, Vec<_>) = call
+        .arguments
+        .into_iter()
+        .map(|(_, argument)| *argument)
+        .partition(ExpressionKind::is_legacy_filter);
+
+    let path = match values.as_slice() {
+        [value] => field_path(value),
+        _ => None,
+    };
+
+    let (root, rest) = match path {
+        Some(path) => path,
+        None if filters.is_empty() => {
+            let arguments = values
+                .into_iter()
+                .map(migrate_expression)
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(call_expression(&name, arguments));
+        }
+        None => {
+            let argument = values
+                .iter()
+                .map(|value| value.format())
+                .collect::<Vec<String>>()
+                .join(", ");
+            return Err(MigrateError::unsupported_argument(
+                &name.to_uppercase(),
+                argument,
+            ));
+        }
+    };
+
+    let mut collection = identifier(&root);
+    for filter in filters {
+        collection = call_expression("where", vec![collection, filter_lambda(filter)]);
+    }
+
+    let mut arguments = vec![collection];
+    if name == "sum" && !rest.is_empty() {
+        let selector = rest.iter().fold(identifier("$"), |object, property| {
+            property_access(object, property)
+        });
+        arguments.push(selector);
+    }
+
+    Ok(call_expression(&name, arguments))
+}
+
+// subtask.estimatePoint -> ("subtask", ["estimatePoint"])
+fn field_path(expr: &ExpressionKind) -> Option<(String, Vec<String>)> {
+    match expr {
+        ExpressionKind::IdentifierKind(_, identifier) => {
+            Some((identifier.name.clone(), Vec::new()))
+        }
+        ExpressionKind::PropertyAccessExpressionKind(_, access) => {
+            let (root, mut rest) = field_path(&access.object.1)?;
+            rest.push(access.property.1.name.clone());
+            Some((root, rest))
+        }
+        _ => None,
+    }
+}
+
+// status=2 -> $.status == 2，右边的标识符在旧写法中按字符串比较，例如 relationship=CHILD
+fn filter_lambda(filter: ExpressionKind) -> ExpressionKind {
+    let binary = match filter {
+        ExpressionKind::BinaryExpressionKind(_, binary) => binary,
+        _ => unreachable!(),
+    };
+    let field = match *binary.left.1 {
+        ExpressionKind::IdentifierKind(_, identifier) => identifier.name,
+        _ => unreachable!(),
+    };
+    let value = match *binary.right.1 {
+        ExpressionKind::IdentifierKind(_, identifier) => ExpressionKind::StringLiteralKind(
+            Range(0, 0),
+            StringLiteral {
+                raw: format!("'{}'", identifier.name),
+                value: identifier.name,
+            },
+        ),
+        value => value,
+    };
+
+    ExpressionKind::BinaryExpressionKind(
+        Range(0, 0),
+        BinaryExpression {
+            left: (
+                Range(0, 0),
+                Box::new(property_access(identifier("$"), &field)),
+            ),
+            operator: (Range(0, 0), binary.operator.1),
+            right: (Range(0, 0), Box::new(value)),
+        },
+    )
+}
+
+// 改写生成的节点没有对应的源码位置，统一使用 Range(0, 0)
+fn identifier(name: &str) -> ExpressionKind {
+    ExpressionKind::IdentifierKind(
+        Range(0, 0),
+        Identifier {
+            name: name.to_string(),
+        },
+    )
+}
+
+fn property_access(object: ExpressionKind, property: &str) -> ExpressionKind {
+    ExpressionKind::PropertyAccessExpressionKind(
+        Range(0, 0),
+        PropertyAccessExpression {
+            object: (Range(0, 0), Box::new(object)),
+            property: (
+                Range(0, 0),
+                Identifier {
+                    name: property.to_string(),
+                },
+            ),
+            optional: false,
+        },
+    )
+}
+
+fn call_expression(name: &str, arguments: Vec<ExpressionKind>) -> ExpressionKind {
+    ExpressionKind::CallExpressionKind(
+        Range(0, 0),
+        CallExpression {
+            callee: (Range(0, 0), Box::new(identifier(name))),
+            arguments: arguments
+                .into_iter()
+                .map(|argument| (Range(0, 0), Box::new(argument)))
+                .collect(),
+        },
+    )
+}
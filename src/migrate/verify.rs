@@ -0,0 +1,86 @@
+use alloc::{format, string::String, vec::Vec};
+use serde_json::Value as JsonValue;
+
+use crate::{
+    mock_time,
+    parse::{ast::to_ast, dependencies::get_dependencies, parse::Formula, to_operator::ToOperator},
+    vm::{context::RuntimeContext, error::ExecuteError, runner::Runner, value::Value},
+};
+
+// 改写前后的公式在同一个 issue 上结果不一致
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    pub issue: usize, // issue 在样本中的下标
+    pub legacy: Result<Value, ExecuteError>,
+    pub migrated: Result<Value, ExecuteError>,
+}
+
+// 在样本 issue 上分别执行改写前后的公式，返回所有结果不一致的 issue
+// 两边都报错时视为一致：例如字段为 null 时旧写法报 Dot 错误，where 报参数错误，但都无法得到结果
+pub fn verify(
+    legacy: &str,
+    migrated: &str,
+    issues: &[JsonValue],
+    now: i64,
+    today: i64,
+) -> Vec<Mismatch> {
+    issues
+        .iter()
+        .enumerate()
+        .filter_map(|(index, issue)| {
+            let legacy = evaluate(legacy, issue, now, today);
+            let migrated = evaluate(migrated, issue, now, today);
+            match (&legacy, &migrated) {
+                (Ok(lhs), Ok(rhs)) if lhs == rhs => None,
+                (Err(_), Err(_)) => None,
+                _ => Some(Mismatch {
+                    issue: index,
+                    legacy,
+                    migrated,
+                }),
+            }
+        })
+        .collect()
+}
+
+pub fn evaluate(
+    expr: &str,
+    issue: &JsonValue,
+    now: i64,
+    today: i64,
+) -> Result<Value, ExecuteError> {
+    let formula =
+        Formula::parse(expr).map_err(|e| ExecuteError::unknown().with_message(format!("{}", e)))?;
+    let (_, ast) = to_ast(formula.paris);
+    let operators = ast.to_operator();
+    let dependencies = get_dependencies(&operators);
+
+    let mut ctx = RuntimeContext::new();
+    ctx.inject_functions();
+    mock_time(&mut ctx, issue, now, today);
+
+    for dependency in dependencies {
+        if ctx.has(&dependency) {
+            continue;
+        }
+        let value = Value::from_json(&issue[&dependency]);
+        ctx.set(dependency, value);
+    }
+
+    Runner.run(operators, &mut ctx)
+}
+
+impl Mismatch {
+    pub fn describe(&self) -> String {
+        let describe = |result: &Result<Value, ExecuteError>| match result {
+            Ok(value) => format!("{}", value),
+            Err(error) => format!("{:?}", error.type_),
+        };
+        format!(
+            "issue #{}: legacy {} / migrated {}",
+            self.issue,
+            describe(&self.legacy),
+            describe(&self.migrated)
+        )
+    }
+}
@@ -1,22 +1,23 @@
-use core::str::FromStr;
-
 use super::{
     parse::Rule,
-    type_ast::{type_def_to_ast, TypeDefine},
+    type_ast::{func_def_to_ast, type_def_to_ast, FuncDefine, TypeDefine},
 };
+use crate::share::{date::parse_date, decimal::parse_decimal, string::unescape};
 use alloc::{
     boxed::Box,
     string::{String, ToString},
     vec::Vec,
 };
-use num::{FromPrimitive, Rational64};
+use num::Rational64;
 use pest::{
     iterators::{Pair, Pairs},
     pratt_parser::PrattParser,
 };
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct Range(usize, usize);
+// 字节偏移，保存在 Program 的 SourceMap 中
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Range(pub usize, pub usize);
 
 impl From<Pair<'_, Rule>> for Range {
     fn from(pair: Pair<Rule>) -> Self {
@@ -36,12 +37,23 @@ pub enum ExpressionKind {
     BinaryExpressionKind(Range, BinaryExpression), // 二元表达式
     CallExpressionKind(Range, CallExpression),   // 函数调用表达式
     PropertyAccessExpressionKind(Range, PropertyAccessExpression), // 属性访问表达式，即 Dot 运算符
+    IndexExpressionKind(Range, IndexExpression), // 下标访问表达式，例如 subtask[0]
+    SliceExpressionKind(Range, SliceExpression), // 切片表达式，例如 subtask[1:3]
 
     StringLiteralKind(Range, StringLiteral), // 字符串字面量
     NumberLiteralKind(Range, NumberLiteral), // 数字字面量
+    BoolLiteralKind(Range, BoolLiteral),     // 布尔字面量 true、false
+    NullLiteralKind(Range, NullLiteral),     // 空值字面量 null
+    DateLiteralKind(Range, DateLiteral),     // 日期字面量，例如 #2024-01-31#
     IdentifierKind(Range, Identifier),       // 标识符
+    ArrayLiteralKind(Range, ArrayLiteral),   // 数组字面量
+    TemplateLiteralKind(Range, TemplateLiteral), // 模板字符串，例如 `完成 ${count(subtask)} 个`
 
     TypeDefineKind(Range, TypeDefine), // 类型定义
+    FuncDefineKind(Range, FuncDefine), // 函数声明
+    LetBindingKind(Range, LetBinding), // let 绑定
+
+    ErrorKind(Range, ErrorExpression), // 容错解析时无法解析的部分
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -82,6 +94,42 @@ pub struct CallExpression {
 pub struct PropertyAccessExpression {
     pub object: (Range, Box<ExpressionKind>),
     pub property: (Range, Identifier),
+    pub optional: bool, // ?. 访问，对象为 Null 时整个访问链的结果为 Null
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexExpression {
+    pub object: (Range, Box<ExpressionKind>),
+    pub index: (Range, Box<ExpressionKind>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SliceExpression {
+    pub object: (Range, Box<ExpressionKind>),
+    pub start: Option<(Range, Box<ExpressionKind>)>, // 省略时从第一个元素开始
+    pub end: Option<(Range, Box<ExpressionKind>)>,   // 省略时到最后一个元素结束
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArrayLiteral {
+    pub elements: Vec<(Range, Box<ExpressionKind>)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LetBinding {
+    pub ident: (Range, Identifier),
+    pub value: (Range, Box<ExpressionKind>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemplateLiteral {
+    pub parts: Vec<TemplatePart>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TemplatePart {
+    Text(Range, StringLiteral),             // raw 是源码中的文本，不含反引号
+    Expression(Range, Box<ExpressionKind>), // ${} 中的表达式
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -96,11 +144,30 @@ pub struct NumberLiteral {
     pub raw: String,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoolLiteral {
+    pub value: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NullLiteral;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DateLiteral {
+    pub value: u64, // 毫秒时间戳，按 UTC 计算
+    pub raw: String,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Identifier {
     pub name: String,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorExpression {
+    pub raw: String,
+}
+
 lazy_static::lazy_static! {
     static ref TYPE_PRATT_PARSER: PrattParser<Rule> = {
         use pest::pratt_parser::{Assoc::*, Op};
@@ -117,14 +184,31 @@ lazy_static::lazy_static! {
                 Op::infix(compare_gt, Left)
             )
 
+            // 空值合并，优先级低于四则运算，a ?? 0 > 3 等价于 (a ?? 0) > 3
+            .op(Op::infix(coalesce, Right))
+
+            // 位运算，和 Python 一致：| < xor < & < 移位 < 四则运算
+            .op(Op::infix(bitOr, Left))
+            .op(Op::infix(bitXor, Left))
+            .op(Op::infix(bitAnd, Left))
+            .op(Op::infix(rightShift, Left) | Op::infix(leftShift, Left))
+
             // 四则运算
             .op(Op::infix(add, Left) | Op::infix(subtract, Left))
-            .op(Op::infix(multiply, Left) | Op::infix(divide, Left))
+            .op(Op::infix(multiply, Left) | Op::infix(divide, Left) | Op::infix(intDivide, Left))
             .op(Op::infix(modulus, Left))
             .op(Op::infix(power, Right))
 
+            // 和 Excel 一致，-a ^ 2 等价于 (-a) ^ 2，和数字字面量 -2 ^ 2 的结果相同
+            .op(Op::prefix(neg) | Op::prefix(pos))
+
             .op(Op::postfix(Rule::fac))
-            .op(Op::postfix(Rule::dot))
+            .op(
+                Op::postfix(Rule::dot)
+                    | Op::postfix(Rule::optional_dot)
+                    | Op::postfix(Rule::index)
+                    | Op::postfix(Rule::slice)
+            )
             .op(Op::postfix(EOI))
     };
 }
@@ -150,15 +234,15 @@ pub fn variable_to_ast(pair: Pair<Rule>) -> ExpressionAstItem {
                 }
             } else if pairs[0].as_rule() == Rule::dot {
                 let range = Range(
-                    first.as_span().start(),
-                    pairs[pairs.len() - 1].as_span().end(),
+                    pairs[pairs.len() - 1].as_span().start(),
+                    first.as_span().end(),
                 );
                 let object = dot_to_property_access(&pairs[1..pairs.len()]);
 
                 let property_pair = first.into_inner().next().unwrap();
                 let property = match property_pair.as_rule() {
                     Rule::identifier => {
-                        let range = Range::from(pairs[pairs.len() - 1].clone());
+                        let range = Range::from(property_pair.clone());
                         let identifier = Identifier {
                             name: property_pair.as_str().to_string(),
                         };
@@ -173,6 +257,7 @@ pub fn variable_to_ast(pair: Pair<Rule>) -> ExpressionAstItem {
                         PropertyAccessExpression {
                             object: (object.0, Box::new(object.1)),
                             property,
+                            optional: false,
                         },
                     ),
                 );
@@ -219,10 +304,8 @@ pub fn literal_to_ast(pair: Pair<Rule>) -> ExpressionAstItem {
         Rule::num => {
             let raw = first.as_str().to_string();
 
-            let value = match raw.contains(".") {
-                true => Rational64::from_f64(raw.parse().unwrap()).unwrap(),
-                false => Rational64::from_str(&raw).unwrap(),
-            };
+            // 按十进制精确转换，1.2 是 6/5，Formula::parse 已经检查过范围
+            let value = parse_decimal(&raw).unwrap();
 
             ExpressionAstItem(
                 range.clone(),
@@ -236,7 +319,36 @@ pub fn literal_to_ast(pair: Pair<Rule>) -> ExpressionAstItem {
                 ExpressionKind::StringLiteralKind(
                     range,
                     StringLiteral {
-                        value: raw[1..raw.len() - 1].to_string(),
+                        // Formula::parse 已经检查过转义
+                        value: unescape(&raw[1..raw.len() - 1]).unwrap(),
+                        raw: raw.to_string(),
+                    },
+                ),
+            )
+        }
+        Rule::boolean => ExpressionAstItem(
+            range.clone(),
+            ExpressionKind::BoolLiteralKind(
+                range,
+                BoolLiteral {
+                    value: first.as_str() == "true",
+                },
+            ),
+        ),
+        Rule::null => ExpressionAstItem(
+            range.clone(),
+            ExpressionKind::NullLiteralKind(range, NullLiteral),
+        ),
+        Rule::date => {
+            let raw = first.as_str();
+            // Formula::parse 已经检查过日期是否存在
+            let value = parse_date(&raw[1..raw.len() - 1]).unwrap();
+            ExpressionAstItem(
+                range.clone(),
+                ExpressionKind::DateLiteralKind(
+                    range,
+                    DateLiteral {
+                        value,
                         raw: raw.to_string(),
                     },
                 ),
@@ -264,6 +376,8 @@ pub fn variable_or_expression(pair: Pair<Rule>) -> ExpressionAstItem {
             )
         }
         Rule::function_call => function_call_to_ast(pair),
+        Rule::array => array_to_ast(pair),
+        Rule::template => template_to_ast(pair),
         _ => unreachable!("variable_or_expression: {:?}", pair),
     }
 }
@@ -306,11 +420,86 @@ fn function_call_to_ast(pair: Pair<Rule>) -> ExpressionAstItem {
     )
 }
 
+fn array_to_ast(pair: Pair<Rule>) -> ExpressionAstItem {
+    let range = Range::from(pair.clone());
+    let elements = pair
+        .into_inner()
+        .flat_map(|element| element.into_inner())
+        .map(variable_or_literal_or_expression)
+        .map(|ExpressionAstItem(range, expression)| (range, Box::new(expression)))
+        .collect::<Vec<_>>();
+
+    ExpressionAstItem(
+        range.clone(),
+        ExpressionKind::ArrayLiteralKind(range, ArrayLiteral { elements }),
+    )
+}
+
+fn let_def_to_ast(pair: Pair<Rule>) -> ExpressionAstItem {
+    let range = Range::from(pair.clone());
+    let mut inner = pair.into_inner().skip(1);
+
+    let ident = inner.next().unwrap();
+    let ExpressionAstItem(value_range, value) =
+        variable_or_literal_or_expression(inner.next().unwrap());
+
+    ExpressionAstItem(
+        range.clone(),
+        ExpressionKind::LetBindingKind(
+            range,
+            LetBinding {
+                ident: (
+                    ident.clone().into(),
+                    Identifier {
+                        name: ident.as_str().to_string(),
+                    },
+                ),
+                value: (value_range, Box::new(value)),
+            },
+        ),
+    )
+}
+
+fn template_to_ast(pair: Pair<Rule>) -> ExpressionAstItem {
+    let range = Range::from(pair.clone());
+    let parts = pair
+        .into_inner()
+        .map(|part| match part.as_rule() {
+            Rule::template_text => TemplatePart::Text(
+                part.clone().into(),
+                StringLiteral {
+                    // Formula::parse 已经检查过转义
+                    value: unescape(part.as_str()).unwrap(),
+                    raw: part.as_str().to_string(),
+                },
+            ),
+            _ => {
+                let (range, expression) = inner_expression(part.into_inner().next().unwrap());
+                TemplatePart::Expression(range, expression)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    ExpressionAstItem(
+        range.clone(),
+        ExpressionKind::TemplateLiteralKind(range, TemplateLiteral { parts }),
+    )
+}
+
+// slice_start、slice_end、index、${} 中只有一个表达式
+fn inner_expression(pair: Pair<Rule>) -> (Range, Box<ExpressionKind>) {
+    let ExpressionAstItem(range, expression) =
+        variable_or_literal_or_expression(pair.into_inner().next().unwrap());
+    (range, Box::new(expression))
+}
+
 pub fn expression_to_ast(paris: Pairs<Rule>) -> ExpressionAstItem {
     TYPE_PRATT_PARSER
         .map_primary(|pair| match pair.as_rule() {
             Rule::literal => literal_to_ast(pair),
             Rule::function_call => function_call_to_ast(pair),
+            Rule::array => array_to_ast(pair),
+            Rule::template => template_to_ast(pair),
             Rule::variable => variable_to_ast(pair),
             Rule::identifier => ExpressionAstItem(
                 pair.clone().into(),
@@ -331,6 +520,8 @@ pub fn expression_to_ast(paris: Pairs<Rule>) -> ExpressionAstItem {
                 expression_to_ast(inner)
             }
             Rule::type_def => type_def_to_ast(pair),
+            Rule::func_def => func_def_to_ast(pair),
+            Rule::let_def => let_def_to_ast(pair),
 
             rule => unreachable!(
                 "Expr::parse expected atom, found {:?}, value {:?}",
@@ -372,6 +563,20 @@ pub fn expression_to_ast(paris: Pairs<Rule>) -> ExpressionAstItem {
                 ),
             }
         })
+        .map_prefix(|op, rhs| {
+            let range = Range(op.as_span().start(), rhs.0 .1);
+            ExpressionAstItem(
+                range.clone(),
+                ExpressionKind::UnaryExpressionKind(
+                    range,
+                    UnaryExpression {
+                        prefix: true,
+                        operator: (op.clone().into(), op.as_str().to_string()),
+                        argument: (rhs.0, Box::new(rhs.1)),
+                    },
+                ),
+            )
+        })
         .map_postfix(|lhs, op| match op.as_rule() {
             Rule::EOI => lhs,
             Rule::fac => {
@@ -388,7 +593,8 @@ pub fn expression_to_ast(paris: Pairs<Rule>) -> ExpressionAstItem {
                     ),
                 )
             }
-            Rule::dot => {
+            Rule::dot | Rule::optional_dot => {
+                let optional = op.as_rule() == Rule::optional_dot;
                 let inner = op.into_inner();
                 let ExpressionAstItem(range, rhs) = expression_to_ast(inner);
                 let property = match rhs {
@@ -408,6 +614,44 @@ pub fn expression_to_ast(paris: Pairs<Rule>) -> ExpressionAstItem {
                                     name: property.name,
                                 },
                             ),
+                            optional,
+                        },
+                    ),
+                )
+            }
+            Rule::index => {
+                let range = Range(lhs.0 .0, op.as_span().end());
+                let index = inner_expression(op.into_inner().next().unwrap());
+                ExpressionAstItem(
+                    range.clone(),
+                    ExpressionKind::IndexExpressionKind(
+                        range,
+                        IndexExpression {
+                            object: (lhs.0, Box::new(lhs.1)),
+                            index,
+                        },
+                    ),
+                )
+            }
+            Rule::slice => {
+                let range = Range(lhs.0 .0, op.as_span().end());
+                let mut start = None;
+                let mut end = None;
+                for bound in op.into_inner() {
+                    match bound.as_rule() {
+                        Rule::slice_start => start = Some(inner_expression(bound)),
+                        Rule::slice_end => end = Some(inner_expression(bound)),
+                        _ => unreachable!(),
+                    }
+                }
+                ExpressionAstItem(
+                    range.clone(),
+                    ExpressionKind::SliceExpressionKind(
+                        range,
+                        SliceExpression {
+                            object: (lhs.0, Box::new(lhs.1)),
+                            start,
+                            end,
                         },
                     ),
                 )
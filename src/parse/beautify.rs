@@ -1,4 +1,5 @@
 use alloc::{
+    boxed::Box,
     format,
     string::{String, ToString},
     vec::Vec,
@@ -6,7 +7,7 @@ use alloc::{
 
 use super::{
     ast::*,
-    type_ast::{NamedType, RecordType, TypeDefine, TypeItemKind},
+    type_ast::{FuncDefine, NamedType, RecordType, TypeDefine, TypeItemKind},
 };
 
 pub trait Beautify {
@@ -103,7 +104,11 @@ impl Beautify for PropertyAccessExpression {
         indent(
             level,
             format!(
-                "PropertyAccessExpression\n{}object\n{}\n{}property\n{}",
+                "{}\n{}object\n{}\n{}property\n{}",
+                match self.optional {
+                    true => "OptionalPropertyAccessExpression",
+                    false => "PropertyAccessExpression",
+                },
                 indent(level + 1, "".to_string()),
                 self.object.1.beautify(level + 2),
                 indent(level + 1, "".to_string()),
@@ -112,6 +117,81 @@ impl Beautify for PropertyAccessExpression {
         )
     }
 }
+impl Beautify for IndexExpression {
+    fn beautify(&self, level: usize) -> String {
+        indent(
+            level,
+            format!(
+                "IndexExpression\n{}object\n{}\n{}index\n{}",
+                indent(level + 1, "".to_string()),
+                self.object.1.beautify(level + 2),
+                indent(level + 1, "".to_string()),
+                self.index.1.beautify(level + 2)
+            ),
+        )
+    }
+}
+impl Beautify for SliceExpression {
+    fn beautify(&self, level: usize) -> String {
+        let bound = |bound: &Option<(Range, Box<ExpressionKind>)>| match bound {
+            Some((_, expr)) => expr.beautify(level + 2),
+            None => indent(level + 2, "(EMPTY)".to_string()),
+        };
+        indent(
+            level,
+            format!(
+                "SliceExpression\n{}object\n{}\n{}start\n{}\n{}end\n{}",
+                indent(level + 1, "".to_string()),
+                self.object.1.beautify(level + 2),
+                indent(level + 1, "".to_string()),
+                bound(&self.start),
+                indent(level + 1, "".to_string()),
+                bound(&self.end)
+            ),
+        )
+    }
+}
+impl Beautify for ArrayLiteral {
+    fn beautify(&self, level: usize) -> String {
+        indent(
+            level,
+            format!(
+                "ArrayLiteral\n{}",
+                match self.elements.len() {
+                    0 => indent(level + 1, "(EMPTY)".to_string()),
+                    _ => self
+                        .elements
+                        .iter()
+                        .map(|expr| expr.1.beautify(level + 1))
+                        .collect::<Vec<String>>()
+                        .join("\n"),
+                }
+            ),
+        )
+    }
+}
+impl Beautify for TemplateLiteral {
+    fn beautify(&self, level: usize) -> String {
+        indent(
+            level,
+            format!(
+                "TemplateLiteral\n{}",
+                match self.parts.len() {
+                    0 => indent(level + 1, "(EMPTY)".to_string()),
+                    _ => self
+                        .parts
+                        .iter()
+                        .map(|part| match part {
+                            TemplatePart::Text(_, text) => text.beautify(level + 1),
+                            TemplatePart::Expression(_, expr) => expr.beautify(level + 1),
+                        })
+                        .collect::<Vec<String>>()
+                        .join("\n"),
+                }
+            ),
+        )
+    }
+}
 impl Beautify for StringLiteral {
     fn beautify(&self, level: usize) -> String {
         indent(level, format!("StringLiteral ('{}')", self.value))
@@ -121,21 +201,38 @@ impl Beautify for StringLiteral {
 impl Beautify for NumberLiteral {
     fn beautify(&self, level: usize) -> String {
         indent(level, format!("NumberLiteral ({})", self.value))
+    }
+}
+impl Beautify for BoolLiteral {
+    fn beautify(&self, level: usize) -> String {
+        indent(level, format!("BoolLiteral ({})", self.value))
+    }
+}
+
+impl Beautify for NullLiteral {
+    fn beautify(&self, level: usize) -> String {
+        indent(level, "NullLiteral".to_string())
+    }
+}
 
-        // 现在 1.2 会输出 6 / 5，而不是 1.2
-        // if self.value.is_integer() {
-        //     indent(level, format!("NumberLiteral ({})", self.value))
-        // } else {
-        //     indent(level, format!("NumberLiteral ({})", *self.value.numer() as f64 / *self.value.denom() as f64))
-        // }
+impl Beautify for DateLiteral {
+    fn beautify(&self, level: usize) -> String {
+        indent(level, format!("DateLiteral ({})", self.value))
     }
 }
+
 impl Beautify for Identifier {
     fn beautify(&self, level: usize) -> String {
         indent(level, format!("Identifier {}", self.name))
     }
 }
 
+impl Beautify for ErrorExpression {
+    fn beautify(&self, level: usize) -> String {
+        indent(level, format!("ErrorExpression ({})", self.raw))
+    }
+}
+
 impl Beautify for NamedType {
     fn beautify(&self, _: usize) -> String {
         format!(
@@ -181,6 +278,19 @@ impl Beautify for TypeItemKind {
     }
 }
 
+impl Beautify for LetBinding {
+    fn beautify(&self, level: usize) -> String {
+        indent(
+            level,
+            format!(
+                "LetBinding {}\n{}",
+                self.ident.1.name,
+                self.value.1.beautify(level + 1)
+            ),
+        )
+    }
+}
+
 impl Beautify for TypeDefine {
     fn beautify(&self, level: usize) -> String {
         indent(
@@ -194,6 +304,24 @@ impl Beautify for TypeDefine {
     }
 }
 
+impl Beautify for FuncDefine {
+    fn beautify(&self, level: usize) -> String {
+        indent(
+            level,
+            format!(
+                "FuncDefine {}({}) -> {}",
+                self.ident.1.name,
+                self.arguments
+                    .iter()
+                    .map(|argument| argument.1.beautify(0))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                self.return_type.1.beautify(0)
+            ),
+        )
+    }
+}
+
 impl Beautify for ExpressionKind {
     fn beautify(&self, level: usize) -> String {
         match self {
@@ -209,10 +337,26 @@ impl Beautify for ExpressionKind {
             ExpressionKind::PropertyAccessExpressionKind(_, property_access_expression) => {
                 property_access_expression.beautify(level)
             }
+            ExpressionKind::IndexExpressionKind(_, index_expression) => {
+                index_expression.beautify(level)
+            }
+            ExpressionKind::SliceExpressionKind(_, slice_expression) => {
+                slice_expression.beautify(level)
+            }
+            ExpressionKind::ArrayLiteralKind(_, array_literal) => array_literal.beautify(level),
+            ExpressionKind::TemplateLiteralKind(_, template_literal) => {
+                template_literal.beautify(level)
+            }
             ExpressionKind::StringLiteralKind(_, string_literal) => string_literal.beautify(level),
             ExpressionKind::NumberLiteralKind(_, number_literal) => number_literal.beautify(level),
+            ExpressionKind::BoolLiteralKind(_, bool_literal) => bool_literal.beautify(level),
+            ExpressionKind::NullLiteralKind(_, null_literal) => null_literal.beautify(level),
+            ExpressionKind::DateLiteralKind(_, date_literal) => date_literal.beautify(level),
             ExpressionKind::IdentifierKind(_, identifier) => identifier.beautify(level),
             ExpressionKind::TypeDefineKind(_, type_define) => type_define.beautify(level),
+            ExpressionKind::FuncDefineKind(_, func_define) => func_define.beautify(level),
+            ExpressionKind::LetBindingKind(_, let_binding) => let_binding.beautify(level),
+            ExpressionKind::ErrorKind(_, error) => error.beautify(level),
         }
     }
 }
@@ -1,20 +1,48 @@
-use alloc::{
-    string::{String, ToString},
-    vec::Vec,
-};
+use alloc::{string::String, vec::Vec};
 
-use crate::share::operator::OperatorCode;
+use crate::share::{function::find_function, operator::OperatorCode};
 
+// 公式执行前需要从 Issue 中读取的字段，包括 lambda 中引用的字段，重复的只保留一个
+// let 绑定的名字不是字段，在绑定之前使用也不会读取同名的字段
 pub fn get_dependencies(codes: &Vec<OperatorCode>) -> Vec<String> {
-    let function_name = ["SUM".to_string(), "COUNT".to_string()];
+    let locals = let_names(codes);
+    let mut dependencies = Vec::new();
+    collect_dependencies(codes, &locals, &mut dependencies);
+    dependencies
+}
+
+// 公式中所有 let 绑定的名字
+pub fn let_names(codes: &[OperatorCode]) -> Vec<String> {
+    let mut names = Vec::new();
+    for code in codes {
+        match code {
+            OperatorCode::StoreLocal(name) if !names.contains(name) => names.push(name.clone()),
+            OperatorCode::PushLambda(lambda) => {
+                for name in let_names(lambda) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    names
+}
 
-    codes
-        .iter()
-        .map(|code| match code {
-            OperatorCode::LoadIdentifier(name) => Some(name),
-            _ => None,
-        })
-        .filter(|name| name.is_some() && !&function_name.contains(name.unwrap()))
-        .map(|func| func.unwrap().clone())
-        .collect::<Vec<_>>()
+fn collect_dependencies(codes: &[OperatorCode], locals: &[String], dependencies: &mut Vec<String>) {
+    for code in codes {
+        match code {
+            OperatorCode::LoadIdentifier(name)
+                if name != "$"
+                    && find_function(name).is_none()
+                    && !locals.contains(name)
+                    && !dependencies.contains(name) =>
+            {
+                dependencies.push(name.clone())
+            }
+            OperatorCode::PushLambda(lambda) => collect_dependencies(lambda, locals, dependencies),
+            _ => {}
+        }
+    }
 }
@@ -0,0 +1,587 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use pest::error::{Error, ErrorVariant, InputLocation};
+
+use super::{
+    ast::Range,
+    parse::{Formula, Rule},
+};
+use crate::share::{
+    decimal::{parse_decimal, DecimalError},
+    string::{blank_comments, open_comment, Quotes},
+};
+
+// 诊断信息的语言，产品和文档都是中英双语的
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    En,
+    Zh,
+}
+
+impl Language {
+    pub fn from_name(name: &str) -> Language {
+        match name.to_ascii_lowercase().as_str() {
+            "zh" | "zh-cn" | "zh_cn" | "cn" => Language::Zh,
+            _ => Language::En,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiagnosticCode {
+    EmptyFormula,
+    UnclosedString,
+    UnknownOperator,
+    MissingClosingParenthesis,
+    UnexpectedClosingParenthesis,
+    UnexpectedEnd,
+    UnexpectedToken,
+    MissingArgument,
+    InvalidDate,
+    InvalidEscape,
+    UnclosedComment,
+    TypeError,
+    NumberOutOfRange,
+}
+
+impl DiagnosticCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiagnosticCode::EmptyFormula => "empty-formula",
+            DiagnosticCode::UnclosedString => "unclosed-string",
+            DiagnosticCode::UnknownOperator => "unknown-operator",
+            DiagnosticCode::MissingClosingParenthesis => "missing-closing-parenthesis",
+            DiagnosticCode::UnexpectedClosingParenthesis => "unexpected-closing-parenthesis",
+            DiagnosticCode::UnexpectedEnd => "unexpected-end",
+            DiagnosticCode::UnexpectedToken => "unexpected-token",
+            DiagnosticCode::MissingArgument => "missing-argument",
+            DiagnosticCode::InvalidDate => "invalid-date",
+            DiagnosticCode::InvalidEscape => "invalid-escape",
+            DiagnosticCode::UnclosedComment => "unclosed-comment",
+            DiagnosticCode::TypeError => "type-error",
+            DiagnosticCode::NumberOutOfRange => "number-out-of-range",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub range: Range,
+    pub code: DiagnosticCode,
+    pub message: String,
+    pub expected: Vec<String>,
+    pub hint: Option<String>,
+}
+
+// 解析公式，失败时返回诊断信息
+pub fn diagnose(input: &str, lang: Language) -> Option<Diagnostic> {
+    match Formula::parse(input) {
+        Ok(_) => None,
+        Err(error) => Some(Diagnostic::from_pest_error(input, &error, lang)),
+    }
+}
+
+const KNOWN_OPERATORS: [&str; 20] = [
+    "+", "-", "*", "/", "^", "%", "!", "==", "=", "!=", "<>", ">=", "<=", "<", ">", ">>", "<<",
+    "??", "&", "|",
+];
+
+fn is_operator_char(c: char) -> bool {
+    "+-*/^%!=<>&|?:~@#".contains(c)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$' || c == '.'
+}
+
+impl Diagnostic {
+    pub fn from_pest_error(input: &str, error: &Error<Rule>, lang: Language) -> Diagnostic {
+        let pos = match error.location {
+            InputLocation::Pos(pos) => pos,
+            InputLocation::Span((start, _)) => start,
+        };
+        let positives = match &error.variant {
+            ErrorVariant::ParsingError { positives, .. } => positives.clone(),
+            // 解析之后的日期、转义、数字范围检查返回自定义错误
+            ErrorVariant::CustomError { .. } => {
+                if let Some((start, end)) = invalid_escape(input) {
+                    return Diagnostic::new(
+                        Range(start, end),
+                        DiagnosticCode::InvalidEscape,
+                        lang,
+                        &input[start..end],
+                        Vec::new(),
+                    );
+                }
+                let (start, end) = match error.location {
+                    InputLocation::Pos(pos) => (pos, pos),
+                    InputLocation::Span(span) => span,
+                };
+                let code = match parse_decimal(&input[start..end]) {
+                    Err(DecimalError::OutOfRange) => DiagnosticCode::NumberOutOfRange,
+                    _ => DiagnosticCode::InvalidDate,
+                };
+                return Diagnostic::new(
+                    Range(start, end),
+                    code,
+                    lang,
+                    &input[start..end],
+                    Vec::new(),
+                );
+            }
+        };
+        let expected = expected_names(&positives, lang);
+
+        if let Some(start) = unclosed_comment(input) {
+            return Diagnostic::new(
+                Range(start, input.len()),
+                DiagnosticCode::UnclosedComment,
+                lang,
+                "",
+                expected,
+            );
+        }
+
+        // 下面的启发式规则按字符扫描，注释中的引号、括号不参与
+        let blanked = blank_comments(input);
+        let input = blanked.as_str();
+
+        if input.trim().is_empty() {
+            return Diagnostic::new(
+                Range(0, input.len()),
+                DiagnosticCode::EmptyFormula,
+                lang,
+                "",
+                expected,
+            );
+        }
+
+        if let Some(start) = unclosed_string(input) {
+            return Diagnostic::new(
+                Range(start, input.len()),
+                DiagnosticCode::UnclosedString,
+                lang,
+                &input[start..start + 1],
+                expected,
+            );
+        }
+
+        if let Some((start, end)) = invalid_escape(input) {
+            return Diagnostic::new(
+                Range(start, end),
+                DiagnosticCode::InvalidEscape,
+                lang,
+                &input[start..end],
+                expected,
+            );
+        }
+
+        if let Some((start, end)) = operator_around(input, pos) {
+            let operator = &input[start..end];
+            if !KNOWN_OPERATORS.contains(&operator) {
+                let mut diagnostic = Diagnostic::new(
+                    Range(start, end),
+                    DiagnosticCode::UnknownOperator,
+                    lang,
+                    operator,
+                    expected,
+                );
+                if let Some(suggestion) = suggest_operator(operator) {
+                    diagnostic.hint = Some(match lang {
+                        Language::En => format!("did you mean `{}`?", suggestion),
+                        Language::Zh => format!("是不是想输入 `{}`？", suggestion),
+                    });
+                }
+                return diagnostic;
+            }
+        }
+
+        let rest = &input[pos..];
+        if rest.starts_with(')') && parenthesis_depth(&input[..pos]) == 0 {
+            return Diagnostic::new(
+                Range(pos, pos + 1),
+                DiagnosticCode::UnexpectedClosingParenthesis,
+                lang,
+                ")",
+                expected,
+            );
+        }
+
+        if rest.trim().is_empty() {
+            let expects_operator = positives.iter().all(|rule| rule_name(*rule, lang).1);
+            if expects_operator {
+                if let Some(open) = unclosed_parenthesis(input) {
+                    return Diagnostic::new(
+                        Range(open, input.len()),
+                        DiagnosticCode::MissingClosingParenthesis,
+                        lang,
+                        "(",
+                        expected,
+                    );
+                }
+            }
+
+            let end = input.trim_end().len();
+            return Diagnostic::new(
+                Range(end, end),
+                DiagnosticCode::UnexpectedEnd,
+                lang,
+                "",
+                expected,
+            );
+        }
+
+        let token = token_at(rest);
+        Diagnostic::new(
+            Range(pos, pos + token.len()),
+            DiagnosticCode::UnexpectedToken,
+            lang,
+            token,
+            expected,
+        )
+    }
+
+    pub(crate) fn new(
+        range: Range,
+        code: DiagnosticCode,
+        lang: Language,
+        token: &str,
+        expected: Vec<String>,
+    ) -> Diagnostic {
+        let (message, hint) = describe(code, lang, token, &expected);
+        Diagnostic {
+            range,
+            code,
+            message,
+            expected,
+            hint,
+        }
+    }
+}
+
+fn describe(
+    code: DiagnosticCode,
+    lang: Language,
+    token: &str,
+    expected: &[String],
+) -> (String, Option<String>) {
+    let expected = match lang {
+        Language::En => expected.join(", "),
+        Language::Zh => expected.join("、"),
+    };
+    match (code, lang) {
+        (DiagnosticCode::EmptyFormula, Language::En) => (
+            "formula is empty".to_string(),
+            Some("write an expression, e.g. `SUM(subtask.estimatePoint)`".to_string()),
+        ),
+        (DiagnosticCode::EmptyFormula, Language::Zh) => (
+            "公式为空".to_string(),
+            Some("请输入表达式，例如 `SUM(subtask.estimatePoint)`".to_string()),
+        ),
+        (DiagnosticCode::UnclosedString, Language::En) => (
+            "unclosed string".to_string(),
+            Some(format!("add {} to close the string", token)),
+        ),
+        (DiagnosticCode::UnclosedString, Language::Zh) => (
+            "字符串没有结束".to_string(),
+            Some(format!("在字符串末尾加上 {}", token)),
+        ),
+        (DiagnosticCode::UnknownOperator, Language::En) => {
+            (format!("unknown operator `{}`", token), None)
+        }
+        (DiagnosticCode::UnknownOperator, Language::Zh) => {
+            (format!("未知的运算符 `{}`", token), None)
+        }
+        (DiagnosticCode::MissingClosingParenthesis, Language::En) => (
+            "missing closing parenthesis".to_string(),
+            Some("add `)` to close the parenthesis opened here".to_string()),
+        ),
+        (DiagnosticCode::MissingClosingParenthesis, Language::Zh) => (
+            "缺少右括号".to_string(),
+            Some("在末尾加上 `)` 来闭合这里的左括号".to_string()),
+        ),
+        (DiagnosticCode::UnexpectedClosingParenthesis, Language::En) => (
+            "unexpected closing parenthesis".to_string(),
+            Some("remove the `)` or add a matching `(`".to_string()),
+        ),
+        (DiagnosticCode::UnexpectedClosingParenthesis, Language::Zh) => (
+            "多余的右括号".to_string(),
+            Some("删除这个 `)`，或者补上对应的 `(`".to_string()),
+        ),
+        (DiagnosticCode::UnexpectedEnd, Language::En) => (
+            format!("unexpected end of formula, expected {}", expected),
+            None,
+        ),
+        (DiagnosticCode::UnexpectedEnd, Language::Zh) => {
+            (format!("公式不完整，缺少{}", expected), None)
+        }
+        (DiagnosticCode::MissingArgument, Language::En) => (
+            "missing argument".to_string(),
+            Some("remove the extra separator or fill in the argument".to_string()),
+        ),
+        (DiagnosticCode::MissingArgument, Language::Zh) => (
+            "缺少参数".to_string(),
+            Some("删除多余的分隔符，或者补上参数".to_string()),
+        ),
+        (DiagnosticCode::InvalidDate, Language::En) => (
+            format!("invalid date `{}`", token),
+            Some("write dates as `#2024-01-31#` or `#2024-01-31T08:30:00#`".to_string()),
+        ),
+        (DiagnosticCode::InvalidDate, Language::Zh) => (
+            format!("日期 `{}` 不存在或者格式不正确", token),
+            Some("日期的格式是 `#2024-01-31#` 或者 `#2024-01-31T08:30:00#`".to_string()),
+        ),
+        (DiagnosticCode::InvalidEscape, Language::En) => (
+            format!("invalid escape `{}`", token),
+            Some(
+                "supported escapes are \\' \\\" \\\\ \\n \\r \\t \\` \\$ and \\u{4E2D}".to_string(),
+            ),
+        ),
+        (DiagnosticCode::InvalidEscape, Language::Zh) => (
+            format!("无法识别的转义 `{}`", token),
+            Some("支持的转义有 \\' \\\" \\\\ \\n \\r \\t \\` \\$ 和 \\u{4E2D}".to_string()),
+        ),
+        (DiagnosticCode::NumberOutOfRange, Language::En) => (
+            format!("number `{}` is out of range", token),
+            Some(
+                "numbers are stored as fractions of 64-bit integers, use fewer digits".to_string(),
+            ),
+        ),
+        (DiagnosticCode::NumberOutOfRange, Language::Zh) => (
+            format!("数字 `{}` 超出范围", token),
+            Some("数字保存为分子和分母都是 64 位整数的分数，请减少位数".to_string()),
+        ),
+        (DiagnosticCode::UnclosedComment, Language::En) => (
+            "unclosed comment".to_string(),
+            Some("add `*/` to close the comment".to_string()),
+        ),
+        (DiagnosticCode::UnclosedComment, Language::Zh) => (
+            "注释没有结束".to_string(),
+            Some("在注释末尾加上 `*/`".to_string()),
+        ),
+        // 类型错误的详细信息由类型检查器给出
+        (DiagnosticCode::TypeError, Language::En) => (format!("type error: {}", token), None),
+        (DiagnosticCode::TypeError, Language::Zh) => (format!("类型错误：{}", token), None),
+        (DiagnosticCode::UnexpectedToken, Language::En) => (
+            format!("unexpected `{}`, expected {}", token, expected),
+            None,
+        ),
+        (DiagnosticCode::UnexpectedToken, Language::Zh) => (
+            format!("这里不应该出现 `{}`，应该是{}", token, expected),
+            None,
+        ),
+    }
+}
+
+// 把语法规则转换成用户能看懂的名字，第二个值表示这个规则是否是运算符
+fn rule_name(rule: Rule, lang: Language) -> (&'static str, bool) {
+    let zh = lang == Language::Zh;
+    match rule {
+        Rule::add => ("`+`", true),
+        Rule::subtract => ("`-`", true),
+        Rule::multiply => ("`*`", true),
+        Rule::divide => ("`/`", true),
+        Rule::power => ("`^`", true),
+        Rule::modulus => ("`%`", true),
+        Rule::rightShift => ("`>>`", true),
+        Rule::leftShift => ("`<<`", true),
+        Rule::intDivide => ("`div`", true),
+        Rule::bitAnd => ("`&`", true),
+        Rule::bitOr => ("`|`", true),
+        Rule::bitXor => ("`xor`", true),
+        Rule::fac => ("`!`", true),
+        Rule::compare_eq => ("`==`", true),
+        Rule::compare_ne => ("`!=`", true),
+        Rule::compare_ge => ("`>=`", true),
+        Rule::compare_le => ("`<=`", true),
+        Rule::compare_lt => ("`<`", true),
+        Rule::compare_gt => ("`>`", true),
+        Rule::dot => ("`.`", true),
+        Rule::optional_dot => ("`?.`", true),
+        Rule::coalesce => ("`??`", true),
+        Rule::index | Rule::slice => ("`[`", true),
+        Rule::EOI => (if zh { "公式结尾" } else { "end of formula" }, true),
+        Rule::num | Rule::int => (if zh { "数字" } else { "number" }, false),
+        Rule::string => (if zh { "字符串" } else { "string" }, false),
+        Rule::literal | Rule::template => (
+            if zh {
+                "数字或字符串"
+            } else {
+                "number or string"
+            },
+            false,
+        ),
+        Rule::identifier | Rule::variable => (if zh { "标识符" } else { "identifier" }, false),
+        Rule::function_call => (if zh { "函数调用" } else { "function call" }, false),
+        Rule::array => (if zh { "数组" } else { "array" }, false),
+        Rule::function_argument => (if zh { "函数参数" } else { "argument" }, false),
+        Rule::type_kw => ("`type`", false),
+        Rule::func_kw => ("`func`", false),
+        _ => (if zh { "表达式" } else { "expression" }, false),
+    }
+}
+
+fn expected_names(positives: &[Rule], lang: Language) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for rule in positives {
+        // 缺少操作数时前缀运算符也能匹配，但后面仍然需要操作数，只提示操作数
+        if matches!(rule, Rule::neg | Rule::pos) {
+            continue;
+        }
+        let name = rule_name(*rule, lang).0.to_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+// 返回没有闭合的块注释的起始位置
+fn unclosed_comment(input: &str) -> Option<usize> {
+    open_comment(input).filter(|start| input[*start..].starts_with("/*"))
+}
+
+// 返回没有闭合的字符串的起始位置
+fn unclosed_string(input: &str) -> Option<usize> {
+    let mut quotes = Quotes::default();
+    let mut start = None;
+    for (i, c) in input.char_indices() {
+        let outside = !quotes.in_string();
+        if quotes.scan(c) && outside {
+            start = Some(i);
+        }
+    }
+    start.filter(|_| quotes.in_string())
+}
+
+// 返回字符串中第一个无法识别的转义的位置，例如 `'a\q'` 中的 `\q`、`'\u{D800}'`
+fn invalid_escape(input: &str) -> Option<(usize, usize)> {
+    let mut quotes = Quotes::default();
+    let mut escaped = false;
+    for (i, c) in input.char_indices() {
+        let inside = quotes.in_string();
+        quotes.scan(c);
+        if !inside || c != '\\' || escaped {
+            escaped = false;
+            continue;
+        }
+        escaped = true;
+
+        let rest = &input[i + 1..];
+        let end = match rest.chars().next() {
+            Some('u') => {
+                let hex = rest
+                    .strip_prefix("u{")
+                    .and_then(|hex| hex.find('}').map(|end| &hex[..end]));
+                match hex {
+                    Some(hex) if is_scalar(hex) => continue,
+                    Some(hex) => i + 4 + hex.len(),
+                    None => i + 2,
+                }
+            }
+            Some(c) if "'\"\\nrt`$".contains(c) => continue,
+            Some(c) => i + 1 + c.len_utf8(),
+            None => continue,
+        };
+        return Some((i, end));
+    }
+    None
+}
+
+fn is_scalar(hex: &str) -> bool {
+    (1..=6).contains(&hex.len())
+        && u32::from_str_radix(hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .is_some()
+}
+
+// 忽略字符串中的括号，返回括号的嵌套深度
+fn parenthesis_depth(input: &str) -> i32 {
+    let mut depth = 0;
+    let mut quotes = Quotes::default();
+    for c in input.chars() {
+        if quotes.scan(c) {
+            continue;
+        }
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+// 返回最后一个没有闭合的左括号的位置
+fn unclosed_parenthesis(input: &str) -> Option<usize> {
+    let mut open = Vec::new();
+    let mut quotes = Quotes::default();
+    for (i, c) in input.char_indices() {
+        if quotes.scan(c) {
+            continue;
+        }
+        match c {
+            '(' => open.push(i),
+            ')' => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    open.pop()
+}
+
+// 错误位置附近连续的运算符字符，例如 `a => b` 中的 `=>`
+fn operator_around(input: &str, pos: usize) -> Option<(usize, usize)> {
+    let start = input[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_operator_char(*c))
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(pos);
+    let end = pos
+        + input[pos..]
+            .chars()
+            .take_while(|c| is_operator_char(*c))
+            .map(|c| c.len_utf8())
+            .sum::<usize>();
+
+    if start == end {
+        None
+    } else {
+        Some((start, end))
+    }
+}
+
+fn suggest_operator(operator: &str) -> Option<&'static str> {
+    match operator {
+        "=>" => Some(">="),
+        "=<" => Some("<="),
+        "**" => Some("^"),
+        "===" => Some("=="),
+        "!==" => Some("!="),
+        "><" => Some("!="),
+        _ => None,
+    }
+}
+
+fn token_at(rest: &str) -> &str {
+    let first = match rest.chars().next() {
+        Some(c) => c,
+        None => return "",
+    };
+    let len = if is_word_char(first) {
+        rest.chars()
+            .take_while(|c| is_word_char(*c))
+            .map(|c| c.len_utf8())
+            .sum()
+    } else {
+        first.len_utf8()
+    };
+    &rest[..len]
+}
@@ -0,0 +1,357 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use super::{
+    ast::*,
+    type_ast::{FuncDefine, NamedType, RecordType, TypeDefine, TypeItemKind},
+};
+use crate::types::operator::FormulaOperator;
+
+// 把 AST 重新输出成规范的公式源码：统一空格、运算符别名只保留一种写法、只加必要的括号
+// 和 Beautify 不同，输出的结果可以再次被解析，并且得到相同的 AST，注释不在 AST 中，格式化之后会丢失
+pub trait Format {
+    fn format(&self) -> String;
+}
+
+// 运算符的规范写法，例如 plus -> +、<> -> !=
+pub fn canonical_operator(raw: &str) -> &str {
+    match FormulaOperator::from_raw(raw) {
+        Some(FormulaOperator::Add) => "+",
+        Some(FormulaOperator::Sub) => "-",
+        Some(FormulaOperator::Mul) => "*",
+        Some(FormulaOperator::Div) => "/",
+        Some(FormulaOperator::Pow) => "^",
+        Some(FormulaOperator::Modulo) => "%",
+        Some(FormulaOperator::Factorial) => "!",
+        Some(FormulaOperator::Eq) => "==",
+        Some(FormulaOperator::Ne) => "!=",
+        Some(FormulaOperator::Gt) => ">",
+        Some(FormulaOperator::Ge) => ">=",
+        Some(FormulaOperator::Lt) => "<",
+        Some(FormulaOperator::Le) => "<=",
+        _ => raw,
+    }
+}
+
+// 和 TYPE_PRATT_PARSER 中的优先级保持一致，数字越大优先级越高
+const COMPARE: u8 = 1;
+const COALESCE: u8 = 2;
+const POWER: u8 = 10;
+const PREFIX: u8 = 11;
+const POSTFIX: u8 = 12;
+const ATOM: u8 = 13;
+
+fn binary_precedence(raw: &str) -> u8 {
+    match FormulaOperator::from_raw(raw) {
+        Some(FormulaOperator::Coalesce) => COALESCE,
+        Some(FormulaOperator::BitOr) => 3,
+        Some(FormulaOperator::BitXor) => 4,
+        Some(FormulaOperator::BitAnd) => 5,
+        Some(FormulaOperator::ShiftLeft) | Some(FormulaOperator::ShiftRight) => 6,
+        Some(FormulaOperator::Add) | Some(FormulaOperator::Sub) => 7,
+        Some(FormulaOperator::Mul) | Some(FormulaOperator::Div) | Some(FormulaOperator::IntDiv) => {
+            8
+        }
+        Some(FormulaOperator::Modulo) => 9,
+        Some(FormulaOperator::Pow) => POWER,
+        _ => COMPARE,
+    }
+}
+
+fn precedence(expr: &ExpressionKind) -> u8 {
+    match expr {
+        ExpressionKind::BinaryExpressionKind(_, binary) => {
+            binary_precedence(binary.operator.1.as_str())
+        }
+        ExpressionKind::UnaryExpressionKind(_, unary) if unary.prefix => PREFIX,
+        ExpressionKind::UnaryExpressionKind(_, _) => POSTFIX,
+        _ => ATOM,
+    }
+}
+
+fn parenthesize(expr: &ExpressionKind, parenthesized: bool) -> String {
+    match parenthesized {
+        true => format!("({})", expr.format()),
+        false => expr.format(),
+    }
+}
+
+impl Format for FormulaBody {
+    fn format(&self) -> String {
+        self.body
+            .iter()
+            .map(|(_, statement)| statement.format())
+            .collect::<Vec<String>>()
+            .join(";\n")
+    }
+}
+
+impl Format for ExpressionStatement {
+    fn format(&self) -> String {
+        self.expression.1.format()
+    }
+}
+
+impl Format for UnaryExpression {
+    fn format(&self) -> String {
+        // 语法上阶乘只能跟在原子后面，例如 (1 + 2)!、(3!)!、(-a)!
+        let argument = &*self.argument.1;
+        let operator = canonical_operator(self.operator.1.as_str());
+        match self.prefix {
+            // 紧挨着的 -1 会被解析成数字字面量，所以 -(1) 输出成 - 1，-(3!) 输出成 - 3!
+            true => {
+                let argument = parenthesize(argument, precedence(argument) < PREFIX);
+                match argument.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+                    true => format!("{} {}", operator, argument),
+                    false => format!("{}{}", operator, argument),
+                }
+            }
+            false => format!(
+                "{}{}",
+                parenthesize(argument, precedence(argument) < ATOM),
+                operator
+            ),
+        }
+    }
+}
+
+impl Format for BinaryExpression {
+    fn format(&self) -> String {
+        let operator = self.operator.1.as_str();
+        let current = binary_precedence(operator);
+        let left = precedence(&self.left.1);
+        let right = precedence(&self.right.1);
+
+        // 比较运算不能连写，a == b == c 无法解析；乘方和空值合并是右结合，其他运算左结合
+        let (left_parens, right_parens) = match current {
+            COMPARE => (left <= COMPARE, right <= COMPARE),
+            COALESCE | POWER => (left <= current, right < current),
+            _ => (left < current, right <= current),
+        };
+
+        format!(
+            "{} {} {}",
+            parenthesize(&self.left.1, left_parens),
+            canonical_operator(operator),
+            parenthesize(&self.right.1, right_parens)
+        )
+    }
+}
+
+impl Format for CallExpression {
+    fn format(&self) -> String {
+        let arguments = self
+            .arguments
+            .iter()
+            .map(|(_, argument)| argument.format())
+            .collect::<Vec<String>>();
+
+        format!("{}({})", self.callee.1.format(), arguments.join(", "))
+    }
+}
+
+impl Format for PropertyAccessExpression {
+    fn format(&self) -> String {
+        let object = &*self.object.1;
+        format!(
+            "{}{}{}",
+            parenthesize(object, precedence(object) < ATOM),
+            if self.optional { "?." } else { "." },
+            self.property.1.name
+        )
+    }
+}
+
+impl Format for IndexExpression {
+    fn format(&self) -> String {
+        let object = &*self.object.1;
+        format!(
+            "{}[{}]",
+            parenthesize(object, precedence(object) < ATOM),
+            self.index.1.format()
+        )
+    }
+}
+
+impl Format for SliceExpression {
+    fn format(&self) -> String {
+        let object = &*self.object.1;
+        let bound = |bound: &Option<(Range, Box<ExpressionKind>)>| match bound {
+            Some((_, expr)) => expr.format(),
+            None => String::new(),
+        };
+        format!(
+            "{}[{}:{}]",
+            parenthesize(object, precedence(object) < ATOM),
+            bound(&self.start),
+            bound(&self.end)
+        )
+    }
+}
+
+impl Format for ArrayLiteral {
+    fn format(&self) -> String {
+        format!(
+            "[{}]",
+            self.elements
+                .iter()
+                .map(|(_, element)| element.format())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    }
+}
+
+// 文本保留源码中的写法，${} 中的表达式重新格式化
+impl Format for TemplateLiteral {
+    fn format(&self) -> String {
+        let parts = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Text(_, text) => text.raw.clone(),
+                TemplatePart::Expression(_, expr) => format!("${{{}}}", expr.format()),
+            })
+            .collect::<Vec<String>>();
+        format!("`{}`", parts.join(""))
+    }
+}
+
+impl Format for StringLiteral {
+    fn format(&self) -> String {
+        self.raw.clone()
+    }
+}
+
+impl Format for NumberLiteral {
+    fn format(&self) -> String {
+        self.raw.clone()
+    }
+}
+
+impl Format for BoolLiteral {
+    fn format(&self) -> String {
+        self.value.to_string()
+    }
+}
+
+impl Format for NullLiteral {
+    fn format(&self) -> String {
+        "null".to_string()
+    }
+}
+
+impl Format for DateLiteral {
+    fn format(&self) -> String {
+        self.raw.clone()
+    }
+}
+
+impl Format for Identifier {
+    fn format(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl Format for NamedType {
+    fn format(&self) -> String {
+        match self.parameters.len() {
+            0 => self.ident.1.name.clone(),
+            _ => format!(
+                "{}<{}>",
+                self.ident.1.name,
+                self.parameters
+                    .iter()
+                    .map(|(_, parameter)| parameter.format())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl Format for RecordType {
+    fn format(&self) -> String {
+        match self.fields.len() {
+            0 => "{}".to_string(),
+            _ => format!(
+                "{{ {} }}",
+                self.fields
+                    .iter()
+                    .map(|(_, field)| format!("{}: {}", field.key.1.name, field.value.1.format()))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl Format for TypeItemKind {
+    fn format(&self) -> String {
+        match self {
+            TypeItemKind::NamedTypeKind(named_type) => named_type.format(),
+            TypeItemKind::RecordTypeKind(record_type) => record_type.format(),
+        }
+    }
+}
+
+impl Format for LetBinding {
+    fn format(&self) -> String {
+        format!("let {} = {}", self.ident.1.name, self.value.1.format())
+    }
+}
+
+impl Format for TypeDefine {
+    fn format(&self) -> String {
+        format!("type {} = {}", self.ident.1.name, self.type_item.1.format())
+    }
+}
+
+impl Format for FuncDefine {
+    fn format(&self) -> String {
+        format!(
+            "func {}({}) -> {}",
+            self.ident.1.name,
+            self.arguments
+                .iter()
+                .map(|(_, argument)| argument.format())
+                .collect::<Vec<String>>()
+                .join(", "),
+            self.return_type.1.format()
+        )
+    }
+}
+
+impl Format for ExpressionKind {
+    fn format(&self) -> String {
+        match self {
+            ExpressionKind::UnaryExpressionKind(_, unary_expression) => unary_expression.format(),
+            ExpressionKind::BinaryExpressionKind(_, binary_expression) => {
+                binary_expression.format()
+            }
+            ExpressionKind::CallExpressionKind(_, call_expression) => call_expression.format(),
+            ExpressionKind::PropertyAccessExpressionKind(_, property_access_expression) => {
+                property_access_expression.format()
+            }
+            ExpressionKind::IndexExpressionKind(_, index_expression) => index_expression.format(),
+            ExpressionKind::SliceExpressionKind(_, slice_expression) => slice_expression.format(),
+            ExpressionKind::ArrayLiteralKind(_, array_literal) => array_literal.format(),
+            ExpressionKind::TemplateLiteralKind(_, template_literal) => template_literal.format(),
+            ExpressionKind::StringLiteralKind(_, string_literal) => string_literal.format(),
+            ExpressionKind::NumberLiteralKind(_, number_literal) => number_literal.format(),
+            ExpressionKind::BoolLiteralKind(_, bool_literal) => bool_literal.format(),
+            ExpressionKind::NullLiteralKind(_, null_literal) => null_literal.format(),
+            ExpressionKind::DateLiteralKind(_, date_literal) => date_literal.format(),
+            ExpressionKind::IdentifierKind(_, identifier) => identifier.format(),
+            ExpressionKind::TypeDefineKind(_, type_define) => type_define.format(),
+            ExpressionKind::FuncDefineKind(_, func_define) => func_define.format(),
+            ExpressionKind::LetBindingKind(_, let_binding) => let_binding.format(),
+            // 容错解析时无法解析的部分原样输出
+            ExpressionKind::ErrorKind(_, error) => error.raw.clone(),
+        }
+    }
+}
@@ -1,13 +1,43 @@
-use alloc::vec::Vec;
+use alloc::{vec, vec::Vec};
 
-use super::ast::{ExpressionKind, ExpressionStatement, FormulaBody};
+use crate::share::date::parse_date;
+
+use super::ast::{
+    CallExpression, ExpressionKind, ExpressionStatement, FormulaBody, Identifier, Range,
+    TemplatePart,
+};
 
 impl FormulaBody {
-    pub fn walk(&self) -> Vec<ExpressionKind> {
+    pub fn walk(&self) -> Vec<&ExpressionKind> {
+        let mut result = Vec::new();
+
+        for expr in self.body.iter() {
+            result.extend(expr.1.walk());
+        }
+
+        result
+    }
+
+    // 从外到内，返回包含 offset 的所有节点
+    pub fn nodes_at(&self, offset: usize) -> Vec<&ExpressionKind> {
         let mut result = Vec::new();
 
         for expr in self.body.iter() {
-            result = [result, expr.1.walk()].concat();
+            let mut node = &expr.1.expression.1;
+            if !node.range().contains(offset) {
+                continue;
+            }
+            loop {
+                result.push(node);
+                match node
+                    .children()
+                    .into_iter()
+                    .find(|child| child.range().contains(offset))
+                {
+                    Some(child) => node = child,
+                    None => break,
+                }
+            }
         }
 
         result
@@ -15,17 +45,158 @@ impl FormulaBody {
 }
 
 impl ExpressionStatement {
-    pub fn walk(&self) -> Vec<ExpressionKind> {
+    pub fn walk(&self) -> Vec<&ExpressionKind> {
         self.expression.1.walk()
     }
 }
 
+impl Range {
+    // 光标在结尾时也算在范围内，例如 `abc|`
+    pub fn contains(&self, offset: usize) -> bool {
+        self.0 <= offset && offset <= self.1
+    }
+}
+
 impl ExpressionKind {
-    pub fn walk(&self) -> Vec<ExpressionKind> {
-        let result = Vec::new();
+    pub fn range(&self) -> &Range {
+        match self {
+            ExpressionKind::UnaryExpressionKind(range, _)
+            | ExpressionKind::BinaryExpressionKind(range, _)
+            | ExpressionKind::CallExpressionKind(range, _)
+            | ExpressionKind::PropertyAccessExpressionKind(range, _)
+            | ExpressionKind::IndexExpressionKind(range, _)
+            | ExpressionKind::SliceExpressionKind(range, _)
+            | ExpressionKind::StringLiteralKind(range, _)
+            | ExpressionKind::NumberLiteralKind(range, _)
+            | ExpressionKind::BoolLiteralKind(range, _)
+            | ExpressionKind::NullLiteralKind(range, _)
+            | ExpressionKind::DateLiteralKind(range, _)
+            | ExpressionKind::IdentifierKind(range, _)
+            | ExpressionKind::ArrayLiteralKind(range, _)
+            | ExpressionKind::TemplateLiteralKind(range, _)
+            | ExpressionKind::TypeDefineKind(range, _)
+            | ExpressionKind::FuncDefineKind(range, _)
+            | ExpressionKind::LetBindingKind(range, _)
+            | ExpressionKind::ErrorKind(range, _) => range,
+        }
+    }
 
-        
+    pub fn children(&self) -> Vec<&ExpressionKind> {
+        match self {
+            ExpressionKind::UnaryExpressionKind(_, unary) => vec![&*unary.argument.1],
+            ExpressionKind::BinaryExpressionKind(_, binary) => {
+                vec![&*binary.left.1, &*binary.right.1]
+            }
+            ExpressionKind::CallExpressionKind(_, call) => {
+                let mut children = vec![&*call.callee.1];
+                children.extend(call.arguments.iter().map(|argument| &*argument.1));
+                children
+            }
+            ExpressionKind::PropertyAccessExpressionKind(_, access) => vec![&*access.object.1],
+            ExpressionKind::IndexExpressionKind(_, index) => {
+                vec![&*index.object.1, &*index.index.1]
+            }
+            ExpressionKind::SliceExpressionKind(_, slice) => {
+                let mut children = vec![&*slice.object.1];
+                children.extend(slice.start.iter().map(|start| &*start.1));
+                children.extend(slice.end.iter().map(|end| &*end.1));
+                children
+            }
+            ExpressionKind::ArrayLiteralKind(_, array) => {
+                array.elements.iter().map(|element| &*element.1).collect()
+            }
+            ExpressionKind::LetBindingKind(_, binding) => vec![&*binding.value.1],
+            ExpressionKind::TemplateLiteralKind(_, template) => template
+                .parts
+                .iter()
+                .filter_map(|part| match part {
+                    TemplatePart::Expression(_, expression) => Some(&**expression),
+                    TemplatePart::Text(_, _) => None,
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    pub fn walk(&self) -> Vec<&ExpressionKind> {
+        let mut result = vec![self];
+
+        for child in self.children() {
+            result.extend(child.walk());
+        }
 
         result
     }
 }
+
+impl CallExpression {
+    // 旧的 SUM、COUNT 函数使用了 status=2 这种过滤写法，按参数的形式判断
+    // SUM(where(subtask, $.status == 2).estimatePoint) 这种新写法的参数和 sum 相同
+    pub fn is_legacy(&self) -> bool {
+        self.is_legacy_function()
+            && self
+                .arguments
+                .iter()
+                .skip(1)
+                .any(|(_, argument)| argument.is_legacy_filter())
+    }
+
+    // 调用的是旧的 SUM、COUNT 函数，不管参数是什么形式
+    pub fn is_legacy_function(&self) -> bool {
+        matches!(
+            &*self.callee.1,
+            ExpressionKind::IdentifierKind(_, Identifier { name }) if name == "SUM" || name == "COUNT"
+        )
+    }
+
+    // date('2024-01-31') 这种参数是字符串字面量的调用，返回日期的毫秒时间戳
+    pub fn date_literal(&self) -> Option<u64> {
+        let is_date = matches!(
+            &*self.callee.1,
+            ExpressionKind::IdentifierKind(_, Identifier { name }) if name == "date"
+        );
+        match (is_date, self.arguments.as_slice()) {
+            (true, [(_, argument)]) => match &**argument {
+                ExpressionKind::StringLiteralKind(_, string) => parse_date(&string.value),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl ExpressionKind {
+    // status=2 这种左边是字段名、右边是字段名或者常量的比较
+    pub fn is_legacy_filter(&self) -> bool {
+        match self {
+            ExpressionKind::BinaryExpressionKind(_, binary) => {
+                ["==", "=", "!=", "<>", ">=", "<=", ">", "<"].contains(&binary.operator.1.as_str())
+                    && matches!(&*binary.left.1, ExpressionKind::IdentifierKind(_, _))
+                    && matches!(
+                        &*binary.right.1,
+                        ExpressionKind::IdentifierKind(_, _)
+                            | ExpressionKind::StringLiteralKind(_, _)
+                            | ExpressionKind::NumberLiteralKind(_, _)
+                    )
+            }
+            _ => false,
+        }
+    }
+
+    // 是否含有没有被内层函数绑定的 $，作为函数参数时就是一个 lambda
+    // 内层函数除第一个参数外，含有 $ 的参数属于内层函数自己的 lambda
+    pub fn is_lambda(&self) -> bool {
+        match self {
+            ExpressionKind::IdentifierKind(_, identifier) => identifier.name == "$",
+            ExpressionKind::CallExpressionKind(_, call) => {
+                call.callee.1.is_lambda()
+                    || call
+                        .arguments
+                        .first()
+                        .map(|(_, argument)| argument.is_lambda())
+                        .unwrap_or(false)
+            }
+            _ => self.children().iter().any(|child| child.is_lambda()),
+        }
+    }
+}
@@ -4,4 +4,8 @@ pub mod beautify;
 pub mod type_ast;
 pub mod to_operator;
 pub mod dependencies;
-// pub mod iter;
\ No newline at end of file
+pub mod diagnostic;
+pub mod format;
+pub mod recover;
+pub mod iter;
+pub mod source_map;
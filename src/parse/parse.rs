@@ -1,6 +1,17 @@
 extern crate pest;
 
-use pest::{error::Error, iterators::Pairs, Parser};
+use alloc::{format, string::String};
+use pest::{
+    error::{Error, ErrorVariant},
+    iterators::{Pair, Pairs},
+    Parser,
+};
+
+use crate::share::{
+    date::parse_date,
+    decimal::{parse_decimal, DecimalError},
+    string::unescape,
+};
 
 #[derive(Parser)]
 #[grammar = "parse/formula.pest"]
@@ -14,8 +25,87 @@ pub struct Formula<'a> {
 impl Formula<'_> {
     pub fn parse(input: &str) -> Result<Formula, Error<Rule>> {
         match FormulaPest::parse(Rule::formula, input) {
-            Ok(pairs) => Ok(Formula { paris: pairs }),
+            Ok(pairs) => {
+                check_literals(pairs.clone())?;
+                Ok(Formula { paris: pairs })
+            }
             Err(e) => Err(e),
         }
     }
 }
+
+// 语法只限制了日期字面量能出现的字符，这里检查 #2024-01-31#、date('2024-01-31') 中的日期是否存在
+// 以及字符串中的 \u{} 是否是合法的 Unicode 字符，数字字面量是否能放进 Rational64
+pub(crate) fn check_literals(pairs: Pairs<Rule>) -> Result<(), Error<Rule>> {
+    for pair in pairs.flatten() {
+        match pair.as_rule() {
+            Rule::string => check_escape(&pair, &pair.as_str()[1..pair.as_str().len() - 1])?,
+            Rule::template_text => check_escape(&pair, pair.as_str())?,
+            Rule::num => check_number(&pair)?,
+            _ => {}
+        }
+
+        let literal = match pair.as_rule() {
+            Rule::date => Some(pair),
+            Rule::function_call => date_call_argument(&pair),
+            _ => None,
+        };
+
+        if let Some(literal) = literal {
+            let raw = literal.as_str();
+            // 转义错误在遍历到字符串时报告
+            let text = unescape(&raw[1..raw.len() - 1]).unwrap_or_default();
+            if parse_date(&text).is_none() {
+                return Err(custom_error(&literal, format!("invalid date `{}`", raw)));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_escape(pair: &Pair<Rule>, text: &str) -> Result<(), Error<Rule>> {
+    match unescape(text) {
+        Some(_) => Ok(()),
+        None => Err(custom_error(
+            pair,
+            format!("invalid escape in `{}`", pair.as_str()),
+        )),
+    }
+}
+
+fn check_number(pair: &Pair<Rule>) -> Result<(), Error<Rule>> {
+    match parse_decimal(pair.as_str()) {
+        Err(DecimalError::OutOfRange) => Err(custom_error(
+            pair,
+            format!("number `{}` is out of range", pair.as_str()),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn custom_error(pair: &Pair<Rule>, message: String) -> Error<Rule> {
+    Error::new_from_span(ErrorVariant::CustomError { message }, pair.as_span())
+}
+
+// date('2024-01-31') 中的字符串，参数不是字符串字面量时在运行时计算
+fn date_call_argument<'a>(pair: &Pair<'a, Rule>) -> Option<Pair<'a, Rule>> {
+    let mut inner = pair.clone().into_inner();
+    if inner.next()?.as_str() != "date" {
+        return None;
+    }
+
+    let argument = inner.next()?;
+    if inner.next().is_some() {
+        return None;
+    }
+
+    let literal = argument.into_inner().next()?;
+    let string = match literal.as_rule() {
+        Rule::literal => literal.into_inner().next()?,
+        _ => return None,
+    };
+    match string.as_rule() {
+        Rule::string => Some(string),
+        _ => None,
+    }
+}
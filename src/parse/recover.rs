@@ -0,0 +1,336 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use pest::Parser;
+
+use super::{
+    ast::{
+        to_ast, variable_or_literal_or_expression, ArrayLiteral, CallExpression, ErrorExpression,
+        ExpressionAstItem, ExpressionKind, ExpressionStatement, FormulaBody, Range,
+    },
+    diagnostic::{diagnose, Diagnostic, DiagnosticCode, Language},
+    parse::{check_literals, Formula, FormulaPest, Rule},
+};
+use crate::share::string::{blank_comments, Quotes};
+
+// 容错解析：一个语句或参数解析失败时，用 ErrorExpression 代替它，继续解析后面的部分
+// 这样编辑器在用户输入到一半的时候，仍然可以提供补全和类型信息
+pub fn parse_tolerant(input: &str, lang: Language) -> (FormulaBody, Vec<Diagnostic>) {
+    let original = input;
+    if let Ok(formula) = Formula::parse(input) {
+        let (_, ast) = to_ast(formula.paris);
+        return (ast, Vec::new());
+    }
+
+    // 按字符切分语句、参数时不能把注释中的分号、括号算进去，注释替换成空格后位置不变
+    let blanked = blank_comments(input);
+    let input = blanked.as_str();
+    // 去掉注释后可以解析，说明只是块注释没有闭合
+    if let Ok(formula) = Formula::parse(input) {
+        let (_, ast) = to_ast(formula.paris);
+        let diagnostics = diagnose(original, lang).into_iter().collect();
+        return (ast, diagnostics);
+    }
+
+    let mut body = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    let segments = split_top_level(input, 0, input.len(), &[';']);
+    if segments
+        .iter()
+        .all(|(start, end)| is_blank(input, *start, *end))
+    {
+        diagnostics.push(Diagnostic::new(
+            Range(0, input.len()),
+            DiagnosticCode::EmptyFormula,
+            lang,
+            "",
+            Vec::new(),
+        ));
+    }
+
+    for (start, end) in segments {
+        if is_blank(input, start, end) {
+            continue;
+        }
+        let expression = recover_expression(
+            input,
+            start,
+            end,
+            Rule::single_statement,
+            lang,
+            &mut diagnostics,
+        );
+        body.push((expression.0.clone(), ExpressionStatement { expression }));
+    }
+
+    (FormulaBody { body }, diagnostics)
+}
+
+fn recover_expression(
+    input: &str,
+    start: usize,
+    end: usize,
+    rule: Rule,
+    lang: Language,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> ExpressionAstItem {
+    let masked = mask(input, start, end);
+
+    let error = match parse_masked(&masked, rule) {
+        Ok(expression) => return expression,
+        Err(error) => error,
+    };
+
+    if let Some(call) = recover_call(input, start, end, lang, diagnostics) {
+        return call;
+    }
+
+    diagnostics.push(Diagnostic::from_pest_error(&masked, &error, lang));
+    if let Some(array) = recover_array(input, start, end, lang) {
+        return array;
+    }
+    let (start, end) = trim(input, start, end);
+    ExpressionAstItem(
+        Range(start, end),
+        ExpressionKind::ErrorKind(
+            Range(start, end),
+            ErrorExpression {
+                raw: input[start..end].to_string(),
+            },
+        ),
+    )
+}
+
+fn parse_masked(masked: &str, rule: Rule) -> Result<ExpressionAstItem, pest::error::Error<Rule>> {
+    let pairs = FormulaPest::parse(rule, masked)?;
+    check_literals(pairs.clone())?;
+    Ok(match rule {
+        Rule::single_statement => {
+            let (_, mut ast) = to_ast(pairs);
+            ast.body.remove(0).1.expression
+        }
+        _ => {
+            let argument = pairs.into_iter().next().unwrap();
+            variable_or_literal_or_expression(argument.into_inner().next().unwrap())
+        }
+    })
+}
+
+// 把 callee(arg1, arg2, ...) 按参数拆开分别解析
+fn recover_call(
+    input: &str,
+    start: usize,
+    end: usize,
+    lang: Language,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<ExpressionAstItem> {
+    let segment_end = end;
+    let (start, end) = trim(input, start, end);
+    let text = &input[start..end];
+
+    let callee_len = text
+        .char_indices()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '$' || *c == '.')
+        .last()
+        .map(|(i, c)| i + c.len_utf8())?;
+    let open =
+        start + callee_len + (text[callee_len..].len() - text[callee_len..].trim_start().len());
+    if !input[open..end].starts_with('(') {
+        return None;
+    }
+
+    // 找到对应的右括号，右括号之后不能再有其他内容
+    let close = match matching_parenthesis(input, open, end) {
+        Some(close) if is_blank(input, close + 1, end) => Some(close),
+        Some(_) => return None,
+        None => None,
+    };
+
+    let mut call_diagnostics = Vec::new();
+    let callee = recover_expression(
+        input,
+        start,
+        start + callee_len,
+        Rule::single_argument,
+        lang,
+        &mut call_diagnostics,
+    );
+    if matches!(callee.1, ExpressionKind::ErrorKind(_, _)) {
+        return None;
+    }
+
+    let arguments_end = close.unwrap_or(end);
+    let segments = split_top_level(input, open + 1, arguments_end, &[',', ';']);
+    let mut arguments = Vec::new();
+    for (index, (arg_start, arg_end)) in segments.iter().enumerate() {
+        if is_blank(input, *arg_start, *arg_end) {
+            // 允许 COUNT(subtask;) 这种末尾的分隔符，以及没有参数的 f()
+            if index != segments.len() - 1 {
+                call_diagnostics.push(Diagnostic::new(
+                    Range(*arg_end, *arg_end + 1),
+                    DiagnosticCode::MissingArgument,
+                    lang,
+                    "",
+                    Vec::new(),
+                ));
+            }
+            continue;
+        }
+        let argument = recover_expression(
+            input,
+            *arg_start,
+            *arg_end,
+            Rule::single_argument,
+            lang,
+            &mut call_diagnostics,
+        );
+        arguments.push((argument.0, Box::new(argument.1)));
+    }
+
+    if close.is_none() {
+        call_diagnostics.push(Diagnostic::new(
+            Range(open, end),
+            DiagnosticCode::MissingClosingParenthesis,
+            lang,
+            "(",
+            Vec::new(),
+        ));
+    }
+
+    diagnostics.extend(call_diagnostics);
+    // 没有右括号时一直延伸到结尾的空白，编辑器在 `SUM(1, |` 中仍然能找到这个调用
+    let range = Range(start, close.map(|close| close + 1).unwrap_or(segment_end));
+    Some(ExpressionAstItem(
+        range.clone(),
+        ExpressionKind::CallExpressionKind(
+            range,
+            CallExpression {
+                callee: (callee.0, Box::new(callee.1)),
+                arguments,
+            },
+        ),
+    ))
+}
+
+// 没有闭合的数组字面量，例如 `[subtask.`，按元素拆开解析
+// 整个数组的语法错误已经报告过了，元素的错误不再重复报告
+fn recover_array(
+    input: &str,
+    start: usize,
+    end: usize,
+    lang: Language,
+) -> Option<ExpressionAstItem> {
+    let segment_end = end;
+    let (start, end) = trim(input, start, end);
+    if !input[start..end].starts_with('[') || matching_parenthesis(input, start, end).is_some() {
+        return None;
+    }
+
+    let mut elements = Vec::new();
+    for (element_start, element_end) in split_top_level(input, start + 1, end, &[',']) {
+        if is_blank(input, element_start, element_end) {
+            continue;
+        }
+        let element = recover_expression(
+            input,
+            element_start,
+            element_end,
+            Rule::single_argument,
+            lang,
+            &mut Vec::new(),
+        );
+        elements.push((element.0, Box::new(element.1)));
+    }
+
+    let range = Range(start, segment_end);
+    Some(ExpressionAstItem(
+        range.clone(),
+        ExpressionKind::ArrayLiteralKind(range, ArrayLiteral { elements }),
+    ))
+}
+
+// 只保留 [start, end) 的内容，前面的部分替换成等长的空格，这样解析出来的 Range 不需要再做偏移
+// 后面的部分直接去掉，否则标识符的范围会包含后面补上的空格
+fn mask(input: &str, start: usize, end: usize) -> String {
+    let mut masked = String::with_capacity(end);
+    for (i, c) in input[..end].char_indices() {
+        if i >= start && i < end {
+            masked.push(c);
+        } else {
+            for _ in 0..c.len_utf8() {
+                masked.push(' ');
+            }
+        }
+    }
+    masked
+}
+
+// 按不在括号、字符串中的分隔符切分
+fn split_top_level(
+    input: &str,
+    start: usize,
+    end: usize,
+    separators: &[char],
+) -> Vec<(usize, usize)> {
+    let mut segments = Vec::new();
+    let mut depth = 0;
+    let mut quotes = Quotes::default();
+    let mut segment_start = start;
+    for (i, c) in input[start..end].char_indices() {
+        let i = start + i;
+        if quotes.scan(c) {
+            continue;
+        }
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            c if depth == 0 && separators.contains(&c) => {
+                segments.push((segment_start, i));
+                segment_start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    segments.push((segment_start, end));
+    segments
+}
+
+// open 处是 ( 或者 [，返回对应的右括号
+fn matching_parenthesis(input: &str, open: usize, end: usize) -> Option<usize> {
+    let (left, right) = match input[open..].starts_with('[') {
+        true => ('[', ']'),
+        false => ('(', ')'),
+    };
+    let mut depth = 0;
+    let mut quotes = Quotes::default();
+    for (i, c) in input[open..end].char_indices() {
+        if quotes.scan(c) {
+            continue;
+        }
+        match c {
+            c if c == left => depth += 1,
+            c if c == right => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_blank(input: &str, start: usize, end: usize) -> bool {
+    start >= end || input[start..end].trim().is_empty()
+}
+
+fn trim(input: &str, start: usize, end: usize) -> (usize, usize) {
+    let text = &input[start..end];
+    let trimmed_start = start + (text.len() - text.trim_start().len());
+    let trimmed_end = start + text.trim_end().len();
+    (trimmed_start, trimmed_end.max(trimmed_start))
+}
@@ -0,0 +1,171 @@
+use alloc::{vec, vec::Vec};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ast::{ExpressionKind, FormulaBody, Range},
+    to_operator::ToOperator,
+};
+use crate::share::operator::OperatorCode;
+
+// 每条指令来自源码中的哪个位置，ranges 和指令一一对应
+// lambda 中的指令单独保存在 lambdas 中，顺序和 PushLambda 出现的顺序相同
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SourceMap {
+    pub ranges: Vec<Range>,
+    pub lambdas: Vec<SourceMap>,
+}
+
+impl SourceMap {
+    // 所有指令都对应同一个位置，用于无法细分的指令
+    pub fn filled(codes: &[OperatorCode], range: &Range) -> Self {
+        Self {
+            ranges: vec![range.clone(); codes.len()],
+            lambdas: codes
+                .iter()
+                .filter_map(|code| match code {
+                    OperatorCode::PushLambda(lambda) => Some(SourceMap::filled(lambda, range)),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn range(&self, index: usize) -> Option<&Range> {
+        self.ranges.get(index)
+    }
+
+    // 按指令查找 lambda 的源码位置，codes 是这个 SourceMap 对应的指令
+    // 执行时 lambda 只携带指令，只有出错时才需要查找，相同的 lambda 返回第一个
+    pub fn lambda(&self, codes: &[OperatorCode], lambda: &[OperatorCode]) -> Option<&SourceMap> {
+        let pushes = codes.iter().filter_map(|code| match code {
+            OperatorCode::PushLambda(codes) => Some(codes),
+            _ => None,
+        });
+        for (codes, source_map) in pushes.zip(&self.lambdas) {
+            if codes.as_slice() == lambda {
+                return Some(source_map);
+            }
+            if let Some(source_map) = source_map.lambda(codes, lambda) {
+                return Some(source_map);
+            }
+        }
+        None
+    }
+}
+
+// operators 是 body.to_operator() 的结果，每条指令对应生成它的最内层的表达式
+// 子表达式的指令在父表达式的指令中是连续的一段，按子表达式的顺序向后查找
+pub fn source_map(body: &FormulaBody, operators: &[OperatorCode]) -> SourceMap {
+    let mut builder = Builder::new(operators);
+    let mut cursor = 0;
+    for (range, statement) in &body.body {
+        let expression = &statement.expression.1;
+        if matches!(
+            expression,
+            ExpressionKind::TypeDefineKind(_, _) | ExpressionKind::FuncDefineKind(_, _)
+        ) {
+            continue;
+        }
+        let codes = expression.to_operator();
+        if let Some(start) = find(operators, cursor, operators.len(), &codes) {
+            builder.assign(expression, start, start + codes.len());
+            // 丢弃值的 Pop 属于整个语句
+            if let Some(OperatorCode::Pop) = operators.get(start + codes.len()) {
+                builder.ranges[start + codes.len()] = Some(range.clone());
+                cursor = start + codes.len() + 1;
+            } else {
+                cursor = start + codes.len();
+            }
+        }
+    }
+    builder.finish(&Range(0, 0))
+}
+
+struct Builder<'a> {
+    codes: &'a [OperatorCode],
+    ranges: Vec<Option<Range>>,
+    lambdas: Vec<Option<SourceMap>>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(codes: &'a [OperatorCode]) -> Self {
+        Self {
+            codes,
+            ranges: vec![None; codes.len()],
+            lambdas: vec![None; codes.len()],
+        }
+    }
+
+    // codes[start..end] 是 expression 的指令
+    fn assign(&mut self, expression: &ExpressionKind, start: usize, end: usize) {
+        for range in &mut self.ranges[start..end] {
+            *range = Some(expression.range().clone());
+        }
+        self.descend(expression, start, end);
+    }
+
+    fn descend(&mut self, expression: &ExpressionKind, start: usize, end: usize) {
+        let mut cursor = start;
+        for child in expression.children() {
+            let codes = child.to_operator();
+            if let Some(position) = find(self.codes, cursor, end, &codes) {
+                self.assign(child, position, position + codes.len());
+                cursor = position + codes.len();
+                continue;
+            }
+            // 含有 $ 的参数编译成 lambda
+            let lambda = OperatorCode::PushLambda(codes);
+            if let Some(position) = (cursor..end).find(|index| self.codes[*index] == lambda) {
+                if let OperatorCode::PushLambda(codes) = &self.codes[position] {
+                    let mut builder = Builder::new(codes);
+                    builder.assign(child, 0, codes.len());
+                    self.lambdas[position] = Some(builder.finish(child.range()));
+                }
+                self.ranges[position] = Some(child.range().clone());
+                cursor = position + 1;
+                continue;
+            }
+            // 访问链展开后子表达式的指令不连续，例如 a?.b.c 中的 a?.b，继续查找更内层的表达式
+            self.descend(child, cursor, end);
+        }
+    }
+
+    // 找不到来源的指令使用 range
+    fn finish(self, range: &Range) -> SourceMap {
+        let ranges = self
+            .ranges
+            .into_iter()
+            .map(|item| item.unwrap_or_else(|| range.clone()))
+            .collect::<Vec<_>>();
+
+        let lambdas = self
+            .codes
+            .iter()
+            .zip(self.lambdas)
+            .enumerate()
+            .filter_map(|(index, (code, lambda))| match code {
+                OperatorCode::PushLambda(codes) => {
+                    Some(lambda.unwrap_or_else(|| SourceMap::filled(codes, &ranges[index])))
+                }
+                _ => None,
+            })
+            .collect();
+        SourceMap { ranges, lambdas }
+    }
+}
+
+fn find(
+    codes: &[OperatorCode],
+    start: usize,
+    end: usize,
+    target: &[OperatorCode],
+) -> Option<usize> {
+    if target.is_empty() || end < start + target.len() {
+        return None;
+    }
+    (start..=end - target.len()).find(|index| codes[*index..*index + target.len()] == *target)
+}
@@ -3,12 +3,15 @@ use alloc::{
     vec,
     vec::Vec,
 };
+use num::Rational64;
 
 use super::ast::{
-    BinaryExpression, CallExpression, ExpressionKind, ExpressionStatement, FormulaBody, Identifier,
-    NumberLiteral, PropertyAccessExpression, StringLiteral, UnaryExpression,
+    ArrayLiteral, BinaryExpression, BoolLiteral, CallExpression, DateLiteral, ExpressionKind,
+    ExpressionStatement, FormulaBody, Identifier, IndexExpression, LetBinding, NullLiteral,
+    NumberLiteral, PropertyAccessExpression, SliceExpression, StringLiteral, TemplateLiteral,
+    TemplatePart, UnaryExpression,
 };
-use crate::share::operator::OperatorCode;
+use crate::{share::operator::OperatorCode, types::operator::FormulaOperator};
 
 pub trait ToOperator {
     fn to_operator(&self) -> Vec<OperatorCode>;
@@ -16,10 +19,31 @@ pub trait ToOperator {
 
 impl ToOperator for FormulaBody {
     fn to_operator(&self) -> Vec<OperatorCode> {
-        self.body
+        // 类型、函数定义只用于类型检查，最后一个表达式是公式的结果，前面的表达式的值被丢弃
+        let is_value = |statement: &ExpressionStatement| {
+            !matches!(
+                statement.expression.1,
+                ExpressionKind::TypeDefineKind(_, _)
+                    | ExpressionKind::FuncDefineKind(_, _)
+                    | ExpressionKind::LetBindingKind(_, _)
+            )
+        };
+        let last = self
+            .body
             .iter()
-            .flat_map(|expr| expr.1.to_operator())
-            .collect::<Vec<_>>()
+            .rposition(|(_, statement)| is_value(statement));
+
+        let mut result = Vec::new();
+        for (index, (_, statement)) in self.body.iter().enumerate() {
+            match &statement.expression.1 {
+                ExpressionKind::TypeDefineKind(_, _) | ExpressionKind::FuncDefineKind(_, _) => {}
+                _ => result.extend(statement.to_operator()),
+            }
+            if is_value(statement) && Some(index) != last {
+                result.push(OperatorCode::Pop);
+            }
+        }
+        result
     }
 }
 
@@ -32,64 +56,88 @@ impl ToOperator for ExpressionStatement {
 impl ToOperator for UnaryExpression {
     fn to_operator(&self) -> Vec<OperatorCode> {
         let mut result = self.argument.1.to_operator();
-        result.push(OperatorCode::Factorial);
+        match (self.prefix, self.operator.1.as_str()) {
+            (true, "-") => result.push(OperatorCode::Negate),
+            // 取正不改变值，类型检查保证参数是数字或时间间隔
+            (true, _) => {}
+            (false, _) => result.push(OperatorCode::Factorial),
+        }
         result
     }
 }
 
 impl ToOperator for BinaryExpression {
     fn to_operator(&self) -> Vec<OperatorCode> {
-        let op = self.operator.1.as_str();
-        if ["==", "=", "!=", "<>", ">=", "<=", ">", "<"].contains(&op) {
-            match (&*self.left.1, &*self.right.1) {
-                (
-                    ExpressionKind::IdentifierKind(_, left),
-                    ExpressionKind::StringLiteralKind(_, right),
-                ) => {
-                    vec![OperatorCode::FilterExpression(
-                        String::from(left.name.clone()),
-                        String::from(op),
-                        String::from(right.raw.clone()),
-                    )]
-                }
-                (
-                    ExpressionKind::IdentifierKind(_, left),
-                    ExpressionKind::IdentifierKind(_, right),
-                ) => {
-                    vec![OperatorCode::FilterExpression(
-                        String::from(left.name.clone()),
-                        String::from(op),
-                        String::from(right.name.clone()),
-                    )]
-                }
-                (
-                    ExpressionKind::IdentifierKind(_, left),
-                    ExpressionKind::NumberLiteralKind(_, right),
-                ) => {
-                    vec![OperatorCode::FilterExpression(
-                        String::from(left.name.clone()),
-                        String::from(op),
-                        right.value.clone().to_string(),
-                    )]
-                }
-                _ => {
-                    vec![]
-                }
-            }
-        } else {
-            let mut result = self.left.1.to_operator();
-            result.extend(self.right.1.to_operator());
-            result.push(match op {
-                "+" => OperatorCode::Add,
-                "-" => OperatorCode::Subtract,
-                "*" => OperatorCode::Multiply,
-                "/" => OperatorCode::Divide,
-                "%" => OperatorCode::Modulo,
-                "^" => OperatorCode::Power,
-                _ => unreachable!("unknown operator"),
-            });
-            result
+        let mut result = self.left.1.to_operator();
+
+        // 右边只在左边为 Null 时执行
+        if FormulaOperator::from_raw(self.operator.1.as_str()) == Some(FormulaOperator::Coalesce) {
+            let right = self.right.1.to_operator();
+            result.push(OperatorCode::Coalesce(right.len() as u32));
+            result.extend(right);
+            return result;
+        }
+
+        result.extend(self.right.1.to_operator());
+        result.push(match FormulaOperator::from_raw(self.operator.1.as_str()) {
+            Some(FormulaOperator::Add) => OperatorCode::Add,
+            Some(FormulaOperator::Sub) => OperatorCode::Subtract,
+            Some(FormulaOperator::Mul) => OperatorCode::Multiply,
+            Some(FormulaOperator::Div) => OperatorCode::Divide,
+            Some(FormulaOperator::Modulo) => OperatorCode::Modulo,
+            Some(FormulaOperator::Pow) => OperatorCode::Power,
+            Some(FormulaOperator::IntDiv) => OperatorCode::IntDivide,
+            Some(FormulaOperator::ShiftLeft) => OperatorCode::ShiftLeft,
+            Some(FormulaOperator::ShiftRight) => OperatorCode::ShiftRight,
+            Some(FormulaOperator::BitAnd) => OperatorCode::BitAnd,
+            Some(FormulaOperator::BitOr) => OperatorCode::BitOr,
+            Some(FormulaOperator::BitXor) => OperatorCode::BitXor,
+            Some(FormulaOperator::Eq) => OperatorCode::Equal,
+            Some(FormulaOperator::Ne) => OperatorCode::NotEqual,
+            Some(FormulaOperator::Gt) => OperatorCode::GreaterThan,
+            Some(FormulaOperator::Ge) => OperatorCode::GreaterThanOrEqual,
+            Some(FormulaOperator::Lt) => OperatorCode::LessThan,
+            Some(FormulaOperator::Le) => OperatorCode::LessThanOrEqual,
+            _ => unreachable!("unknown operator"),
+        });
+        result
+    }
+}
+
+// 旧的过滤写法 SUM(subtask.estimatePoint; status=2) 中的 status=2
+fn legacy_filter(expr: &ExpressionKind) -> Option<OperatorCode> {
+    let binary = match expr {
+        ExpressionKind::BinaryExpressionKind(_, binary) => binary,
+        _ => return None,
+    };
+    let op = binary.operator.1.as_str();
+    if !["==", "=", "!=", "<>", ">=", "<=", ">", "<"].contains(&op) {
+        return None;
+    }
+
+    match (&*binary.left.1, &*binary.right.1) {
+        (ExpressionKind::IdentifierKind(_, left), ExpressionKind::StringLiteralKind(_, right)) => {
+            Some(OperatorCode::FilterExpression(
+                String::from(left.name.clone()),
+                String::from(op),
+                String::from(right.raw.clone()),
+            ))
         }
+        (ExpressionKind::IdentifierKind(_, left), ExpressionKind::IdentifierKind(_, right)) => {
+            Some(OperatorCode::FilterExpression(
+                String::from(left.name.clone()),
+                String::from(op),
+                String::from(right.name.clone()),
+            ))
+        }
+        (ExpressionKind::IdentifierKind(_, left), ExpressionKind::NumberLiteralKind(_, right)) => {
+            Some(OperatorCode::FilterExpression(
+                String::from(left.name.clone()),
+                String::from(op),
+                right.value.clone().to_string(),
+            ))
+        }
+        _ => None,
     }
 }
 
@@ -99,6 +147,27 @@ impl ToOperator for NumberLiteral {
     }
 }
 
+impl ToOperator for BoolLiteral {
+    fn to_operator(&self) -> Vec<OperatorCode> {
+        vec![OperatorCode::PushBool(self.value)]
+    }
+}
+
+impl ToOperator for NullLiteral {
+    fn to_operator(&self) -> Vec<OperatorCode> {
+        vec![OperatorCode::PushNull]
+    }
+}
+
+// 和 Issue 中的 createTime、GET_NOW 一样是毫秒时间戳数字，可以直接比较和相减
+impl ToOperator for DateLiteral {
+    fn to_operator(&self) -> Vec<OperatorCode> {
+        vec![OperatorCode::PushNumber(Rational64::from_integer(
+            self.value as i64,
+        ))]
+    }
+}
+
 impl ToOperator for Identifier {
     fn to_operator(&self) -> Vec<OperatorCode> {
         vec![OperatorCode::LoadIdentifier(self.name.clone())]
@@ -113,13 +182,36 @@ impl ToOperator for StringLiteral {
 
 impl ToOperator for CallExpression {
     fn to_operator(&self) -> Vec<OperatorCode> {
-        // TODO: 为了实现奇怪的过滤方式，这里受到的影响最大，需要重构
+        // date('2024-01-31') 在编译时计算
+        if let Some(value) = self.date_literal() {
+            return vec![OperatorCode::PushNumber(Rational64::from_integer(
+                value as i64,
+            ))];
+        }
+
         let mut result = self.callee.1.to_operator();
 
+        // 没有 status=2 这种过滤条件时按源码顺序生成，下面的排序会打乱 ?? 和 ?. 的跳转
+        if !self.is_legacy() {
+            // 第一个参数之后含有 $ 的参数是 lambda，由函数对每个元素执行
+            for (index, (_, arg)) in self.arguments.iter().enumerate() {
+                match index > 0 && arg.is_lambda() {
+                    true => result.push(OperatorCode::PushLambda(arg.to_operator())),
+                    false => result.extend(arg.to_operator()),
+                }
+            }
+            result.push(OperatorCode::Call(self.arguments.len() as u8));
+            return result;
+        }
+
+        // TODO: 为了实现奇怪的过滤方式，这里受到的影响最大，需要重构
         let mut args = self
             .arguments
             .iter()
-            .flat_map(|arg| arg.1.to_operator())
+            .flat_map(|arg| match legacy_filter(&arg.1) {
+                Some(filter) => vec![filter],
+                None => arg.1.to_operator(),
+            })
             .collect::<Vec<_>>();
 
         // Order: LoadIdentifier > FilterExpression > LoadPropertyAccess > Call
@@ -143,29 +235,158 @@ impl ToOperator for CallExpression {
         });
 
         result.extend(args);
+        result.push(OperatorCode::Call((self.arguments.len() - 1) as u8));
+        result
+    }
+}
 
-        let has_filter = result.iter().any(|arg| {
-            if let OperatorCode::FilterExpression(_, _, _) = arg {
-                return true;
-            }
-            false
-        });
+// 访问链中的一环，例如 a?.b.c[0] 中的 ?.b、.c、[0]
+trait ChainLink {
+    fn object(&self) -> &ExpressionKind;
+    fn optional(&self) -> bool {
+        false
+    }
+    // 不包括 object 的指令
+    fn link_operator(&self) -> Vec<OperatorCode>;
+}
 
-        if has_filter {
-            result.push(OperatorCode::Call((self.arguments.len() - 1) as u8));
-        } else {
-            result.push(OperatorCode::Call(self.arguments.len() as u8));
+fn as_link(expr: &ExpressionKind) -> Option<&dyn ChainLink> {
+    match expr {
+        ExpressionKind::PropertyAccessExpressionKind(_, dot) => Some(dot),
+        ExpressionKind::IndexExpressionKind(_, index) => Some(index),
+        ExpressionKind::SliceExpressionKind(_, slice) => Some(slice),
+        _ => None,
+    }
+}
+
+// 展开整个访问链，?. 处的对象为 Null 时跳到链的末尾，后面的访问都不再执行
+fn chain_to_operator(link: &dyn ChainLink) -> Vec<OperatorCode> {
+    let mut links = vec![link];
+    let mut root = link.object();
+    while let Some(link) = as_link(root) {
+        links.push(link);
+        root = link.object();
+    }
+
+    let mut result = root.to_operator();
+    let mut skips = Vec::new();
+    for link in links.iter().rev() {
+        if link.optional() {
+            skips.push(result.len());
+            result.push(OperatorCode::SkipIfNull(0));
+        }
+        result.extend(link.link_operator());
+    }
+
+    let end = result.len();
+    for skip in skips {
+        result[skip] = OperatorCode::SkipIfNull((end - skip - 1) as u32);
+    }
+    result
+}
+
+impl ChainLink for PropertyAccessExpression {
+    fn object(&self) -> &ExpressionKind {
+        &self.object.1
+    }
+
+    fn optional(&self) -> bool {
+        self.optional
+    }
+
+    fn link_operator(&self) -> Vec<OperatorCode> {
+        vec![OperatorCode::LoadPropertyAccess(
+            self.property.1.name.clone(),
+        )]
+    }
+}
+
+impl ChainLink for IndexExpression {
+    fn object(&self) -> &ExpressionKind {
+        &self.object.1
+    }
+
+    fn link_operator(&self) -> Vec<OperatorCode> {
+        let mut result = self.index.1.to_operator();
+        result.push(OperatorCode::Index);
+        result
+    }
+}
+
+impl ChainLink for SliceExpression {
+    fn object(&self) -> &ExpressionKind {
+        &self.object.1
+    }
+
+    fn link_operator(&self) -> Vec<OperatorCode> {
+        let mut result = Vec::new();
+        for bound in self.start.iter().chain(self.end.iter()) {
+            result.extend(bound.1.to_operator());
         }
+        result.push(OperatorCode::Slice(
+            self.start.is_some(),
+            self.end.is_some(),
+        ));
         result
     }
 }
 
 impl ToOperator for PropertyAccessExpression {
     fn to_operator(&self) -> Vec<OperatorCode> {
-        let mut result = self.object.1.to_operator();
-        result.push(OperatorCode::LoadPropertyAccess(
-            self.property.1.name.clone(),
-        ));
+        chain_to_operator(self)
+    }
+}
+
+impl ToOperator for IndexExpression {
+    fn to_operator(&self) -> Vec<OperatorCode> {
+        chain_to_operator(self)
+    }
+}
+
+impl ToOperator for SliceExpression {
+    fn to_operator(&self) -> Vec<OperatorCode> {
+        chain_to_operator(self)
+    }
+}
+
+impl ToOperator for ArrayLiteral {
+    fn to_operator(&self) -> Vec<OperatorCode> {
+        let mut result = self
+            .elements
+            .iter()
+            .flat_map(|element| element.1.to_operator())
+            .collect::<Vec<_>>();
+        result.push(OperatorCode::MakeArray(self.elements.len() as u32));
+        result
+    }
+}
+
+impl ToOperator for LetBinding {
+    fn to_operator(&self) -> Vec<OperatorCode> {
+        let mut result = self.value.1.to_operator();
+        result.push(OperatorCode::StoreLocal(self.ident.1.name.clone()));
+        result
+    }
+}
+
+impl ToOperator for TemplateLiteral {
+    fn to_operator(&self) -> Vec<OperatorCode> {
+        // 没有 ${} 的模板字符串就是普通的字符串
+        match self.parts.as_slice() {
+            [] => return vec![OperatorCode::PushString(String::new())],
+            [TemplatePart::Text(_, text)] => return text.to_operator(),
+            _ => {}
+        }
+
+        let mut result = self
+            .parts
+            .iter()
+            .flat_map(|part| match part {
+                TemplatePart::Text(_, text) => text.to_operator(),
+                TemplatePart::Expression(_, expr) => expr.to_operator(),
+            })
+            .collect::<Vec<_>>();
+        result.push(OperatorCode::Concat(self.parts.len() as u32));
         result
     }
 }
@@ -185,9 +406,17 @@ impl ToOperator for ExpressionKind {
             // }
             ExpressionKind::StringLiteralKind(_, string_literal) => string_literal.to_operator(),
             ExpressionKind::NumberLiteralKind(_, number_literal) => number_literal.to_operator(),
+            ExpressionKind::BoolLiteralKind(_, bool_literal) => bool_literal.to_operator(),
+            ExpressionKind::NullLiteralKind(_, null_literal) => null_literal.to_operator(),
+            ExpressionKind::DateLiteralKind(_, date_literal) => date_literal.to_operator(),
             ExpressionKind::IdentifierKind(_, identifier) => identifier.to_operator(),
             // ExpressionKind::TypeDefineKind(_, type_define) => type_define.to_operator(),
             ExpressionKind::PropertyAccessExpressionKind(_, dot) => dot.to_operator(),
+            ExpressionKind::IndexExpressionKind(_, index) => index.to_operator(),
+            ExpressionKind::SliceExpressionKind(_, slice) => slice.to_operator(),
+            ExpressionKind::ArrayLiteralKind(_, array) => array.to_operator(),
+            ExpressionKind::TemplateLiteralKind(_, template) => template.to_operator(),
+            ExpressionKind::LetBindingKind(_, binding) => binding.to_operator(),
             _ => todo!("not implemented"),
         }
     }
@@ -19,6 +19,13 @@ pub struct TypeDefine {
     pub type_item: (Range, TypeItemKind),
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct FuncDefine {
+    pub ident: (Range, Identifier),
+    pub arguments: Vec<(Range, TypeItemKind)>,
+    pub return_type: (Range, TypeItemKind),
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum TypeItemKind {
     NamedTypeKind(NamedType),   // 具名类型
@@ -202,3 +209,52 @@ pub fn type_def_to_ast(pair: Pair<Rule>) -> ExpressionAstItem {
         ),
     )
 }
+
+pub fn func_def_to_ast(pair: Pair<Rule>) -> ExpressionAstItem {
+    let mut pairs = pair.clone().into_inner();
+
+    match pairs.next() {
+        Some(kw) => {
+            if kw.as_str() != "func" {
+                panic!(
+                    "func_def_to_ast: keyword error, expected 'func', got '{}'",
+                    kw.as_str()
+                );
+            }
+        }
+        None => panic!("func_def_to_ast: no keyword"),
+    }
+
+    let identifier = match pairs.next() {
+        Some(ident) if ident.as_rule() == Rule::identifier => (
+            ident.clone().into(),
+            Identifier {
+                name: ident.as_str().to_string(),
+            },
+        ),
+        _ => panic!("func_def_to_ast: no identifier"),
+    };
+
+    let mut arguments = vec![];
+    let mut return_type = None;
+    for item in pairs {
+        let type_item = item.clone().into_inner().next().unwrap().into_inner();
+        match item.as_rule() {
+            Rule::func_def_argument => arguments.push(type_item_to_ast(type_item)),
+            Rule::func_def_return => return_type = Some(type_item_to_ast(type_item)),
+            _ => unreachable!(),
+        }
+    }
+
+    ExpressionAstItem(
+        pair.clone().into(),
+        ExpressionKind::FuncDefineKind(
+            pair.clone().into(),
+            FuncDefine {
+                ident: identifier,
+                arguments,
+                return_type: return_type.expect("func_def_to_ast: no return type"),
+            },
+        ),
+    )
+}
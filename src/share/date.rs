@@ -0,0 +1,73 @@
+// 解析 ISO 8601 格式的日期时间，按 UTC 计算，返回毫秒时间戳
+// 支持 2024-01-31、2024-01-31T08:30、2024-01-31T08:30:00，末尾可以带 Z，不支持 1970 年以前的日期
+pub fn parse_date(text: &str) -> Option<u64> {
+    let text = text.strip_suffix('Z').unwrap_or(text);
+    let (date, time) = match text.find(['T', ' ']) {
+        Some(at) => (&text[..at], Some(&text[at + 1..])),
+        None => (text, None),
+    };
+
+    let mut parts = date.split('-');
+    let year = digits(parts.next()?, 4)?;
+    let month = digits(parts.next()?, 2)?;
+    let day = digits(parts.next()?, 2)?;
+    if parts.next().is_some()
+        || year < 1970
+        || !(1..=12).contains(&month)
+        || day < 1
+        || day > days_in_month(year, month)
+    {
+        return None;
+    }
+
+    let seconds = match time {
+        Some(time) => {
+            let mut parts = time.split(':');
+            let hour = digits(parts.next()?, 2)?;
+            let minute = digits(parts.next()?, 2)?;
+            let second = match parts.next() {
+                Some(second) => digits(second, 2)?,
+                None => 0,
+            };
+            if parts.next().is_some() || hour > 23 || minute > 59 || second > 59 {
+                return None;
+            }
+            hour * 3600 + minute * 60 + second
+        }
+        None => 0,
+    };
+
+    Some((days_from_epoch(year, month, day) * 86_400 + seconds) * 1000)
+}
+
+// 固定位数的数字，例如月份必须写成 01
+fn digits(text: &str, len: usize) -> Option<u64> {
+    match text.len() == len && text.bytes().all(|b| b.is_ascii_digit()) {
+        true => text.parse().ok(),
+        false => None,
+    }
+}
+
+fn is_leap_year(year: u64) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// 1970-01-01 到指定日期的天数
+fn days_from_epoch(year: u64, month: u64, day: u64) -> u64 {
+    let leap_days = |year: u64| year / 4 - year / 100 + year / 400;
+    let years = year - 1970;
+    let mut days = years * 365 + leap_days(year - 1) - leap_days(1969);
+    for month in 1..month {
+        days += days_in_month(year, month);
+    }
+    days + day - 1
+}
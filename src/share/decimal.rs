@@ -0,0 +1,78 @@
+use alloc::string::String;
+use num::{pow, BigInt, BigRational, Rational64, ToPrimitive, Zero};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecimalError {
+    Invalid,    // 不是十进制数字
+    OutOfRange, // 约分后分子或者分母超出 i64
+}
+
+// 十进制文本精确转换成分数，支持小数和科学计数法，例如 0.1 是 1/10，1.5e-3 是 3/2000
+pub fn parse_decimal(text: &str) -> Result<Rational64, DecimalError> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(index) => (&text[..index], Some(&text[index + 1..])),
+        None => (text, None),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let is_digits = |text: &str| text.bytes().all(|c| c.is_ascii_digit());
+    if integer.is_empty() && fraction.is_empty() || !is_digits(integer) || !is_digits(fraction) {
+        return Err(DecimalError::Invalid);
+    }
+
+    let digits = String::from(integer) + fraction;
+    let digits = digits.trim_start_matches('0');
+    if digits.is_empty() {
+        return match exponent.map(parse_exponent) {
+            Some(Err(DecimalError::Invalid)) => Err(DecimalError::Invalid),
+            _ => Ok(Rational64::zero()),
+        };
+    }
+    let significant = digits.trim_end_matches('0');
+    let exponent = match exponent {
+        Some(exponent) => parse_exponent(exponent)?,
+        None => 0,
+    } - fraction.len() as i64
+        + (digits.len() - significant.len()) as i64;
+
+    // 超过 40 位有效数字或者指数太大时，约分后的分子或者分母一定超出 i64，不再计算
+    if significant.len() > 40 || exponent.abs() > 60 {
+        return Err(DecimalError::OutOfRange);
+    }
+    let numer = significant
+        .parse::<BigInt>()
+        .map_err(|_| DecimalError::Invalid)?;
+    let scale = pow(BigInt::from(10), exponent.unsigned_abs() as usize);
+    let value = match exponent < 0 {
+        true => BigRational::new(numer, scale),
+        false => BigRational::from_integer(numer * scale),
+    };
+    let value = match negative {
+        true => -value,
+        false => value,
+    };
+    from_big(&value).ok_or(DecimalError::OutOfRange)
+}
+
+// 指数的数字太多时按超出范围处理
+fn parse_exponent(text: &str) -> Result<i64, DecimalError> {
+    let digits = text
+        .strip_prefix('-')
+        .or_else(|| text.strip_prefix('+'))
+        .unwrap_or(text);
+    if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(DecimalError::Invalid);
+    }
+    text.parse().map_err(|_| DecimalError::OutOfRange)
+}
+
+// 约分后的 BigRational 放回 Rational64，超出 i64 时返回 None
+pub fn from_big(value: &BigRational) -> Option<Rational64> {
+    Some(Rational64::new_raw(
+        value.numer().to_i64()?,
+        value.denom().to_i64()?,
+    ))
+}
@@ -0,0 +1,241 @@
+use alloc::{format, string::String, vec::Vec};
+
+// 内置函数的签名和文档，类型检查、编辑器补全、悬停提示、参数提示都从这里读取
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionParameter {
+    pub name: &'static str,
+    pub type_: &'static str,
+    pub doc: &'static str,
+    pub doc_zh: &'static str,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionInfo {
+    pub name: &'static str,
+    pub parameters: &'static [FunctionParameter],
+    pub variadic: bool, // 最后一个参数可以重复出现
+    pub return_type: &'static str,
+    pub doc: &'static str,
+    pub doc_zh: &'static str,
+}
+
+pub static FUNCTIONS: &[FunctionInfo] = &[
+    FunctionInfo {
+        name: "SUM",
+        parameters: &[FunctionParameter {
+            name: "values",
+            type_: "Number | Number[]",
+            doc: "numbers to add up, or an array such as `subtask.estimatePoint`",
+            doc_zh: "要相加的数字，或者数组，例如 `subtask.estimatePoint`",
+        }],
+        variadic: true,
+        return_type: "Number",
+        doc: "Returns the sum of the numbers, empty values count as 0.",
+        doc_zh: "返回所有数字的和，空值按 0 计算。",
+    },
+    FunctionInfo {
+        name: "COUNT",
+        parameters: &[FunctionParameter {
+            name: "values",
+            type_: "Array | Number",
+            doc: "an array such as `subtask`, or numbers to count",
+            doc_zh: "数组，例如 `subtask`，或者要计数的数字",
+        }],
+        variadic: true,
+        return_type: "Number",
+        doc: "Returns the number of elements in the array, or the number of arguments.",
+        doc_zh: "返回数组中元素的个数，或者参数的个数。",
+    },
+    FunctionInfo {
+        name: "sum",
+        parameters: &[
+            FunctionParameter {
+                name: "values",
+                type_: "Array | Number",
+                doc: "an array such as `subtask`, or numbers to add up",
+                doc_zh: "数组，例如 `subtask`，或者要相加的数字",
+            },
+            FunctionParameter {
+                name: "selector",
+                type_: "Lambda | Number",
+                doc: "the value to add up for each element, e.g. `$.estimatePoint`",
+                doc_zh: "每个元素要相加的值，例如 `$.estimatePoint`",
+            },
+        ],
+        variadic: true,
+        return_type: "Number",
+        doc: "Returns the sum of `selector` over the array, e.g. `sum(subtask, $.estimatePoint)`. Empty values count as 0.",
+        doc_zh: "对数组中每个元素的 `selector` 求和，例如 `sum(subtask, $.estimatePoint)`，空值按 0 计算。",
+    },
+    FunctionInfo {
+        name: "count",
+        parameters: &[FunctionParameter {
+            name: "values",
+            type_: "Array",
+            doc: "an array such as `subtask` or `where(subtask, $.status == 2)`",
+            doc_zh: "数组，例如 `subtask` 或者 `where(subtask, $.status == 2)`",
+        }],
+        variadic: false,
+        return_type: "Number",
+        doc: "Returns the number of elements in the array.",
+        doc_zh: "返回数组中元素的个数。",
+    },
+    FunctionInfo {
+        name: "where",
+        parameters: &[
+            FunctionParameter {
+                name: "values",
+                type_: "Array",
+                doc: "the array to filter",
+                doc_zh: "要过滤的数组",
+            },
+            FunctionParameter {
+                name: "predicate",
+                type_: "Lambda",
+                doc: "the condition each kept element satisfies, e.g. `$.status == 2`",
+                doc_zh: "保留的元素需要满足的条件，例如 `$.status == 2`",
+            },
+        ],
+        variadic: false,
+        return_type: "Array",
+        doc: "Returns the elements for which `predicate` is true.",
+        doc_zh: "返回满足 `predicate` 条件的元素。",
+    },
+    FunctionInfo {
+        name: "in",
+        parameters: &[
+            FunctionParameter {
+                name: "value",
+                type_: "Any",
+                doc: "the value to look for, e.g. `status`",
+                doc_zh: "要查找的值，例如 `status`",
+            },
+            FunctionParameter {
+                name: "values",
+                type_: "Array",
+                doc: "the candidates, e.g. `[1, 2, 4]`",
+                doc_zh: "候选值，例如 `[1, 2, 4]`",
+            },
+        ],
+        variadic: false,
+        return_type: "Bool",
+        doc: "Returns true if `values` contains `value`, e.g. `in(status, [1, 2, 4])`. An empty value is never contained.",
+        doc_zh: "`values` 中包含 `value` 时返回 true，例如 `in(status, [1, 2, 4])`，空值不属于任何数组。",
+    },
+    FunctionInfo {
+        name: "date",
+        parameters: &[FunctionParameter {
+            name: "text",
+            type_: "String",
+            doc: "an ISO 8601 date such as `'2024-01-31'` or `'2024-01-31T08:30:00'`, in UTC",
+            doc_zh: "ISO 8601 格式的日期，例如 `'2024-01-31'` 或 `'2024-01-31T08:30:00'`，按 UTC 计算",
+        }],
+        variadic: false,
+        return_type: "DateTime",
+        doc: "Converts the text to a date, same as the literal `#2024-01-31#`. A literal argument is checked when the formula is compiled.",
+        doc_zh: "把文本转换成日期，和字面量 `#2024-01-31#` 相同。参数是字面量时在编译时检查。",
+    },
+    FunctionInfo {
+        name: "length",
+        parameters: &[FunctionParameter {
+            name: "text",
+            type_: "String",
+            doc: "the text to measure, e.g. `title`",
+            doc_zh: "要计算长度的文本，例如 `title`",
+        }],
+        variadic: false,
+        return_type: "Number",
+        doc: "Returns the number of characters in the text, `length('需求')` is 2.",
+        doc_zh: "返回文本中的字符个数，`length('需求')` 是 2。",
+    },
+    FunctionInfo {
+        name: "substring",
+        parameters: &[
+            FunctionParameter {
+                name: "text",
+                type_: "String",
+                doc: "the text to take characters from",
+                doc_zh: "要截取的文本",
+            },
+            FunctionParameter {
+                name: "start",
+                type_: "Number",
+                doc: "index of the first character, negative numbers count from the end",
+                doc_zh: "第一个字符的下标，负数从末尾开始",
+            },
+            FunctionParameter {
+                name: "end",
+                type_: "Number",
+                doc: "index after the last character, can be omitted to take the rest",
+                doc_zh: "最后一个字符之后的下标，省略时截取到末尾",
+            },
+        ],
+        variadic: false,
+        return_type: "String",
+        doc: "Returns the characters from `start` up to `end`, e.g. `substring('需求文档', 0, 2)` is `'需求'`.",
+        doc_zh: "返回从 `start` 到 `end` 之间的字符，例如 `substring('需求文档', 0, 2)` 是 `'需求'`。",
+    },
+    FunctionInfo {
+        name: "upper",
+        parameters: &[FunctionParameter {
+            name: "text",
+            type_: "String",
+            doc: "the text to convert",
+            doc_zh: "要转换的文本",
+        }],
+        variadic: false,
+        return_type: "String",
+        doc: "Returns the text in upper case.",
+        doc_zh: "返回转换成大写的文本。",
+    },
+    FunctionInfo {
+        name: "lower",
+        parameters: &[FunctionParameter {
+            name: "text",
+            type_: "String",
+            doc: "the text to convert",
+            doc_zh: "要转换的文本",
+        }],
+        variadic: false,
+        return_type: "String",
+        doc: "Returns the text in lower case.",
+        doc_zh: "返回转换成小写的文本。",
+    },
+];
+
+pub fn find_function(name: &str) -> Option<&'static FunctionInfo> {
+    FUNCTIONS.iter().find(|function| function.name == name)
+}
+
+impl FunctionInfo {
+    // SUM(values: Number | Number[], ...): Number
+    pub fn signature(&self) -> String {
+        format!(
+            "{}({}): {}",
+            self.name,
+            self.parameter_labels().join(", "),
+            self.return_type
+        )
+    }
+
+    pub fn parameter_labels(&self) -> Vec<String> {
+        let mut labels = self
+            .parameters
+            .iter()
+            .map(|parameter| format!("{}: {}", parameter.name, parameter.type_))
+            .collect::<Vec<_>>();
+        if self.variadic {
+            labels.push(String::from("..."));
+        }
+        labels
+    }
+
+    // 第 index 个实参对应的形参，可变参数都对应最后一个形参
+    pub fn parameter_at(&self, index: usize) -> Option<&'static FunctionParameter> {
+        match self.parameters.get(index) {
+            Some(parameter) => Some(parameter),
+            None if self.variadic => self.parameters.last(),
+            None => None,
+        }
+    }
+}
@@ -1 +1,6 @@
+pub mod date;
+pub mod function;
 pub mod operator;
+pub mod program;
+pub mod string;
+pub mod decimal;
@@ -1,7 +1,10 @@
-use alloc::string::String;
+use alloc::{string::String, vec::Vec};
 use num::Rational64;
 use serde::{Deserialize, Serialize};
 
+use crate::vm::error::ExecuteErrorType;
+
+// 编译结果用 bincode 序列化后保存，bincode 按变体的顺序编码，新的指令只能加在最后
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum OperatorCode {
     // Math
@@ -24,4 +27,50 @@ pub enum OperatorCode {
     // func
     Call(u8),
     FilterExpression(String, String, String), // 因为现在还没做栈帧，不好做循环，所以先用这个来实现 filter
+
+    // 比较，结果为 Bool
+    Equal,
+    NotEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    LessThan,
+    LessThanOrEqual,
+
+    PushLambda(Vec<OperatorCode>), // 含有 $ 的参数，例如 where(subtask, $.status == 2) 中的 $.status == 2，由函数对每个元素执行
+
+    // 数组
+    MakeArray(u32),    // 弹出 n 个元素组成数组，例如 [1, 2, 4]
+    Index,             // 下标访问，负数从末尾开始，越界得到 Null
+    Slice(bool, bool), // 切片，两个值分别表示是否有 start、end
+
+    // 跳转，由 Runner 修改执行位置
+    SkipIfNull(u32), // ?. 访问，栈顶为 Null 时跳过后面 n 条指令，保留栈顶的 Null 作为整个访问链的结果
+    Coalesce(u32),   // ??，栈顶不是 Null 时跳过右边的 n 条指令，否则弹出 Null 继续执行右边
+
+    Negate, // 取负
+
+    // 整数运算，操作数必须是整数
+    IntDivide, // 整除，向 0 取整
+    ShiftLeft,
+    ShiftRight,
+    BitAnd,
+    BitOr,
+    BitXor,
+
+    PushBool(bool),
+    PushNull,
+
+    Concat(u32), // 模板字符串，弹出 n 个值转换成文本后拼接，Null 转换成空字符串
+
+    StoreLocal(String), // let 绑定，弹出栈顶的值保存到局部作用域，LoadIdentifier 优先读取局部作用域
+    Pop,                // 丢弃不是结果的表达式语句的值
+
+    // Program 把上面携带字符串的指令换成常量池、槽位的下标，见 share::program
+    PushConstant(u32),     // 常量池中的数字或字符串
+    LoadSlot(u32),         // 标识符的槽位，执行前按名字从 heap 中取值
+    StoreSlot(u32),        // let 绑定，写入槽位
+    LoadProperty(u32),     // 属性名在常量池中的下标，含义同 LoadPropertyAccess
+    Filter(u32, u32, u32), // 三个字符串在常量池中的下标，含义同 FilterExpression
+
+    Throw(ExecuteErrorType, Option<String>), // 优化时折叠出错的常量表达式，例如 1 / 0，执行到这里才报错
 }
@@ -0,0 +1,266 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use hashbrown::HashMap;
+use num::Rational64;
+use serde::{Deserialize, Serialize};
+
+use super::operator::OperatorCode;
+use crate::{
+    parse::{
+        ast::FormulaBody,
+        dependencies::get_dependencies,
+        source_map::{source_map, SourceMap},
+        to_operator::ToOperator,
+    },
+    types::types::FormulaValueType,
+    vm::{
+        error::{ExecuteError, ExecuteErrorType},
+        optimizer::optimize_with_source_map,
+    },
+};
+
+// 保存的字节码格式：MAGIC | 格式版本 u16 | 校验和 u32 | bincode 编码的 Program，整数都是小端
+// 没有 MAGIC 的是第一版直接保存的 bincode(Vec<OperatorCode>)，当作版本 0 读取
+// 版本 1 使用常量池和槽位的下标，并记录指令的源码位置
+pub const MAGIC: [u8; 4] = *b"FRML";
+pub const FORMAT_VERSION: u16 = 1;
+pub const COMPILER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const HEADER_LEN: usize = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Constant {
+    Number(Rational64),
+    String(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Program {
+    pub compiler_version: String,  // 编译时的引擎版本，版本 0 升级上来的为空
+    pub constants: Vec<Constant>, // PushConstant、LoadProperty、Filter 引用的常量，相同的只保存一次
+    pub slots: Vec<String>,       // LoadSlot、StoreSlot 引用的标识符，包括字段、函数和 let 绑定
+    pub dependencies: Vec<String>, // 执行前需要从 Issue 中读取的字段
+    pub result_type: Option<FormulaValueType>, // 编译时有 schema 才能推断
+    pub operators: Vec<OperatorCode>,
+    pub source_map: SourceMap, // 每条指令的源码位置，旧版本升级上来的为空
+}
+
+// 编译选项，调试时可以关闭优化，对照编译器直接生成的指令
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompileOptions {
+    pub optimize: bool, // 常量折叠、恒等式化简，见 vm::optimizer
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self { optimize: true }
+    }
+}
+
+// 生成 Program 时常量和标识符到下标的映射，相同的只保存一次
+#[derive(Default)]
+struct Interner {
+    constants: HashMap<Constant, u32>,
+    slots: HashMap<String, u32>,
+}
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum LoadErrorType {
+    UnsupportedVersion, // 比当前引擎新的格式
+    ChecksumMismatch,   // 数据被截断或者损坏
+    DecodeError,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadError {
+    pub type_: LoadErrorType,
+    pub message: Option<String>,
+}
+
+impl LoadError {
+    pub fn new(type_: LoadErrorType) -> Self {
+        Self {
+            type_,
+            message: None,
+        }
+    }
+
+    pub fn with_message(self, msg: String) -> Self {
+        Self {
+            type_: self.type_,
+            message: Some(msg),
+        }
+    }
+
+    pub fn unsupported_version(version: u16) -> Self {
+        Self::new(LoadErrorType::UnsupportedVersion).with_message(format!(
+            "format version {} is newer than {}",
+            version, FORMAT_VERSION
+        ))
+    }
+
+    pub fn checksum_mismatch() -> Self {
+        Self::new(LoadErrorType::ChecksumMismatch)
+    }
+
+    pub fn decode_error(msg: String) -> Self {
+        Self::new(LoadErrorType::DecodeError).with_message(msg)
+    }
+}
+
+impl Program {
+    // operators 是编译器生成的携带字符串的指令
+    pub fn new(operators: Vec<OperatorCode>, result_type: Option<FormulaValueType>) -> Self {
+        let mut program = Program {
+            compiler_version: COMPILER_VERSION.to_string(),
+            constants: Vec::new(),
+            slots: Vec::new(),
+            dependencies: get_dependencies(&operators),
+            result_type,
+            operators: Vec::new(),
+            source_map: SourceMap::default(),
+        };
+        program.operators = program.intern(operators, &mut Interner::default());
+        program
+    }
+
+    // 编译公式并记录源码位置，按选项优化后再生成 Program
+    // new 不做优化，旧版本的字节码升级时保持原来的指令
+    pub fn compile(
+        body: &FormulaBody,
+        result_type: Option<FormulaValueType>,
+        options: &CompileOptions,
+    ) -> Self {
+        let operators = body.to_operator();
+        let source_map = source_map(body, &operators);
+        let (operators, source_map) = match options.optimize {
+            true => optimize_with_source_map(operators, source_map),
+            false => (operators, source_map),
+        };
+        let mut program = Program::new(operators, result_type);
+        program.source_map = source_map;
+        program
+    }
+
+    pub fn constant(&self, index: u32) -> Result<&Constant, ExecuteError> {
+        self.constants.get(index as usize).ok_or_else(|| {
+            ExecuteError::new(ExecuteErrorType::InvalidBytecode)
+                .with_message(format!("constant {} out of range", index))
+        })
+    }
+
+    pub fn constant_str(&self, index: u32) -> Result<&str, ExecuteError> {
+        match self.constant(index)? {
+            Constant::String(value) => Ok(value),
+            _ => Err(ExecuteError::new(ExecuteErrorType::InvalidBytecode)
+                .with_message(format!("constant {} is not a string", index))),
+        }
+    }
+
+    fn intern(
+        &mut self,
+        operators: Vec<OperatorCode>,
+        interner: &mut Interner,
+    ) -> Vec<OperatorCode> {
+        operators
+            .into_iter()
+            .map(|code| match code {
+                OperatorCode::PushNumber(value) => {
+                    OperatorCode::PushConstant(self.add_constant(Constant::Number(value), interner))
+                }
+                OperatorCode::PushString(value) => {
+                    OperatorCode::PushConstant(self.add_constant(Constant::String(value), interner))
+                }
+                // $ 在 lambda 中每个元素都不同，仍然按名字读取
+                OperatorCode::LoadIdentifier(name) if name != "$" => {
+                    OperatorCode::LoadSlot(self.add_slot(name, interner))
+                }
+                OperatorCode::StoreLocal(name) => {
+                    OperatorCode::StoreSlot(self.add_slot(name, interner))
+                }
+                OperatorCode::LoadPropertyAccess(property) => OperatorCode::LoadProperty(
+                    self.add_constant(Constant::String(property), interner),
+                ),
+                OperatorCode::FilterExpression(left, op, value) => OperatorCode::Filter(
+                    self.add_constant(Constant::String(left), interner),
+                    self.add_constant(Constant::String(op), interner),
+                    self.add_constant(Constant::String(value), interner),
+                ),
+                OperatorCode::PushLambda(lambda) => {
+                    OperatorCode::PushLambda(self.intern(lambda, interner))
+                }
+                code => code,
+            })
+            .collect()
+    }
+
+    fn add_constant(&mut self, constant: Constant, interner: &mut Interner) -> u32 {
+        let constants = &mut self.constants;
+        *interner
+            .constants
+            .entry(constant)
+            .or_insert_with_key(|constant| {
+                constants.push(constant.clone());
+                (constants.len() - 1) as u32
+            })
+    }
+
+    fn add_slot(&mut self, name: String, interner: &mut Interner) -> u32 {
+        let slots = &mut self.slots;
+        *interner.slots.entry(name).or_insert_with_key(|name| {
+            slots.push(name.clone());
+            (slots.len() - 1) as u32
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let payload = bincode::serialize(self).unwrap();
+        let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&checksum(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    // 读取任意版本保存的字节码，旧版本升级成当前的 Program
+    pub fn from_bytes(bytes: &[u8]) -> Result<Program, LoadError> {
+        if !bytes.starts_with(&MAGIC) {
+            let operators = bincode::deserialize::<Vec<OperatorCode>>(bytes)
+                .map_err(|e| LoadError::decode_error(e.to_string()))?;
+            let mut program = Program::new(operators, None);
+            program.compiler_version = String::new();
+            return Ok(program);
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(LoadError::decode_error("header is truncated".to_string()));
+        }
+
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        let expected = u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
+        let payload = &bytes[HEADER_LEN..];
+        if version > FORMAT_VERSION {
+            return Err(LoadError::unsupported_version(version));
+        }
+        if checksum(payload) != expected {
+            return Err(LoadError::checksum_mismatch());
+        }
+
+        bincode::deserialize::<Program>(payload).map_err(|e| LoadError::decode_error(e.to_string()))
+    }
+}
+
+// CRC-32（IEEE），只用来发现保存过程中的损坏
+fn checksum(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
@@ -0,0 +1,102 @@
+use alloc::{string::String, vec::Vec};
+
+// 把字符串字面量、模板字符串中的转义还原成字符，text 不含引号
+// 语法已经保证转义的格式正确，\u{} 不是合法的 Unicode 字符（例如 \u{D800}）时返回 None
+pub fn unescape(text: &str) -> Option<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            't' => result.push('\t'),
+            'u' => {
+                let rest = chars.as_str().strip_prefix('{')?;
+                let end = rest.find('}')?;
+                let code = u32::from_str_radix(&rest[..end], 16).ok()?;
+                result.push(char::from_u32(code)?);
+                chars = rest[end + 1..].chars();
+            }
+            c => result.push(c),
+        }
+    }
+    Some(result)
+}
+
+// 逐个字符扫描源码时记录是否在字符串里面，转义的引号不会结束字符串
+// 模板字符串整体当作一个字符串，其中的括号、逗号不参与匹配和切分
+#[derive(Default)]
+pub struct Quotes {
+    quote: Option<char>,
+    escaped: bool,
+}
+
+impl Quotes {
+    // 返回 c 是否属于字符串，包括两边的引号
+    pub fn scan(&mut self, c: char) -> bool {
+        match self.quote {
+            Some(_) if self.escaped => self.escaped = false,
+            Some(_) if c == '\\' => self.escaped = true,
+            Some(q) if c == q => self.quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' || c == '`' => self.quote = Some(c),
+            None => return false,
+        }
+        true
+    }
+
+    pub fn in_string(&self) -> bool {
+        self.quote.is_some()
+    }
+}
+
+// 找出源码中不在字符串里的 // 行注释和 /* */ 块注释，返回字节范围
+// 块注释没有闭合时一直到末尾
+pub fn comments(text: &str) -> Vec<(usize, usize)> {
+    let mut result = Vec::new();
+    let mut quotes = Quotes::default();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if quotes.scan(c) {
+            continue;
+        }
+        let end = match (c, chars.peek()) {
+            ('/', Some((_, '/'))) => text[i..].find('\n').map_or(text.len(), |end| i + end),
+            ('/', Some((_, '*'))) => text[i + 2..]
+                .find("*/")
+                .map_or(text.len(), |end| i + end + 4),
+            _ => continue,
+        };
+        result.push((i, end));
+        while chars.next_if(|(j, _)| *j < end).is_some() {}
+    }
+    result
+}
+
+// 一直延续到文本末尾的注释的起始位置：没有换行的行注释，或者没有闭合的块注释
+pub fn open_comment(text: &str) -> Option<usize> {
+    let (start, end) = comments(text).pop()?;
+    let comment = &text[start..end];
+    let closed = comment.starts_with("/*") && comment.len() >= 4 && comment.ends_with("*/");
+    (end == text.len() && !closed).then_some(start)
+}
+
+// 把注释替换成等长的空格（保留换行），注释中的引号、括号、分号不会影响按字符扫描的逻辑，位置也不需要偏移
+pub fn blank_comments(text: &str) -> String {
+    let mut result = String::from(text);
+    for (start, end) in comments(text).into_iter().rev() {
+        let blank = text[start..end]
+            .chars()
+            .map(|c| match c {
+                '\n' => String::from("\n"),
+                c => " ".repeat(c.len_utf8()),
+            })
+            .collect::<String>();
+        result.replace_range(start..end, &blank);
+    }
+    result
+}
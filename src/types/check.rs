@@ -1,8 +1,17 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+};
 use pest::iterators::Pairs;
 use pest::pratt_parser::PrattParser;
 
-use super::{error::TypeError, types::FormulaValueType};
-use crate::parse::parse::Rule;
+use super::{
+    error::TypeError, infer::InferType, schema::Schema, target::TargetType, types::FormulaValueType,
+};
+use crate::parse::{
+    ast::to_ast,
+    parse::{Formula, Rule},
+};
 
 lazy_static::lazy_static! {
     static ref TYPE_PRATT_PARSER: PrattParser<Rule> = {
@@ -52,3 +61,51 @@ pub fn type_check(paris: &Pairs<Rule>) -> Result<FormulaValueType, TypeError> {
         })
         .parse(paris.clone())
 }
+
+// 在保存公式前，检查公式的结果类型是否和字段类型匹配
+pub fn check_formula(
+    expr: &str,
+    target_type: &str,
+    schema: &Schema,
+) -> Result<FormulaValueType, TypeError> {
+    let target = TargetType::from_name(target_type)
+        .ok_or_else(|| TypeError::unknown_target_type(target_type))?;
+
+    let formula = Formula::parse(expr).map_err(|e| TypeError::parse_error(e.to_string()))?;
+    let (_, ast) = to_ast(formula.paris);
+
+    let mut schema = schema.clone();
+    schema.inject_builtins();
+
+    let result_type = ast.infer_type(&schema)?;
+    if target.accepts(&result_type) {
+        return Ok(result_type);
+    }
+
+    let error = TypeError::target_mismatch(result_type.clone(), target.clone());
+    let expr = expr.trim();
+    Err(match (&result_type, target.is_date_time()) {
+        (FormulaValueType::Duration, true) => error
+            .with_suggestion(String::from(
+                "store the result in a number field (integer or decimal), a duration is saved as milliseconds",
+            ))
+            .with_suggestion(format!(
+                "add the duration to a datetime to get a datetime, e.g. `GET_CREATE_TIME + ({})`",
+                expr
+            )),
+        (FormulaValueType::Number, true) => error.with_suggestion(String::from(
+            "store the result in a number field (integer or decimal)",
+        )),
+        (FormulaValueType::DateTime, false) => error
+            .with_suggestion(String::from("store the result in a datetime field"))
+            .with_suggestion(format!(
+                "subtract another datetime to get a duration, e.g. `GET_NOW - ({})`",
+                expr
+            )),
+        (FormulaValueType::Array, false) => error.with_suggestion(format!(
+            "aggregate the list into a number, e.g. `SUM({})` or `COUNT({})`",
+            expr, expr
+        )),
+        _ => error,
+    })
+}
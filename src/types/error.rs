@@ -1,19 +1,32 @@
-use alloc::string::String;
+use alloc::{format, string::String, vec::Vec};
 
-use super::{operator::FormulaOperator, types::FormulaValueType};
+use super::{operator::FormulaOperator, target::TargetType, types::FormulaValueType};
 
 // 数值相关错误
 #[derive(Clone, Debug, PartialEq)]
 pub enum TypeErrorType {
     UnknownError,
 
+    ParseError,
+    InvalidSchema,
+    UnknownTargetType,
+
     OperatorMismatchError(FormulaOperator, FormulaValueType, Option<FormulaValueType>),
+
+    IdentifierNotFound,
+    FunctionNotFound,
+    PropertyNotFound,
+    FunctionInvalidArgument,
+
+    // 公式的结果类型和字段的类型不匹配
+    TargetMismatch(FormulaValueType, TargetType),
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct TypeError {
     pub type_: TypeErrorType,
     pub message: Option<String>,
+    pub suggestions: Vec<String>, // 建议的修改方式，例如类型转换
 }
 
 impl TypeError {
@@ -21,6 +34,7 @@ impl TypeError {
         Self {
             type_,
             message: None,
+            suggestions: Vec::new(),
         }
     }
 
@@ -30,11 +44,16 @@ impl TypeError {
 
     pub fn with_message(self, msg: String) -> Self {
         Self {
-            type_: self.type_,
             message: Some(msg),
+            ..self
         }
     }
 
+    pub fn with_suggestion(mut self, suggestion: String) -> Self {
+        self.suggestions.push(suggestion);
+        self
+    }
+
     pub fn operator_mismatch(
         operator: FormulaOperator,
         lhs: FormulaValueType,
@@ -42,4 +61,42 @@ impl TypeError {
     ) -> Self {
         Self::new(TypeErrorType::OperatorMismatchError(operator, lhs, rhs))
     }
+
+    pub fn parse_error(msg: String) -> Self {
+        Self::new(TypeErrorType::ParseError).with_message(msg)
+    }
+
+    pub fn invalid_schema(msg: String) -> Self {
+        Self::new(TypeErrorType::InvalidSchema).with_message(msg)
+    }
+
+    pub fn unknown_target_type(name: &str) -> Self {
+        Self::new(TypeErrorType::UnknownTargetType)
+            .with_message(format!("unknown field type: {}", name))
+    }
+
+    pub fn identifier_not_found(identifier: &str) -> Self {
+        Self::new(TypeErrorType::IdentifierNotFound)
+            .with_message(format!("identifier not found: {}", identifier))
+    }
+
+    pub fn function_not_found(name: &str) -> Self {
+        Self::new(TypeErrorType::FunctionNotFound)
+            .with_message(format!("function not found: {}", name))
+    }
+
+    pub fn property_not_found(property: &str) -> Self {
+        Self::new(TypeErrorType::PropertyNotFound)
+            .with_message(format!("property not found: {}", property))
+    }
+
+    pub fn function_invalid_argument(name: &str, actual: &FormulaValueType) -> Self {
+        Self::new(TypeErrorType::FunctionInvalidArgument)
+            .with_message(format!("function {} can not accept {}", name, actual))
+    }
+
+    pub fn target_mismatch(result: FormulaValueType, target: TargetType) -> Self {
+        let message = format!("{} produced but field expects {}", result, target.name());
+        Self::new(TypeErrorType::TargetMismatch(result, target)).with_message(message)
+    }
 }
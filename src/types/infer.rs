@@ -0,0 +1,433 @@
+use alloc::string::ToString;
+
+use super::{
+    error::TypeError,
+    operator::FormulaOperator,
+    schema::{Schema, SchemaField},
+    types::FormulaValueType,
+};
+use crate::{
+    parse::ast::{
+        ArrayLiteral, BinaryExpression, CallExpression, ExpressionKind, ExpressionStatement,
+        FormulaBody, Identifier, IndexExpression, LetBinding, PropertyAccessExpression,
+        SliceExpression, TemplateLiteral, TemplatePart, UnaryExpression,
+    },
+    share::function::{find_function, FunctionInfo},
+};
+
+// 在不执行公式的情况下推导公式结果的类型
+pub trait InferType {
+    fn infer_type(&self, schema: &Schema) -> Result<FormulaValueType, TypeError>;
+}
+
+impl InferType for FormulaBody {
+    fn infer_type(&self, schema: &Schema) -> Result<FormulaValueType, TypeError> {
+        // 按顺序检查每个语句，let 绑定的名字在后面的语句中可以使用
+        // 类型、函数定义和 let 绑定不产生值，结果由最后一个表达式决定
+        let mut scope = schema.clone();
+        let mut result = None;
+        for (_, statement) in self.body.iter() {
+            match &statement.expression.1 {
+                ExpressionKind::TypeDefineKind(_, _) | ExpressionKind::FuncDefineKind(_, _) => {}
+                ExpressionKind::LetBindingKind(_, binding) => binding.bind(&mut scope)?,
+                expression => result = Some(expression.infer_type(&scope)?),
+            }
+        }
+
+        result.ok_or_else(|| TypeError::unknown().with_message("formula is empty".to_string()))
+    }
+}
+
+impl FormulaBody {
+    // 加上所有 let 绑定之后的 schema，编辑器用来补全、显示绑定的类型
+    pub fn scope(&self, schema: &Schema) -> Schema {
+        let mut scope = schema.clone();
+        for (_, statement) in self.body.iter() {
+            if let ExpressionKind::LetBindingKind(_, binding) = &statement.expression.1 {
+                let _ = binding.bind(&mut scope);
+            }
+        }
+        scope
+    }
+}
+
+impl LetBinding {
+    // 把绑定的名字加到 scope 中，值是字段或者数组时保留属性
+    // 例如 let done = where(subtask, $.status == 2) 之后可以使用 done.estimatePoint
+    pub fn bind(&self, scope: &mut Schema) -> Result<(), TypeError> {
+        let value = &*self.value.1;
+        let type_ = value.infer_type(scope)?;
+        let fields = match type_ {
+            FormulaValueType::Array => element_fields(value, scope),
+            _ => field_of(value, scope).and_then(|field| field.fields),
+        };
+        scope.set(self.ident.1.name.clone(), SchemaField { type_, fields });
+        Ok(())
+    }
+}
+
+impl InferType for ExpressionStatement {
+    fn infer_type(&self, schema: &Schema) -> Result<FormulaValueType, TypeError> {
+        self.expression.1.infer_type(schema)
+    }
+}
+
+impl InferType for UnaryExpression {
+    fn infer_type(&self, schema: &Schema) -> Result<FormulaValueType, TypeError> {
+        let argument = self.argument.1.infer_type(schema)?;
+        match self.prefix {
+            // 取正和取负接受的类型相同
+            true => argument.negate(),
+            false => argument.factorial(),
+        }
+    }
+}
+
+impl InferType for BinaryExpression {
+    fn infer_type(&self, schema: &Schema) -> Result<FormulaValueType, TypeError> {
+        let lhs = self.left.1.infer_type(schema)?;
+        let rhs = self.right.1.infer_type(schema)?;
+
+        match FormulaOperator::from_raw(self.operator.1.as_str()) {
+            Some(FormulaOperator::Add) => lhs.add(rhs),
+            Some(FormulaOperator::Sub) => lhs.subtract(rhs),
+            Some(FormulaOperator::Mul) => lhs.multiply(rhs),
+            Some(FormulaOperator::Div) => lhs.divide(rhs),
+            Some(FormulaOperator::Modulo) => lhs.modulo(rhs),
+            Some(FormulaOperator::Pow) => lhs.pow(rhs),
+            Some(
+                operator @ (FormulaOperator::IntDiv
+                | FormulaOperator::ShiftLeft
+                | FormulaOperator::ShiftRight
+                | FormulaOperator::BitAnd
+                | FormulaOperator::BitOr
+                | FormulaOperator::BitXor),
+            ) => lhs.integer_operation(operator, rhs),
+            // 左边确定为 Null 时取右边的类型，否则取左边的类型
+            Some(FormulaOperator::Coalesce) => match lhs {
+                FormulaValueType::Null => Ok(rhs),
+                lhs => Ok(lhs),
+            },
+            Some(op) if op.is_compare() => Ok(FormulaValueType::Bool),
+            _ => Err(TypeError::unknown()
+                .with_message(alloc::format!("unknown operator {}", self.operator.1))),
+        }
+    }
+}
+
+impl InferType for CallExpression {
+    fn infer_type(&self, schema: &Schema) -> Result<FormulaValueType, TypeError> {
+        let name = match &*self.callee.1 {
+            ExpressionKind::IdentifierKind(_, Identifier { name }) => name.as_str(),
+            _ => return Err(TypeError::function_not_found("")),
+        };
+
+        let function = match find_function(name) {
+            Some(function) => function,
+            None => return Err(TypeError::function_not_found(name)),
+        };
+
+        // 旧的过滤写法 COUNT(subtask; status=2) 中，status 是 subtask 元素的属性
+        // 新的写法 where(subtask, $.status == 2) 中，$ 是 subtask 的元素
+        let record = self
+            .arguments
+            .first()
+            .and_then(|(_, arg)| element_fields(arg, schema));
+
+        for (index, (_, arg)) in self.arguments.iter().enumerate() {
+            if index > 0 && arg.is_lambda() {
+                let mut scope = schema.clone();
+                scope.set(
+                    "$".to_string(),
+                    SchemaField {
+                        type_: FormulaValueType::Object,
+                        fields: record.clone(),
+                    },
+                );
+                arg.infer_type(&scope)?;
+                continue;
+            }
+
+            if let Some(field) = legacy_filter_field(arg).filter(|_| self.is_legacy()) {
+                match record {
+                    Some(fields) if !fields.has(field) => {
+                        return Err(TypeError::property_not_found(field))
+                    }
+                    _ => continue,
+                }
+            }
+
+            let arg_type = arg.infer_type(schema)?;
+            if !accepts(function, index, &arg_type) {
+                return Err(TypeError::function_invalid_argument(name, &arg_type));
+            }
+        }
+
+        Ok(FormulaValueType::from_name(function.return_type).unwrap_or(FormulaValueType::Null))
+    }
+}
+
+impl InferType for PropertyAccessExpression {
+    fn infer_type(&self, schema: &Schema) -> Result<FormulaValueType, TypeError> {
+        let property = self.property.1.name.as_str();
+
+        let object = match field_of(&self.object.1, schema) {
+            Some(object) => object,
+            None => {
+                // 对象不是一个字段（例如函数调用的结果），只能根据类型判断
+                return match self.object.1.infer_type(schema)? {
+                    FormulaValueType::Array => Ok(FormulaValueType::Array),
+                    // 运行时对 Null 取属性得到 Null
+                    FormulaValueType::Null => Ok(FormulaValueType::Null),
+                    object_type => Err(TypeError::operator_mismatch(
+                        FormulaOperator::Dot,
+                        object_type,
+                        None,
+                    )),
+                };
+            }
+        };
+
+        let property_type = match object.fields.as_ref() {
+            Some(fields) => match fields.get(property) {
+                Some(field) => Some(field.type_.clone()),
+                None => return Err(TypeError::property_not_found(property)),
+            },
+            None => None,
+        };
+
+        match (&object.type_, property_type) {
+            // 数组的属性访问是 map 的含义，结果仍然是数组
+            (FormulaValueType::Array, _) => Ok(FormulaValueType::Array),
+            (FormulaValueType::Object, Some(property_type)) => Ok(property_type),
+            (FormulaValueType::Object, None) => Err(TypeError::property_not_found(property)),
+            (object_type, _) => Err(TypeError::operator_mismatch(
+                FormulaOperator::Dot,
+                object_type.clone(),
+                None,
+            )),
+        }
+    }
+}
+
+impl InferType for IndexExpression {
+    fn infer_type(&self, schema: &Schema) -> Result<FormulaValueType, TypeError> {
+        let object_type = self.object.1.infer_type(schema)?;
+        let index_type = self.index.1.infer_type(schema)?;
+        if object_type == FormulaValueType::Null {
+            return Ok(FormulaValueType::Null);
+        }
+        if object_type != FormulaValueType::Array || index_type != FormulaValueType::Number {
+            return Err(TypeError::operator_mismatch(
+                FormulaOperator::Index,
+                object_type,
+                Some(index_type),
+            ));
+        }
+
+        // 元素类型未知时按 Null 处理，越界时结果本来也是 Null
+        Ok(element_type(&self.object.1, schema).unwrap_or(FormulaValueType::Null))
+    }
+}
+
+impl InferType for SliceExpression {
+    fn infer_type(&self, schema: &Schema) -> Result<FormulaValueType, TypeError> {
+        let object_type = self.object.1.infer_type(schema)?;
+        if object_type != FormulaValueType::Array {
+            return Err(TypeError::operator_mismatch(
+                FormulaOperator::Index,
+                object_type,
+                None,
+            ));
+        }
+
+        for (_, bound) in self.start.iter().chain(self.end.iter()) {
+            let bound_type = bound.infer_type(schema)?;
+            if bound_type != FormulaValueType::Number {
+                return Err(TypeError::operator_mismatch(
+                    FormulaOperator::Index,
+                    object_type,
+                    Some(bound_type),
+                ));
+            }
+        }
+
+        Ok(FormulaValueType::Array)
+    }
+}
+
+impl InferType for ArrayLiteral {
+    fn infer_type(&self, schema: &Schema) -> Result<FormulaValueType, TypeError> {
+        for (_, element) in self.elements.iter() {
+            element.infer_type(schema)?;
+        }
+        Ok(FormulaValueType::Array)
+    }
+}
+
+// ${} 中的任意值都会转换成文本
+impl InferType for TemplateLiteral {
+    fn infer_type(&self, schema: &Schema) -> Result<FormulaValueType, TypeError> {
+        for part in self.parts.iter() {
+            if let TemplatePart::Expression(_, expr) = part {
+                expr.infer_type(schema)?;
+            }
+        }
+        Ok(FormulaValueType::String)
+    }
+}
+
+impl InferType for ExpressionKind {
+    fn infer_type(&self, schema: &Schema) -> Result<FormulaValueType, TypeError> {
+        match self {
+            ExpressionKind::UnaryExpressionKind(_, unary_expression) => {
+                unary_expression.infer_type(schema)
+            }
+            ExpressionKind::BinaryExpressionKind(_, binary_expression) => {
+                binary_expression.infer_type(schema)
+            }
+            ExpressionKind::CallExpressionKind(_, call_expression) => {
+                call_expression.infer_type(schema)
+            }
+            ExpressionKind::PropertyAccessExpressionKind(_, property_access_expression) => {
+                property_access_expression.infer_type(schema)
+            }
+            ExpressionKind::IndexExpressionKind(_, index_expression) => {
+                index_expression.infer_type(schema)
+            }
+            ExpressionKind::SliceExpressionKind(_, slice_expression) => {
+                slice_expression.infer_type(schema)
+            }
+            ExpressionKind::ArrayLiteralKind(_, array_literal) => array_literal.infer_type(schema),
+            ExpressionKind::TemplateLiteralKind(_, template_literal) => {
+                template_literal.infer_type(schema)
+            }
+            ExpressionKind::StringLiteralKind(_, _) => Ok(FormulaValueType::String),
+            ExpressionKind::NumberLiteralKind(_, _) => Ok(FormulaValueType::Number),
+            ExpressionKind::BoolLiteralKind(_, _) => Ok(FormulaValueType::Bool),
+            ExpressionKind::NullLiteralKind(_, _) => Ok(FormulaValueType::Null),
+            ExpressionKind::DateLiteralKind(_, _) => Ok(FormulaValueType::DateTime),
+            ExpressionKind::IdentifierKind(_, identifier) => match schema.get(&identifier.name) {
+                Some(field) => Ok(field.type_.clone()),
+                None => Err(TypeError::identifier_not_found(&identifier.name)),
+            },
+            ExpressionKind::TypeDefineKind(_, _)
+            | ExpressionKind::FuncDefineKind(_, _)
+            | ExpressionKind::LetBindingKind(_, _) => Ok(FormulaValueType::Null),
+            ExpressionKind::ErrorKind(_, error) => Err(TypeError::parse_error(alloc::format!(
+                "invalid expression: {}",
+                error.raw
+            ))),
+        }
+    }
+}
+
+// 沿着 a.b.c、subtask[0].name 的路径查找 schema 中声明的字段
+pub(crate) fn field_of(expr: &ExpressionKind, schema: &Schema) -> Option<SchemaField> {
+    match expr {
+        ExpressionKind::IdentifierKind(_, identifier) => schema.get(&identifier.name).cloned(),
+        ExpressionKind::PropertyAccessExpressionKind(_, access) => {
+            field_of(&access.object.1, schema)?
+                .fields?
+                .get(&access.property.1.name)
+                .cloned()
+        }
+        ExpressionKind::IndexExpressionKind(_, index) => {
+            let array = field_of(&index.object.1, schema)?;
+            match array.type_ {
+                FormulaValueType::Array => Some(SchemaField {
+                    type_: FormulaValueType::Object,
+                    fields: Some(array.fields?),
+                }),
+                _ => None,
+            }
+        }
+        ExpressionKind::SliceExpressionKind(_, slice) => field_of(&slice.object.1, schema),
+        _ => None,
+    }
+}
+
+// 数组元素的类型，subtask 的元素是对象，subtask.estimatePoint 的元素是 estimatePoint 的类型
+fn element_type(expr: &ExpressionKind, schema: &Schema) -> Option<FormulaValueType> {
+    match expr {
+        ExpressionKind::ArrayLiteralKind(_, array) => {
+            let mut types = array
+                .elements
+                .iter()
+                .map(|(_, element)| element.infer_type(schema).ok());
+            let first = types.next()??;
+            match types.all(|type_| type_.as_ref() == Some(&first)) {
+                true => Some(first),
+                false => None,
+            }
+        }
+        ExpressionKind::SliceExpressionKind(_, slice) => element_type(&slice.object.1, schema),
+        _ => match field_of(expr, schema)? {
+            SchemaField {
+                type_: FormulaValueType::Array,
+                fields: Some(_),
+            } => Some(FormulaValueType::Object),
+            SchemaField {
+                type_: FormulaValueType::Array,
+                fields: None,
+            } => None,
+            field => Some(field.type_),
+        },
+    }
+}
+
+// 数组元素的属性，例如 subtask、subtask.estimatePoint、where(subtask, ...) 都返回 subtask 元素的属性
+pub(crate) fn element_fields(expr: &ExpressionKind, schema: &Schema) -> Option<Schema> {
+    match expr {
+        ExpressionKind::PropertyAccessExpressionKind(_, access) => field_of(expr, schema)
+            .and_then(|field| field.fields)
+            .or_else(|| element_fields(&access.object.1, schema)),
+        ExpressionKind::CallExpressionKind(_, call) => {
+            element_fields(&call.arguments.first()?.1, schema)
+        }
+        ExpressionKind::SliceExpressionKind(_, slice) => element_fields(&slice.object.1, schema),
+        _ => field_of(expr, schema)?.fields,
+    }
+}
+
+// 按照函数签名中声明的参数类型检查参数，例如 "Array | Number"、"Number[]"、"Any"
+// 可变参数重复使用最后一个参数的类型，Null 可以传给任意参数
+fn accepts(function: &FunctionInfo, index: usize, arg_type: &FormulaValueType) -> bool {
+    let parameter = match function.parameters.get(index) {
+        Some(parameter) => parameter,
+        None if function.variadic => match function.parameters.last() {
+            Some(parameter) => parameter,
+            None => return false,
+        },
+        None => return false,
+    };
+
+    *arg_type == FormulaValueType::Null
+        || parameter
+            .type_
+            .split('|')
+            .map(|name| name.trim())
+            .any(|name| match name {
+                "Any" => true,
+                name if name.ends_with("[]") => *arg_type == FormulaValueType::Array,
+                name => FormulaValueType::from_name(name).as_ref() == Some(arg_type),
+            })
+}
+
+// 旧的过滤写法 status=2，返回被过滤的属性名
+fn legacy_filter_field(expr: &ExpressionKind) -> Option<&str> {
+    match expr {
+        ExpressionKind::BinaryExpressionKind(_, binary) => {
+            let is_compare = FormulaOperator::from_raw(binary.operator.1.as_str())
+                .map(|op| op.is_compare())
+                .unwrap_or(false);
+            match (is_compare, &*binary.left.1) {
+                (true, ExpressionKind::IdentifierKind(_, identifier)) => {
+                    Some(identifier.name.as_str())
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
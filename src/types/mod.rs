@@ -3,3 +3,6 @@ pub mod check;
 pub mod types;
 pub mod operator;
 pub mod ast;
+pub mod infer;
+pub mod schema;
+pub mod target;
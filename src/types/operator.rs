@@ -10,6 +10,14 @@ pub enum FormulaOperator {
     Modulo,
     Neg,
 
+    // 整数计算
+    IntDiv,
+    ShiftLeft,
+    ShiftRight,
+    BitAnd,
+    BitOr,
+    BitXor,
+
     // 逻辑计算
     And,
     Or,
@@ -26,4 +34,50 @@ pub enum FormulaOperator {
     // 其他
     Dot,
     Call,
+    Index,    // 下标和切片
+    Coalesce, // 空值合并 ??
+}
+
+impl FormulaOperator {
+    // 把源码中的运算符（包括 plus、divide by 这些别名）转换成 FormulaOperator
+    pub fn from_raw(raw: &str) -> Option<FormulaOperator> {
+        match raw {
+            "+" | "with" | "plus" | "add" => Some(FormulaOperator::Add),
+            "-" | "without" | "subtract" | "minus" => Some(FormulaOperator::Sub),
+            "*" | "times" | "multiply by" | "mul" => Some(FormulaOperator::Mul),
+            "/" | "divide by" | "divide" => Some(FormulaOperator::Div),
+            "^" | "power" => Some(FormulaOperator::Pow),
+            "%" | "mod" => Some(FormulaOperator::Modulo),
+            "!" => Some(FormulaOperator::Factorial),
+
+            "div" => Some(FormulaOperator::IntDiv),
+            "<<" => Some(FormulaOperator::ShiftLeft),
+            ">>" => Some(FormulaOperator::ShiftRight),
+            "&" => Some(FormulaOperator::BitAnd),
+            "|" => Some(FormulaOperator::BitOr),
+            "xor" => Some(FormulaOperator::BitXor),
+
+            "==" | "=" => Some(FormulaOperator::Eq),
+            "!=" | "<>" => Some(FormulaOperator::Ne),
+            ">" => Some(FormulaOperator::Gt),
+            ">=" => Some(FormulaOperator::Ge),
+            "<" => Some(FormulaOperator::Lt),
+            "<=" => Some(FormulaOperator::Le),
+
+            "??" => Some(FormulaOperator::Coalesce),
+            _ => None,
+        }
+    }
+
+    pub fn is_compare(&self) -> bool {
+        matches!(
+            self,
+            FormulaOperator::Eq
+                | FormulaOperator::Ne
+                | FormulaOperator::Gt
+                | FormulaOperator::Ge
+                | FormulaOperator::Lt
+                | FormulaOperator::Le
+        )
+    }
 }
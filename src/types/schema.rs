@@ -0,0 +1,109 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use hashbrown::HashMap;
+use serde_json::Value as JsonValue;
+
+use super::{error::TypeError, types::FormulaValueType};
+
+// 公式可以访问的字段及其类型，用来在保存公式前做静态检查
+// JSON 格式:
+// { "estimatePoint": "Number", "subtask": { "type": "Array", "fields": { "status": "Number" } } }
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Schema {
+    pub fields: HashMap<String, SchemaField>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchemaField {
+    pub type_: FormulaValueType,
+    pub fields: Option<Schema>, // Object 的属性，或者 Array 中每个元素的属性
+}
+
+impl SchemaField {
+    pub fn new(type_: FormulaValueType) -> Self {
+        Self {
+            type_,
+            fields: None,
+        }
+    }
+
+    fn from_json(name: &str, json: &JsonValue) -> Result<SchemaField, TypeError> {
+        match json {
+            JsonValue::String(type_name) => Ok(SchemaField::new(parse_type_name(name, type_name)?)),
+            JsonValue::Object(obj) => {
+                let type_ = match obj.get("type") {
+                    Some(JsonValue::String(type_name)) => parse_type_name(name, type_name)?,
+                    _ => {
+                        return Err(TypeError::invalid_schema(format!(
+                            "field {} has no type",
+                            name
+                        )))
+                    }
+                };
+                let fields = match obj.get("fields") {
+                    Some(fields) => Some(Schema::from_json(fields)?),
+                    None => None,
+                };
+                Ok(SchemaField { type_, fields })
+            }
+            _ => Err(TypeError::invalid_schema(format!(
+                "field {} should be a type name or an object",
+                name
+            ))),
+        }
+    }
+}
+
+fn parse_type_name(name: &str, type_name: &str) -> Result<FormulaValueType, TypeError> {
+    FormulaValueType::from_name(type_name).ok_or_else(|| {
+        TypeError::invalid_schema(format!("field {} has unknown type {}", name, type_name))
+    })
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_json(json: &JsonValue) -> Result<Schema, TypeError> {
+        match json {
+            JsonValue::Object(obj) => {
+                let mut schema = Schema::new();
+                for (name, value) in obj {
+                    schema.set(name.to_string(), SchemaField::from_json(name, value)?);
+                }
+                Ok(schema)
+            }
+            JsonValue::Null => Ok(Schema::new()),
+            _ => Err(TypeError::invalid_schema(
+                "schema should be an object".to_string(),
+            )),
+        }
+    }
+
+    // 和 RuntimeContext 中注入的时间变量保持一致，除非 schema 中已经声明了
+    pub fn inject_builtins(&mut self) {
+        for name in ["GET_NOW", "GET_TODAY", "GET_UPDATE_TIME", "GET_CREATE_TIME"] {
+            if !self.has(name) {
+                self.set(
+                    name.to_string(),
+                    SchemaField::new(FormulaValueType::DateTime),
+                );
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SchemaField> {
+        self.fields.get(name)
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.fields.contains_key(name)
+    }
+
+    pub fn set(&mut self, name: String, field: SchemaField) {
+        self.fields.insert(name, field);
+    }
+}
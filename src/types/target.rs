@@ -0,0 +1,54 @@
+use super::types::FormulaValueType;
+
+// 公式字段的类型，即公式记录中的 type，例如 integer、decimal、datetime
+#[derive(Clone, Debug, PartialEq)]
+pub enum TargetType {
+    Integer,
+    Decimal,
+    PercentageNumber,
+    PercentageBar,
+    Date,
+    DateTime,
+}
+
+impl TargetType {
+    pub fn from_name(name: &str) -> Option<TargetType> {
+        match name {
+            "integer" => Some(TargetType::Integer),
+            "decimal" => Some(TargetType::Decimal),
+            "percentage_number" => Some(TargetType::PercentageNumber),
+            "percentage_bar" => Some(TargetType::PercentageBar),
+            "date" => Some(TargetType::Date),
+            "datetime" => Some(TargetType::DateTime),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TargetType::Integer => "integer",
+            TargetType::Decimal => "decimal",
+            TargetType::PercentageNumber => "percentage_number",
+            TargetType::PercentageBar => "percentage_bar",
+            TargetType::Date => "date",
+            TargetType::DateTime => "datetime",
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        !self.is_date_time()
+    }
+
+    pub fn is_date_time(&self) -> bool {
+        matches!(self, TargetType::Date | TargetType::DateTime)
+    }
+
+    // 时间间隔在运行时是毫秒数，所以允许保存到数字类型的字段里
+    pub fn accepts(&self, value_type: &FormulaValueType) -> bool {
+        match value_type {
+            FormulaValueType::Number | FormulaValueType::Duration => self.is_number(),
+            FormulaValueType::DateTime => self.is_date_time(),
+            _ => false,
+        }
+    }
+}
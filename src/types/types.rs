@@ -1,6 +1,9 @@
+use core::fmt::Display;
+use serde::{Deserialize, Serialize};
+
 use super::{error::TypeError, operator::FormulaOperator};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum FormulaValueType {
     Error,
     Bool,
@@ -9,9 +12,25 @@ pub enum FormulaValueType {
     DateTime,
     Duration,
     Array,
+    Object,
+    Null,
 }
 
 impl FormulaValueType {
+    pub fn from_name(name: &str) -> Option<FormulaValueType> {
+        match name.to_ascii_lowercase().as_str() {
+            "bool" | "boolean" => Some(FormulaValueType::Bool),
+            "number" => Some(FormulaValueType::Number),
+            "string" => Some(FormulaValueType::String),
+            "datetime" | "date" => Some(FormulaValueType::DateTime),
+            "duration" => Some(FormulaValueType::Duration),
+            "array" => Some(FormulaValueType::Array),
+            "object" => Some(FormulaValueType::Object),
+            "null" => Some(FormulaValueType::Null),
+            _ => None,
+        }
+    }
+
     pub fn add(self, _rhs: FormulaValueType) -> Result<FormulaValueType, TypeError> {
         match (self.clone(), _rhs.clone()) {
             (FormulaValueType::Number, FormulaValueType::Number) => Ok(FormulaValueType::Number),
@@ -37,4 +56,119 @@ impl FormulaValueType {
             )),
         }
     }
+
+    pub fn subtract(self, rhs: FormulaValueType) -> Result<FormulaValueType, TypeError> {
+        match (&self, &rhs) {
+            (FormulaValueType::Number, FormulaValueType::Number) => Ok(FormulaValueType::Number),
+
+            // 两个时间相减得到的是时间间隔，而不是时间
+            (FormulaValueType::DateTime, FormulaValueType::DateTime) => {
+                Ok(FormulaValueType::Duration)
+            }
+            (FormulaValueType::DateTime, FormulaValueType::Duration) => {
+                Ok(FormulaValueType::DateTime)
+            }
+            (FormulaValueType::Duration, FormulaValueType::Duration) => {
+                Ok(FormulaValueType::Duration)
+            }
+
+            _ => Err(TypeError::operator_mismatch(
+                FormulaOperator::Sub,
+                self,
+                Some(rhs),
+            )),
+        }
+    }
+
+    pub fn multiply(self, rhs: FormulaValueType) -> Result<FormulaValueType, TypeError> {
+        match (&self, &rhs) {
+            (FormulaValueType::Number, FormulaValueType::Number) => Ok(FormulaValueType::Number),
+            (FormulaValueType::Duration, FormulaValueType::Number)
+            | (FormulaValueType::Number, FormulaValueType::Duration) => {
+                Ok(FormulaValueType::Duration)
+            }
+            _ => Err(TypeError::operator_mismatch(
+                FormulaOperator::Mul,
+                self,
+                Some(rhs),
+            )),
+        }
+    }
+
+    pub fn divide(self, rhs: FormulaValueType) -> Result<FormulaValueType, TypeError> {
+        match (&self, &rhs) {
+            (FormulaValueType::Number, FormulaValueType::Number) => Ok(FormulaValueType::Number),
+            (FormulaValueType::Duration, FormulaValueType::Number) => {
+                Ok(FormulaValueType::Duration)
+            }
+            _ => Err(TypeError::operator_mismatch(
+                FormulaOperator::Div,
+                self,
+                Some(rhs),
+            )),
+        }
+    }
+
+    pub fn modulo(self, rhs: FormulaValueType) -> Result<FormulaValueType, TypeError> {
+        match (&self, &rhs) {
+            (FormulaValueType::Number, FormulaValueType::Number) => Ok(FormulaValueType::Number),
+            _ => Err(TypeError::operator_mismatch(
+                FormulaOperator::Modulo,
+                self,
+                Some(rhs),
+            )),
+        }
+    }
+
+    pub fn pow(self, rhs: FormulaValueType) -> Result<FormulaValueType, TypeError> {
+        match (&self, &rhs) {
+            (FormulaValueType::Number, FormulaValueType::Number) => Ok(FormulaValueType::Number),
+            _ => Err(TypeError::operator_mismatch(
+                FormulaOperator::Pow,
+                self,
+                Some(rhs),
+            )),
+        }
+    }
+
+    // 整除、移位、位运算，是否为整数只能在运行时检查
+    pub fn integer_operation(
+        self,
+        operator: FormulaOperator,
+        rhs: FormulaValueType,
+    ) -> Result<FormulaValueType, TypeError> {
+        match (&self, &rhs) {
+            (FormulaValueType::Number, FormulaValueType::Number) => Ok(FormulaValueType::Number),
+            _ => Err(TypeError::operator_mismatch(operator, self, Some(rhs))),
+        }
+    }
+
+    pub fn negate(self) -> Result<FormulaValueType, TypeError> {
+        match &self {
+            FormulaValueType::Number => Ok(FormulaValueType::Number),
+            FormulaValueType::Duration => Ok(FormulaValueType::Duration),
+            _ => Err(TypeError::operator_mismatch(
+                FormulaOperator::Neg,
+                self,
+                None,
+            )),
+        }
+    }
+
+    pub fn factorial(self) -> Result<FormulaValueType, TypeError> {
+        match &self {
+            FormulaValueType::Number => Ok(FormulaValueType::Number),
+            _ => Err(TypeError::operator_mismatch(
+                FormulaOperator::Factorial,
+                self,
+                None,
+            )),
+        }
+    }
+}
+
+impl Display for FormulaValueType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?}", self)
+    }
 }
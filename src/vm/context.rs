@@ -1,27 +1,155 @@
 use alloc::{
     boxed::Box,
     string::{String, ToString},
+    sync::Arc,
     vec::Vec,
 };
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 
-use super::{function::RuntimeFunction, value::Value};
+use super::{error::ExecuteError, function::RuntimeFunction, trace::Observer, value::Value};
+use crate::share::{operator::OperatorCode, program::Program};
+
+// Null 参与运算时的处理方式，详见 formula.md 中的空值一节
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NullMode {
+    Propagate, // 取属性、取下标结果为 Null，例如没有 parent 时 parent.assignee.name 为 Null
+    Strict,    // 取属性、取下标报错，和数组以外的值一样
+    Lenient,   // 在 Propagate 的基础上，算术运算有一边是 Null 时结果也为 Null
+}
+
+// 执行时的资源限制，超过时报错，避免死循环卡住浏览器或者溢出 panic
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    pub max_instructions: usize,  // 一次执行的指令数，包括 lambda 中的指令
+    pub max_stack_depth: usize,   // 值栈的深度，lambda 使用自己的值栈
+    pub max_array_length: usize,  // 指令产生的数组长度，包括从 Issue 中读取的字段
+    pub max_string_length: usize, // 指令产生的文本长度，按 UTF-8 字节计算
+    pub max_factorial: i64,       // 阶乘的参数，超过 20 时结果超出 Rational64
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_instructions: 1_000_000,
+            max_stack_depth: 1024,
+            max_array_length: 100_000,
+            max_string_length: 1 << 20,
+            max_factorial: 20,
+        }
+    }
+}
 
 pub struct RuntimeContext {
     pub heap: HashMap<String, Value>,
+    pub locals: HashMap<String, Option<Value>>, // let 绑定的值，执行公式前放入所有 let 的名字，绑定之前为 None
     pub function_table: HashMap<String, Box<dyn RuntimeFunction>>,
     pub value_stack: Vec<Value>,
+    pub null_mode: NullMode,
+    pub null_filter: bool, // 执行 where 的条件时为 true，和 SQL 一样和 Null 比较的结果都是 false，跳过属性为 Null 的元素
+    pub program: Option<Arc<Program>>, // 正在执行的 Program，PushConstant 等指令从这里读取常量
+    pub slots: Vec<Option<Value>>, // 按 program.slots 的顺序从 heap 中取出的值，没有的为 None
+    pub observer: Option<Box<dyn Observer>>, // 设置后每条指令执行前后都会回调，用于记录执行过程
+    pub limits: Limits,
+    pub instructions: usize, // 本次执行已经执行的指令数，每次执行公式前清零
 }
 
 impl RuntimeContext {
     pub fn new() -> Self {
         RuntimeContext {
             heap: HashMap::new(),
+            locals: HashMap::new(),
             function_table: HashMap::new(),
             value_stack: Vec::new(),
+            null_mode: NullMode::Propagate,
+            null_filter: false,
+            program: None,
+            slots: Vec::new(),
+            observer: None,
+            limits: Limits::default(),
+            instructions: 0,
+        }
+    }
+
+    // 执行 Program 之前把标识符解析成槽位，执行时不再按名字查找
+    pub fn load(&mut self, program: &Arc<Program>) {
+        // let 绑定的槽位在绑定之前没有值，不读取 heap 中同名的字段
+        let stored = program
+            .operators
+            .iter()
+            .filter_map(|code| match code {
+                OperatorCode::StoreSlot(index) => Some(*index as usize),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        self.slots = program
+            .slots
+            .iter()
+            .enumerate()
+            .map(|(index, name)| match stored.contains(&index) {
+                true => None,
+                false => self.heap.get(name).cloned(),
+            })
+            .collect();
+        self.program = Some(program.clone());
+    }
+
+    // 回调时暂时取出 observer，observer 可以读取整个 ctx
+    pub fn observe(&mut self, callback: impl FnOnce(&mut dyn Observer, &RuntimeContext)) {
+        if let Some(mut observer) = self.observer.take() {
+            callback(observer.as_mut(), self);
+            self.observer = Some(observer);
         }
     }
 
+    // 每条指令执行前计数
+    pub fn count_instruction(&mut self) -> Result<(), ExecuteError> {
+        self.instructions += 1;
+        match self.instructions > self.limits.max_instructions {
+            true => Err(ExecuteError::instruction_limit_exceeded(
+                self.limits.max_instructions,
+            )),
+            false => Ok(()),
+        }
+    }
+
+    // 每条指令执行后检查栈的深度和栈顶的值，数组和文本只能由指令产生，检查栈顶就足够
+    pub fn check_limits(&self) -> Result<(), ExecuteError> {
+        let limits = &self.limits;
+        if self.value_stack.len() > limits.max_stack_depth {
+            return Err(ExecuteError::stack_limit_exceeded(limits.max_stack_depth));
+        }
+        match self.value_stack.last() {
+            Some(Value::Array(items)) if items.len() > limits.max_array_length => Err(
+                ExecuteError::array_length_limit_exceeded(limits.max_array_length, items.len()),
+            ),
+            Some(Value::String(text)) if text.len() > limits.max_string_length => Err(
+                ExecuteError::string_length_limit_exceeded(limits.max_string_length, text.len()),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn propagates_null(&self) -> bool {
+        self.null_mode != NullMode::Strict
+    }
+
+    // 字节码不合法时返回 StackUnderflow，不会 panic
+    pub fn pop(&mut self) -> Result<Value, ExecuteError> {
+        self.value_stack
+            .pop()
+            .ok_or_else(ExecuteError::stack_underflow)
+    }
+
+    // 按入栈的顺序弹出栈顶的 count 个值
+    pub fn pop_many(&mut self, count: usize) -> Result<Vec<Value>, ExecuteError> {
+        let at = self
+            .value_stack
+            .len()
+            .checked_sub(count)
+            .ok_or_else(ExecuteError::stack_underflow)?;
+        Ok(self.value_stack.split_off(at))
+    }
+
     pub fn reset_stack(&mut self) {
         self.value_stack.clear();
     }
@@ -29,6 +157,18 @@ impl RuntimeContext {
     pub fn inject_functions(&mut self) {
         self.set("SUM".to_string(), Value::Function("sum".to_string()));
         self.set("COUNT".to_string(), Value::Function("count".to_string()));
+        self.set("sum".to_string(), Value::Function("sum".to_string()));
+        self.set("count".to_string(), Value::Function("count".to_string()));
+        self.set("where".to_string(), Value::Function("where".to_string()));
+        self.set("in".to_string(), Value::Function("in".to_string()));
+        self.set("date".to_string(), Value::Function("date".to_string()));
+        self.set("length".to_string(), Value::Function("length".to_string()));
+        self.set(
+            "substring".to_string(),
+            Value::Function("substring".to_string()),
+        );
+        self.set("upper".to_string(), Value::Function("upper".to_string()));
+        self.set("lower".to_string(), Value::Function("lower".to_string()));
     }
 
     pub fn get(&self, key: &String) -> Option<&Value> {
@@ -0,0 +1,129 @@
+use alloc::{string::String, vec::Vec};
+
+use super::{
+    error::ExecuteError,
+    trace::{Trace, TraceStep},
+    value::Value,
+};
+use crate::parse::ast::Range;
+
+// 在记录好的执行过程上单步调试，公式的执行没有副作用，回放和重新执行的结果相同
+// 指令在 lambda 中时 Rust 的调用栈还在函数里，不能暂停，所以先完整执行一遍再回放
+pub struct Debugger {
+    trace: Trace,
+    next: usize, // 下一步的序号，0 表示还没有开始
+    breakpoints: Vec<Range>,
+}
+
+impl Debugger {
+    pub fn new(trace: Trace) -> Self {
+        Self {
+            trace,
+            next: 0,
+            breakpoints: Vec::new(),
+        }
+    }
+
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    pub fn result(&self) -> &Result<Value, ExecuteError> {
+        &self.trace.result
+    }
+
+    // 源码位置和断点有重叠的指令会停下，空的断点表示光标所在的位置
+    pub fn add_breakpoint(&mut self, range: Range) {
+        if !self.breakpoints.contains(&range) {
+            self.breakpoints.push(range);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, range: &Range) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint != range);
+        self.breakpoints.len() != count
+    }
+
+    pub fn breakpoints(&self) -> &[Range] {
+        &self.breakpoints
+    }
+
+    // 回到开始，断点保留
+    pub fn restart(&mut self) {
+        self.next = 0;
+    }
+
+    // 执行下一条指令，已经执行完时返回 None
+    pub fn step(&mut self) -> Option<&TraceStep> {
+        if self.next >= self.trace.steps.len() {
+            return None;
+        }
+        self.next += 1;
+        self.current()
+    }
+
+    // 一直执行到断点，没有遇到断点时停在最后一条指令之后并返回 None
+    pub fn resume(&mut self) -> Option<&TraceStep> {
+        while self.next < self.trace.steps.len() {
+            self.next += 1;
+            if self.at_breakpoint() {
+                return self.current();
+            }
+        }
+        None
+    }
+
+    // 最近执行的指令
+    pub fn current(&self) -> Option<&TraceStep> {
+        self.next
+            .checked_sub(1)
+            .and_then(|index| self.trace.steps.get(index))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.trace.steps.len()
+    }
+
+    // 当前的值栈，栈顶在最后
+    pub fn stack(&self) -> &[Value] {
+        self.current()
+            .map(|step| step.stack.as_slice())
+            .unwrap_or_default()
+    }
+
+    // 当前可以访问的变量：读取的字段、已经执行的 let 和 lambda 中的 $
+    pub fn heap(&self) -> Vec<(String, Value)> {
+        let mut heap = self.trace.inputs.clone();
+        let stores = self.trace.steps[..self.next]
+            .iter()
+            .filter_map(|step| step.store.as_ref());
+        for (name, value) in stores {
+            match heap.iter_mut().find(|(key, _)| key == name) {
+                Some(entry) => entry.1 = value.clone(),
+                None => heap.push((name.clone(), value.clone())),
+            }
+        }
+        if let Some(item) = self.current().and_then(|step| step.item.as_ref()) {
+            heap.push(("$".into(), item.clone()));
+        }
+        heap
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        let range = match self.current().and_then(|step| step.range.as_ref()) {
+            Some(range) => range,
+            None => return false,
+        };
+        self.breakpoints
+            .iter()
+            .any(|breakpoint| overlaps(range, breakpoint))
+    }
+}
+
+fn overlaps(range: &Range, breakpoint: &Range) -> bool {
+    match breakpoint.0 == breakpoint.1 {
+        true => range.0 <= breakpoint.0 && breakpoint.0 < range.1,
+        false => range.0 < breakpoint.1 && breakpoint.0 < range.1,
+    }
+}
@@ -0,0 +1,173 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use super::verifier::stack_effect;
+use crate::{
+    parse::source_map::SourceMap,
+    share::{
+        operator::OperatorCode,
+        program::{Constant, Program},
+    },
+};
+
+// 反汇编 Program，排查线上公式的结果时查看编译器实际生成的指令
+// 每行依次是指令序号、指令和解析后的常量、槽位、执行后栈的深度、来源的源码位置
+// source 是编译时的公式，传入时在位置后面显示对应的源码
+pub fn disassemble(program: &Program, source: Option<&str>) -> String {
+    let mut lines = Vec::new();
+    lines.push(format!(
+        "; compiler {}, result {}",
+        match program.compiler_version.as_str() {
+            "" => "unknown",
+            version => version,
+        },
+        program
+            .result_type
+            .as_ref()
+            .map(|result_type| result_type.to_string())
+            .unwrap_or_else(|| "unknown".to_string())
+    ));
+    for (index, constant) in program.constants.iter().enumerate() {
+        lines.push(format!(
+            "; constant {} = {}",
+            index,
+            constant_text(constant)
+        ));
+    }
+    for (index, slot) in program.slots.iter().enumerate() {
+        lines.push(format!("; slot {} = {}", index, slot));
+    }
+    if !program.dependencies.is_empty() {
+        lines.push(format!(
+            "; dependencies: {}",
+            program.dependencies.join(", ")
+        ));
+    }
+    lines.push(format!(
+        "{:>6}  {:<40} {:>5}  source",
+        "#", "instruction", "depth"
+    ));
+
+    Disassembler { program, source }.lines(&program.operators, &program.source_map, "", &mut lines);
+    lines.join("\n")
+}
+
+// 单条指令的文本，和反汇编中的指令列相同
+pub fn instruction(program: &Program, pc: usize, code: &OperatorCode) -> String {
+    Disassembler {
+        program,
+        source: None,
+    }
+    .instruction(pc, code)
+}
+
+struct Disassembler<'a> {
+    program: &'a Program,
+    source: Option<&'a str>,
+}
+
+impl Disassembler<'_> {
+    // lambda 的指令缩进显示，序号带上 PushLambda 的序号，例如 3.0
+    fn lines(
+        &self,
+        codes: &[OperatorCode],
+        source_map: &SourceMap,
+        prefix: &str,
+        lines: &mut Vec<String>,
+    ) {
+        let mut depth = Some(0usize);
+        let mut lambdas = source_map.lambdas.iter();
+
+        for (pc, code) in codes.iter().enumerate() {
+            let (pops, pushes) = stack_effect(code);
+            // 不跳转时的深度，指令不合法导致栈下溢时显示 -
+            depth = depth
+                .and_then(|depth| depth.checked_sub(pops))
+                .map(|depth| depth + pushes);
+
+            let number = format!("{}{}", prefix, pc);
+            let source = match source_map.range(pc) {
+                Some(range) => {
+                    let text = self
+                        .source
+                        .and_then(|source| source.get(range.0..range.1))
+                        .map(|text| text.replace('\n', " "))
+                        .unwrap_or_default();
+                    format!("{}..{}  {}", range.0, range.1, text)
+                }
+                None => String::new(),
+            };
+            lines.push(
+                format!(
+                    "{:>6}  {:<40} {:>5}  {}",
+                    number,
+                    self.instruction(pc, code),
+                    depth
+                        .map(|depth| depth.to_string())
+                        .unwrap_or("-".to_string()),
+                    source
+                )
+                .trim_end()
+                .to_string(),
+            );
+
+            if let OperatorCode::PushLambda(lambda) = code {
+                let lambda_map = lambdas.next().cloned().unwrap_or_default();
+                self.lines(lambda, &lambda_map, &format!("  {}.", number.trim()), lines);
+            }
+        }
+    }
+
+    fn instruction(&self, pc: usize, code: &OperatorCode) -> String {
+        let constant = |index: &u32| match self.program.constants.get(*index as usize) {
+            Some(constant) => constant_text(constant),
+            None => "<invalid constant>".to_string(),
+        };
+        let name = |index: &u32| match self.program.constants.get(*index as usize) {
+            Some(Constant::String(name)) => name.clone(),
+            _ => "<invalid constant>".to_string(),
+        };
+        let slot = |index: &u32| match self.program.slots.get(*index as usize) {
+            Some(slot) => slot.clone(),
+            None => "<invalid slot>".to_string(),
+        };
+
+        match code {
+            OperatorCode::PushConstant(index) => {
+                format!("PushConstant({})  {}", index, constant(index))
+            }
+            OperatorCode::LoadSlot(index) => format!("LoadSlot({})  {}", index, slot(index)),
+            OperatorCode::StoreSlot(index) => format!("StoreSlot({})  {}", index, slot(index)),
+            OperatorCode::LoadProperty(index) => {
+                format!("LoadProperty({})  .{}", index, name(index))
+            }
+            OperatorCode::Filter(left, op, value) => format!(
+                "Filter({}, {}, {})  {}{}{}",
+                left,
+                op,
+                value,
+                name(left),
+                name(op),
+                name(value)
+            ),
+            OperatorCode::SkipIfNull(count) => {
+                format!("SkipIfNull({})  -> {}", count, pc + 1 + *count as usize)
+            }
+            OperatorCode::Coalesce(count) => {
+                format!("Coalesce({})  -> {}", count, pc + 1 + *count as usize)
+            }
+            OperatorCode::PushLambda(lambda) => format!("PushLambda({})", lambda.len()),
+            code => format!("{:?}", code),
+        }
+    }
+}
+
+fn constant_text(constant: &Constant) -> String {
+    match constant {
+        Constant::Number(value) => value.to_string(),
+        Constant::String(value) => format!("{:?}", value),
+    }
+}
@@ -3,9 +3,12 @@ use alloc::{
     string::{String, ToString},
     vec::Vec,
 };
+use serde::{Deserialize, Serialize};
 
-// 数值相关错误
-#[derive(Clone, Debug, Copy, PartialEq)]
+use crate::parse::ast::Range;
+
+// 数值相关错误，Throw 指令会保存错误类型，新的类型只能加在最后
+#[derive(Serialize, Deserialize, Clone, Debug, Copy, PartialEq)]
 pub enum ExecuteErrorType {
     UnknownError,
 
@@ -19,6 +22,7 @@ pub enum ExecuteErrorType {
     PowNotRational,
     FactorialNotInteger,
     FactorialNotNegative,
+    OperandNotInteger, // 整除、移位、位运算的操作数必须是整数
 
     IdentifierNotFound,
 
@@ -31,12 +35,46 @@ pub enum ExecuteErrorType {
 
     DotInputNotAObjectArray,
     DotNotFountProperty,
+
+    IndexInputNotArray, // 只有数组可以使用下标和切片
+    IndexNotInteger,
+
+    InvalidDate, // 文本不是 ISO 8601 格式的日期，或者日期不存在
+
+    StackUnderflow,  // 指令需要的值比栈中的多，只有不合法的字节码会出现
+    InvalidBytecode, // 执行前的检查发现字节码不合法
+
+    // 超过 RuntimeContext.limits
+    InstructionLimitExceeded,
+    StackLimitExceeded,
+    ArrayLengthLimitExceeded,
+    StringLengthLimitExceeded,
+    FactorialLimitExceeded,
+
+    NumberOverflow, // 结果的分子或者分母超出 i64
+
+    NoResultExpression, // 公式只有 let 语句，没有作为结果的表达式
+}
+
+impl ExecuteErrorType {
+    // 超过资源限制的错误和执行时的设置有关，不能在编译期折叠
+    pub fn is_limit(&self) -> bool {
+        matches!(
+            self,
+            ExecuteErrorType::InstructionLimitExceeded
+                | ExecuteErrorType::StackLimitExceeded
+                | ExecuteErrorType::ArrayLengthLimitExceeded
+                | ExecuteErrorType::StringLengthLimitExceeded
+                | ExecuteErrorType::FactorialLimitExceeded
+        )
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ExecuteError {
     pub type_: ExecuteErrorType,
     pub message: Option<String>,
+    pub range: Option<Range>, // 出错的子表达式的源码位置，只有执行带 SourceMap 的 Program 时才有
 }
 
 impl ExecuteError {
@@ -44,6 +82,7 @@ impl ExecuteError {
         Self {
             type_,
             message: None,
+            range: None,
         }
     }
 
@@ -53,8 +92,19 @@ impl ExecuteError {
 
     pub fn with_message(self, msg: String) -> Self {
         Self {
-            type_: self.type_,
             message: Some(msg),
+            ..self
+        }
+    }
+
+    // 已经有位置的不覆盖，lambda 中的错误保留 lambda 中的位置，而不是调用函数的位置
+    pub fn with_range(self, range: Option<&Range>) -> Self {
+        match self.range {
+            Some(_) => self,
+            None => Self {
+                range: range.cloned(),
+                ..self
+            },
         }
     }
 
@@ -87,11 +137,23 @@ impl ExecuteError {
         Self::new(ExecuteErrorType::FactorialNotNegative)
     }
 
+    pub fn operand_not_integer(operator: &str, actual: String) -> Self {
+        Self::new(ExecuteErrorType::OperandNotInteger).with_message(format!(
+            "{} expect integer operands, actual: {}",
+            operator, actual
+        ))
+    }
+
     pub fn result_count_mismatch(actual: usize) -> Self {
         Self::new(ExecuteErrorType::ResultCountMismatchError)
             .with_message(format!("result count expect 1, actual: {}", actual))
     }
 
+    pub fn no_result_expression() -> Self {
+        Self::new(ExecuteErrorType::NoResultExpression)
+            .with_message("formula has no result expression".to_string())
+    }
+
     pub fn identifier_not_found(identifier: &String) -> Self {
         Self::new(ExecuteErrorType::IdentifierNotFound)
             .with_message(format!("identifier not found: {}", identifier))
@@ -118,7 +180,7 @@ impl ExecuteError {
         ))
     }
 
-    pub fn dot_input_not_object_array(property: &String) -> Self {
+    pub fn dot_input_not_object_array(property: &str) -> Self {
         Self::new(ExecuteErrorType::DotInputNotAObjectArray)
             .with_message(format!("when read property ({})", property))
     }
@@ -131,4 +193,68 @@ impl ExecuteError {
     pub fn filter_argument_not_a_number() -> Self {
         Self::new(ExecuteErrorType::FilterArgumentNotANumber)
     }
+
+    pub fn index_input_not_array(actual: &str) -> Self {
+        Self::new(ExecuteErrorType::IndexInputNotArray)
+            .with_message(format!("index expect Array, actual: {}", actual))
+    }
+
+    pub fn invalid_date(text: &str) -> Self {
+        Self::new(ExecuteErrorType::InvalidDate).with_message(format!("invalid date: {}", text))
+    }
+
+    pub fn stack_underflow() -> Self {
+        Self::new(ExecuteErrorType::StackUnderflow)
+    }
+
+    pub fn invalid_bytecode(position: usize, reason: String) -> Self {
+        Self::new(ExecuteErrorType::InvalidBytecode)
+            .with_message(format!("invalid bytecode at {}: {}", position, reason))
+    }
+
+    pub fn instruction_limit_exceeded(limit: usize) -> Self {
+        Self::new(ExecuteErrorType::InstructionLimitExceeded)
+            .with_message(format!("more than {} instructions executed", limit))
+    }
+
+    pub fn stack_limit_exceeded(limit: usize) -> Self {
+        Self::new(ExecuteErrorType::StackLimitExceeded)
+            .with_message(format!("stack depth exceeds {}", limit))
+    }
+
+    pub fn array_length_limit_exceeded(limit: usize, actual: usize) -> Self {
+        Self::new(ExecuteErrorType::ArrayLengthLimitExceeded).with_message(format!(
+            "array length expect at most {}, actual: {}",
+            limit, actual
+        ))
+    }
+
+    pub fn string_length_limit_exceeded(limit: usize, actual: usize) -> Self {
+        Self::new(ExecuteErrorType::StringLengthLimitExceeded).with_message(format!(
+            "string length expect at most {}, actual: {}",
+            limit, actual
+        ))
+    }
+
+    pub fn factorial_limit_exceeded(limit: i64, actual: &str) -> Self {
+        Self::new(ExecuteErrorType::FactorialLimitExceeded).with_message(format!(
+            "factorial expect at most {}, actual: {}",
+            limit, actual
+        ))
+    }
+
+    pub fn shift_out_of_range(count: i64) -> Self {
+        Self::new(ExecuteErrorType::NumberOverflow)
+            .with_message(format!("shift count {} out of range 0..64", count))
+    }
+
+    pub fn number_overflow() -> Self {
+        Self::new(ExecuteErrorType::NumberOverflow)
+            .with_message("numerator or denominator exceeds 64 bits".to_string())
+    }
+
+    pub fn index_not_integer(actual: &str) -> Self {
+        Self::new(ExecuteErrorType::IndexNotInteger)
+            .with_message(format!("index expect integer, actual: {}", actual))
+    }
 }
@@ -1,7 +1,8 @@
 use alloc::{string::String, vec, vec::Vec};
 use num::{Rational64, Zero};
 
-use super::{error::ExecuteError, value::Value};
+use super::{context::RuntimeContext, error::ExecuteError, runner::Runner, value::Value};
+use crate::share::{date::parse_date, operator::OperatorCode};
 
 pub trait RuntimeFunction {
     fn run(&self, args: &Vec<Value>) -> Result<Value, ExecuteError>;
@@ -74,10 +75,148 @@ impl RuntimeFunction for CountFunction {
     }
 }
 
-pub fn run_runtime_function(name: &String, args: &Vec<Value>) -> Result<Value, ExecuteError> {
+pub fn run_runtime_function(
+    name: &String,
+    args: &Vec<Value>,
+    ctx: &mut RuntimeContext,
+) -> Result<Value, ExecuteError> {
     match name.as_str() {
-        "sum" => SumFunction.run(args),
-        "count" => CountFunction.run(args),
-        _ => Err(ExecuteError::function_not_found(&name)),
+        "sum" => SumFunction.run(&map_lambda(args, ctx)?),
+        "count" => CountFunction.run(&map_lambda(args, ctx)?),
+        "where" => where_function(args, ctx),
+        "in" => in_function(args),
+        "date" => date_function(args),
+        "length" => length_function(args),
+        "substring" => substring_function(args),
+        "upper" => case_function(args, str::to_uppercase),
+        "lower" => case_function(args, str::to_lowercase),
+        _ => Err(ExecuteError::function_not_found(name)),
+    }
+}
+
+// sum(subtask, $.estimatePoint) 先对每个元素执行 lambda，相当于 sum(subtask.estimatePoint)
+fn map_lambda(args: &Vec<Value>, ctx: &mut RuntimeContext) -> Result<Vec<Value>, ExecuteError> {
+    match args.as_slice() {
+        [Value::Array(items), Value::Lambda(lambda)] => {
+            // where 条件中的 sum(..., $.a == null) 仍然按普通的比较计算
+            let outer = core::mem::replace(&mut ctx.null_filter, false);
+            let result = items
+                .iter()
+                .map(|item| Runner.call_lambda(lambda, item.clone(), ctx))
+                .collect::<Result<Vec<_>, _>>();
+            ctx.null_filter = outer;
+            Ok(vec![Value::Array(result?)])
+        }
+        _ => Ok(args.clone()),
+    }
+}
+
+// where(subtask, $.status == 2) 保留 lambda 结果为 true 的元素
+// 条件中和 Null 比较的结果都是 false，$.status != 2 不会保留 status 为 Null 的元素
+fn where_function(args: &Vec<Value>, ctx: &mut RuntimeContext) -> Result<Value, ExecuteError> {
+    match args.as_slice() {
+        [Value::Array(items), Value::Lambda(lambda)] => {
+            let outer = core::mem::replace(&mut ctx.null_filter, true);
+            let result = where_items(items, lambda, ctx);
+            ctx.null_filter = outer;
+            result
+        }
+        _ => Err(ExecuteError::function_invalid_argument(
+            vec!["Array", "Lambda"],
+            args.iter().map(|a| a.get_type()).collect(),
+        )),
+    }
+}
+
+fn where_items(
+    items: &[Value],
+    lambda: &[OperatorCode],
+    ctx: &mut RuntimeContext,
+) -> Result<Value, ExecuteError> {
+    let mut result = Vec::new();
+    for item in items {
+        match Runner.call_lambda(lambda, item.clone(), ctx)? {
+            Value::Bool(true) => result.push(item.clone()),
+            Value::Bool(false) | Value::Null => {}
+            value => {
+                return Err(ExecuteError::function_invalid_argument(
+                    vec!["Bool"],
+                    vec![value.get_type()],
+                ))
+            }
+        }
+    }
+    Ok(Value::Array(result))
+}
+
+// in(status, [1, 2, 4])，和 == 一样，Null 只等于 Null
+fn in_function(args: &Vec<Value>) -> Result<Value, ExecuteError> {
+    match args.as_slice() {
+        [value, Value::Array(items)] => Ok(Value::Bool(items.contains(value))),
+        _ => Err(ExecuteError::function_invalid_argument(
+            vec!["Any", "Array"],
+            args.iter().map(|a| a.get_type()).collect(),
+        )),
+    }
+}
+
+// date(text)，参数是字符串字面量时已经在编译时计算，这里处理来自字段的文本
+// 结果和日期字面量一样是毫秒时间戳数字
+fn date_function(args: &Vec<Value>) -> Result<Value, ExecuteError> {
+    match args.as_slice() {
+        [Value::String(text)] => match parse_date(text) {
+            Some(value) => Ok(Value::Number(Rational64::from_integer(value as i64))),
+            None => Err(ExecuteError::invalid_date(text)),
+        },
+        [Value::Null] => Ok(Value::Null),
+        _ => Err(ExecuteError::function_invalid_argument(
+            vec!["String"],
+            args.iter().map(|a| a.get_type()).collect(),
+        )),
+    }
+}
+
+// 字符串函数都按 Unicode 字符计算，'中文'.length 是 2 而不是 UTF-8 的字节数 6
+fn length_function(args: &Vec<Value>) -> Result<Value, ExecuteError> {
+    match args.as_slice() {
+        [Value::String(text)] => Ok(Value::Number((text.chars().count() as i64).into())),
+        [Value::Null] => Ok(Value::Null),
+        _ => Err(ExecuteError::function_invalid_argument(
+            vec!["String"],
+            args.iter().map(|a| a.get_type()).collect(),
+        )),
+    }
+}
+
+// substring(text, start, end)，和切片一样包含 start、不包含 end，负数从末尾开始，end 可以省略
+fn substring_function(args: &Vec<Value>) -> Result<Value, ExecuteError> {
+    let (text, start, end) = match args.as_slice() {
+        [Value::Null, ..] => return Ok(Value::Null),
+        [Value::String(text), start @ Value::Number(_)] => (text, start, None),
+        [Value::String(text), start @ Value::Number(_), end @ Value::Number(_)] => {
+            (text, start, Some(end.clone()))
+        }
+        _ => {
+            return Err(ExecuteError::function_invalid_argument(
+                vec!["String", "Number", "Number"],
+                args.iter().map(|a| a.get_type()).collect(),
+            ))
+        }
+    };
+
+    let (start, end) = Value::slice_range(text.chars().count(), Some(start.clone()), end)?;
+    Ok(Value::String(
+        text.chars().skip(start).take(end - start).collect(),
+    ))
+}
+
+fn case_function(args: &Vec<Value>, convert: fn(&str) -> String) -> Result<Value, ExecuteError> {
+    match args.as_slice() {
+        [Value::String(text)] => Ok(Value::String(convert(text))),
+        [Value::Null] => Ok(Value::Null),
+        _ => Err(ExecuteError::function_invalid_argument(
+            vec!["String"],
+            args.iter().map(|a| a.get_type()).collect(),
+        )),
     }
 }
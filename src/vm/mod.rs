@@ -2,4 +2,10 @@ pub mod runner;
 pub mod value;
 pub mod error;
 pub mod function;
-pub mod context;
\ No newline at end of file
+pub mod context;
+pub mod verifier;
+pub mod optimizer;
+pub mod disassembler;
+pub mod trace;
+pub mod debugger;
+pub mod number;
\ No newline at end of file
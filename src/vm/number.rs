@@ -0,0 +1,57 @@
+use num::{BigInt, BigRational, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Rational64};
+
+use super::error::ExecuteError;
+use crate::share::decimal::from_big;
+
+// Rational64 的运算，溢出时用 BigRational 重新计算
+// 约分后能放回 Rational64 的使用约分后的结果，例如分母不断变大的小数求和，否则报 NumberOverflow
+pub fn add(a: &Rational64, b: &Rational64) -> Result<Rational64, ExecuteError> {
+    checked(a.checked_add(b), || big(a) + big(b))
+}
+
+pub fn sub(a: &Rational64, b: &Rational64) -> Result<Rational64, ExecuteError> {
+    checked(a.checked_sub(b), || big(a) - big(b))
+}
+
+pub fn mul(a: &Rational64, b: &Rational64) -> Result<Rational64, ExecuteError> {
+    checked(a.checked_mul(b), || big(a) * big(b))
+}
+
+// 调用方先检查除数不为 0
+pub fn div(a: &Rational64, b: &Rational64) -> Result<Rational64, ExecuteError> {
+    checked(a.checked_div(b), || big(a) / big(b))
+}
+
+// Rational64 的取余没有检查溢出，只有整数直接计算
+pub fn rem(a: &Rational64, b: &Rational64) -> Result<Rational64, ExecuteError> {
+    let fast = match a.is_integer() && b.is_integer() {
+        true => a
+            .numer()
+            .checked_rem(*b.numer())
+            .map(Rational64::from_integer),
+        false => None,
+    };
+    checked(fast, || big(a) % big(b))
+}
+
+pub fn neg(a: &Rational64) -> Result<Rational64, ExecuteError> {
+    let fast = a
+        .numer()
+        .checked_neg()
+        .map(|numer| Rational64::new_raw(numer, *a.denom()));
+    checked(fast, || -big(a))
+}
+
+fn checked(
+    fast: Option<Rational64>,
+    exact: impl FnOnce() -> BigRational,
+) -> Result<Rational64, ExecuteError> {
+    match fast {
+        Some(result) => Ok(result),
+        None => from_big(&exact()).ok_or_else(ExecuteError::number_overflow),
+    }
+}
+
+fn big(value: &Rational64) -> BigRational {
+    BigRational::new_raw(BigInt::from(*value.numer()), BigInt::from(*value.denom()))
+}
@@ -0,0 +1,428 @@
+use alloc::{string::String, vec, vec::Vec};
+
+use super::{context::RuntimeContext, runner::Runnable, value::Value, verifier::stack_effect};
+use crate::{
+    parse::{ast::Range, source_map::SourceMap},
+    share::operator::OperatorCode,
+};
+
+// 结果一定是数字的函数，length 等遇到 Null 返回 Null，不能算在内
+const NUMBER_FUNCTIONS: [&str; 4] = ["sum", "SUM", "count", "COUNT"];
+
+// 编译期优化：折叠常量子表达式、化简 x * 1、x + 0 这样的恒等式、去掉不会执行的 ?? 右边和 ?. 访问
+// 折叠时用 VM 计算，出错的表达式换成 Throw，执行到这里才报错，和不优化时的行为一致
+// 指令不合法（例如栈下溢）时原样返回，由执行前的检查报错
+pub fn optimize(operators: Vec<OperatorCode>) -> Vec<OperatorCode> {
+    let source_map = SourceMap::filled(&operators, &Range(0, 0));
+    optimize_with_source_map(operators, source_map).0
+}
+
+// 同时调整每条指令的源码位置，折叠后的指令对应所有参与计算的表达式
+pub fn optimize_with_source_map(
+    operators: Vec<OperatorCode>,
+    source_map: SourceMap,
+) -> (Vec<OperatorCode>, SourceMap) {
+    let mut locals = Vec::new();
+    collect_locals(&operators, &mut locals);
+    let source_map = match source_map.ranges.len() == operators.len() {
+        true => source_map,
+        false => SourceMap::filled(&operators, &Range(0, 0)),
+    };
+    match (Optimizer { locals }).optimize(&operators, &source_map) {
+        Some(output) => output.finish(),
+        None => (operators, source_map),
+    }
+}
+
+fn collect_locals(codes: &[OperatorCode], locals: &mut Vec<String>) {
+    for code in codes {
+        match code {
+            OperatorCode::StoreLocal(name) => locals.push(name.clone()),
+            OperatorCode::PushLambda(lambda) => collect_locals(lambda, locals),
+            _ => {}
+        }
+    }
+}
+
+// 编译期对栈中每个值的了解
+#[derive(Clone, Debug)]
+enum Entry {
+    Const(Value, usize), // 常量和压入它的指令在输出中的位置
+    Number,              // 不知道值，但一定是数字
+    Function,            // 内置函数，Call 时判断结果是否一定是数字
+    Dynamic,
+}
+
+impl Entry {
+    fn is_number(&self) -> bool {
+        matches!(self, Entry::Number | Entry::Const(Value::Number(_), _))
+    }
+
+    fn is_const(&self, value: i64) -> bool {
+        match self {
+            Entry::Const(Value::Number(number), _) => *number == value.into(),
+            _ => false,
+        }
+    }
+}
+
+// 优化后的指令，源码位置和指令一起增删
+#[derive(Default)]
+struct Output {
+    codes: Vec<OperatorCode>,
+    ranges: Vec<Range>,
+    lambdas: Vec<SourceMap>,
+}
+
+impl Output {
+    fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    fn push(&mut self, code: OperatorCode, range: Range) {
+        self.codes.push(code);
+        self.ranges.push(range);
+    }
+
+    fn pop(&mut self) {
+        self.codes.pop();
+        self.ranges.pop();
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.codes.remove(index);
+        self.ranges.remove(index);
+    }
+
+    // 删除 first 之后的指令，返回它们和 range 一起覆盖的源码范围
+    fn truncate(&mut self, first: usize, range: &Range) -> Range {
+        let span = self.ranges[first..]
+            .iter()
+            .fold(range.clone(), |span, item| {
+                Range(span.0.min(item.0), span.1.max(item.1))
+            });
+        self.codes.truncate(first);
+        self.ranges.truncate(first);
+        span
+    }
+
+    fn finish(self) -> (Vec<OperatorCode>, SourceMap) {
+        let source_map = SourceMap {
+            ranges: self.ranges,
+            lambdas: self.lambdas,
+        };
+        (self.codes, source_map)
+    }
+}
+
+struct Optimizer {
+    locals: Vec<String>, // let 绑定的名字，可能覆盖同名的函数
+}
+
+impl Optimizer {
+    fn optimize(&self, codes: &[OperatorCode], source_map: &SourceMap) -> Option<Output> {
+        let mut output = Output::default();
+        // 下一个 PushLambda 在 source_map.lambdas 中的位置
+        let mut lambdas = source_map.lambdas.iter();
+        let mut stack: Vec<Entry> = Vec::new();
+        // 原来每条指令在输出中的位置，最后用来修正跳转的条数
+        let mut positions = vec![0; codes.len() + 1];
+        // 输出中跳转指令的位置和原来的跳转目标
+        let mut jumps: Vec<(usize, usize)> = Vec::new();
+        // 跳转指令和跳转目标之前的指令不能再删除，否则跳转的条数会错
+        let mut barrier = 0;
+
+        let mut pc = 0;
+        while pc < codes.len() {
+            positions[pc] = output.len();
+            if jumps.iter().any(|(_, target)| *target == pc) {
+                // 两条路径到达这里时栈中的值可能不同
+                stack.iter_mut().for_each(|entry| *entry = Entry::Dynamic);
+                barrier = output.len();
+            }
+
+            let code = &codes[pc];
+            let range = source_map.ranges[pc].clone();
+            pc += 1;
+            let (pops, _) = stack_effect(code);
+            if stack.len() < pops {
+                return None;
+            }
+
+            match code {
+                OperatorCode::PushNumber(_)
+                | OperatorCode::PushString(_)
+                | OperatorCode::PushBool(_)
+                | OperatorCode::PushNull => {
+                    let mut ctx = RuntimeContext::new();
+                    code.run(&mut ctx).ok()?;
+                    stack.push(Entry::Const(ctx.pop().ok()?, output.len()));
+                    output.push(code.clone(), range);
+                }
+                OperatorCode::LoadIdentifier(name) => {
+                    stack.push(
+                        match NUMBER_FUNCTIONS.contains(&name.as_str())
+                            && !self.locals.contains(name)
+                        {
+                            true => Entry::Function,
+                            false => Entry::Dynamic,
+                        },
+                    );
+                    output.push(code.clone(), range);
+                }
+                OperatorCode::PushLambda(lambda) => {
+                    let lambda_map = lambdas
+                        .next()
+                        .filter(|lambda_map| lambda_map.ranges.len() == lambda.len())
+                        .cloned()
+                        .unwrap_or_else(|| SourceMap::filled(lambda, &range));
+                    let (lambda, lambda_map) = match self.optimize(lambda, &lambda_map) {
+                        Some(optimized) => optimized.finish(),
+                        None => (lambda.clone(), lambda_map),
+                    };
+                    stack.push(Entry::Dynamic);
+                    output.push(OperatorCode::PushLambda(lambda), range);
+                    output.lambdas.push(lambda_map);
+                }
+                OperatorCode::Coalesce(count) | OperatorCode::SkipIfNull(count) => {
+                    let skip = pc + *count as usize;
+                    if skip > codes.len() {
+                        return None;
+                    }
+                    let coalesce = matches!(code, OperatorCode::Coalesce(_));
+                    let top = stack.last().unwrap();
+                    match top {
+                        // ?? 左边一定是 Null，直接执行右边
+                        Entry::Const(Value::Null, start)
+                            if coalesce && *start >= barrier && *start + 1 == output.len() =>
+                        {
+                            output.pop();
+                            stack.pop();
+                        }
+                        // ?? 左边不是 Null，或者 ?. 左边是 Null，跳过的指令不会执行
+                        Entry::Const(value, _) if coalesce == (*value != Value::Null) => {
+                            for position in &mut positions[pc..skip] {
+                                *position = output.len();
+                            }
+                            for code in &codes[pc..skip] {
+                                if let OperatorCode::PushLambda(_) = code {
+                                    lambdas.next();
+                                }
+                            }
+                            pc = skip;
+                        }
+                        // ?. 左边不是 Null，继续访问
+                        Entry::Const(_, _) if !coalesce => {}
+                        _ => {
+                            if coalesce {
+                                stack.pop();
+                            } else {
+                                *stack.last_mut().unwrap() = Entry::Dynamic;
+                            }
+                            stack.iter_mut().for_each(|entry| *entry = Entry::Dynamic);
+                            jumps.push((output.len(), skip));
+                            output.push(code.clone(), range);
+                            barrier = output.len();
+                        }
+                    }
+                }
+                code => {
+                    if !self.simplify(code, &mut output, &mut stack, barrier) {
+                        self.fold(code, range, &mut output, &mut stack, barrier);
+                    }
+                }
+            }
+        }
+        positions[codes.len()] = output.len();
+
+        for (position, target) in jumps {
+            let count = (positions[target] - position - 1) as u32;
+            output.codes[position] = match output.codes[position] {
+                OperatorCode::Coalesce(_) => OperatorCode::Coalesce(count),
+                _ => OperatorCode::SkipIfNull(count),
+            };
+        }
+        Some(output)
+    }
+
+    // 化简 x * 1、1 * x、x / 1、x ^ 1、x + 0、0 + x、x - 0，x 必须一定是数字
+    // 否则 'a' + 0 是 'a0'，Null * 1 在严格模式下会报错，去掉运算会改变结果
+    fn simplify(
+        &self,
+        code: &OperatorCode,
+        output: &mut Output,
+        stack: &mut Vec<Entry>,
+        barrier: usize,
+    ) -> bool {
+        // 两边都是常量时由 fold 计算
+        let (lhs, rhs) = match stack.as_slice() {
+            [.., Entry::Const(_, _), Entry::Const(_, _)] => return false,
+            [.., lhs, rhs] => (lhs, rhs),
+            _ => return false,
+        };
+        let identity = match code {
+            OperatorCode::Add | OperatorCode::Subtract => 0,
+            OperatorCode::Multiply | OperatorCode::Divide | OperatorCode::Power => 1,
+            _ => return false,
+        };
+        let commutative = matches!(code, OperatorCode::Add | OperatorCode::Multiply);
+
+        match (lhs, rhs) {
+            (lhs, Entry::Const(_, start))
+                if lhs.is_number()
+                    && rhs.is_const(identity)
+                    && *start >= barrier
+                    && *start + 1 == output.len() =>
+            {
+                output.pop();
+                stack.pop();
+                true
+            }
+            (Entry::Const(_, start), rhs)
+                if commutative
+                    && rhs.is_number()
+                    && lhs.is_const(identity)
+                    && *start >= barrier =>
+            {
+                // start 之后没有跳转，删除这条指令不影响跳转的条数
+                output.remove(*start);
+                let len = stack.len();
+                stack.remove(len - 2);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // 参数都是常量时在编译期计算，否则只记录结果是否一定是数字
+    fn fold(
+        &self,
+        code: &OperatorCode,
+        range: Range,
+        output: &mut Output,
+        stack: &mut Vec<Entry>,
+        barrier: usize,
+    ) {
+        let (pops, pushes) = stack_effect(code);
+        let inputs = stack.split_off(stack.len() - pops);
+
+        if let Some(first) = self.foldable(code, &inputs, output.len(), barrier) {
+            let mut ctx = RuntimeContext::new();
+            for input in &inputs {
+                if let Entry::Const(value, _) = input {
+                    ctx.value_stack.push(value.clone());
+                }
+            }
+            let folded = match code.run(&mut ctx) {
+                Ok(()) => ctx.pop().ok().and_then(|value| {
+                    push_value(&value).map(|folded| (folded, Entry::Const(value, first)))
+                }),
+                Err(error) if error.type_.is_limit() => None,
+                Err(error) => Some((
+                    OperatorCode::Throw(error.type_, error.message),
+                    Entry::Dynamic,
+                )),
+            };
+            if let Some((folded, entry)) = folded {
+                let span = output.truncate(first, &range);
+                output.push(folded, span);
+                stack.push(entry);
+                return;
+            }
+        }
+
+        output.push(code.clone(), range);
+        if pushes == 1 {
+            stack.push(result_entry(code, &inputs));
+        }
+    }
+
+    // 可以折叠时返回第一个参数在输出中的位置，参数必须是连续的常量指令
+    fn foldable(
+        &self,
+        code: &OperatorCode,
+        inputs: &[Entry],
+        len: usize,
+        barrier: usize,
+    ) -> Option<usize> {
+        let pure = matches!(
+            code,
+            OperatorCode::Add
+                | OperatorCode::Subtract
+                | OperatorCode::Multiply
+                | OperatorCode::Divide
+                | OperatorCode::Modulo
+                | OperatorCode::Power
+                | OperatorCode::IntDivide
+                | OperatorCode::ShiftLeft
+                | OperatorCode::ShiftRight
+                | OperatorCode::BitAnd
+                | OperatorCode::BitOr
+                | OperatorCode::BitXor
+                | OperatorCode::Equal
+                | OperatorCode::NotEqual
+                | OperatorCode::GreaterThan
+                | OperatorCode::GreaterThanOrEqual
+                | OperatorCode::LessThan
+                | OperatorCode::LessThanOrEqual
+                | OperatorCode::Factorial
+                | OperatorCode::Negate
+                | OperatorCode::Concat(_)
+        );
+        if !pure || inputs.is_empty() || inputs.len() > len {
+            return None;
+        }
+
+        let first = len - inputs.len();
+        let constant = inputs.iter().enumerate().all(|(index, input)| match input {
+            // Null 参与运算的结果取决于执行时的 NullMode
+            Entry::Const(Value::Null, _) => matches!(code, OperatorCode::Concat(_)),
+            Entry::Const(_, start) => *start == first + index,
+            _ => false,
+        });
+        match constant && first >= barrier {
+            true => Some(first),
+            false => None,
+        }
+    }
+}
+
+// 运算结果是否一定是数字，用于化简恒等式
+fn result_entry(code: &OperatorCode, inputs: &[Entry]) -> Entry {
+    let numbers = inputs.iter().all(Entry::is_number);
+    match code {
+        OperatorCode::Add
+        | OperatorCode::Subtract
+        | OperatorCode::Multiply
+        | OperatorCode::Divide
+        | OperatorCode::Modulo
+        | OperatorCode::Power
+        | OperatorCode::Negate
+        | OperatorCode::Factorial
+        | OperatorCode::IntDivide
+        | OperatorCode::ShiftLeft
+        | OperatorCode::ShiftRight
+        | OperatorCode::BitAnd
+        | OperatorCode::BitOr
+        | OperatorCode::BitXor
+            if numbers =>
+        {
+            Entry::Number
+        }
+        OperatorCode::Call(_) => match inputs.first() {
+            Some(Entry::Function) => Entry::Number,
+            _ => Entry::Dynamic,
+        },
+        _ => Entry::Dynamic,
+    }
+}
+
+// 折叠的结果能用一条 Push 指令表示时才替换，数组、时长等保留原来的指令
+fn push_value(value: &Value) -> Option<OperatorCode> {
+    match value {
+        Value::Number(value) => Some(OperatorCode::PushNumber(*value)),
+        Value::String(value) => Some(OperatorCode::PushString(value.clone())),
+        Value::Bool(value) => Some(OperatorCode::PushBool(*value)),
+        _ => None,
+    }
+}
@@ -1,10 +1,26 @@
-use alloc::vec::Vec;
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 use num::Rational64;
 
 use super::{
-    context::RuntimeContext, error::ExecuteError, function::run_runtime_function, value::Value,
+    context::{NullMode, RuntimeContext},
+    error::{ExecuteError, ExecuteErrorType},
+    function::run_runtime_function,
+    trace::Step,
+    value::Value,
+    verifier::{verify, verify_program},
+};
+use crate::{
+    parse::{dependencies::let_names, source_map::SourceMap},
+    share::{
+        operator::OperatorCode,
+        program::{Constant, Program},
+    },
 };
-use crate::share::operator::OperatorCode;
 
 pub struct Runner;
 
@@ -17,10 +33,53 @@ impl Runner {
         if context.value_stack.len() > 0 {
             return Err(ExecuteError::stack_not_empty());
         }
+        context.locals = let_names(&operators)
+            .into_iter()
+            .map(|name| (name, None))
+            .collect();
+        context.instructions = 0;
+        // 没有 Program，lambda 出错时不能使用之前执行的 Program 的源码位置
+        context.program = None;
+        verify(&operators)?;
+        self.execute_all(&operators, None, None, context)
+    }
 
-        for operator in operators {
-            operator.run(context)?;
+    // 执行编译保存的 Program，标识符在执行前解析成槽位，出错时附上出错指令的源码位置
+    pub fn run_program(
+        &self,
+        program: &Arc<Program>,
+        context: &mut RuntimeContext,
+    ) -> Result<Value, ExecuteError> {
+        if !context.value_stack.is_empty() {
+            return Err(ExecuteError::stack_not_empty());
         }
+        // 没有结果时指向最后一条 let 语句
+        verify_program(program).map_err(|error| match error.type_ {
+            ExecuteErrorType::NoResultExpression => {
+                let last = program.operators.len().saturating_sub(1);
+                error.with_range(program.source_map.range(last))
+            }
+            _ => error,
+        })?;
+        context.load(program);
+        context.instructions = 0;
+        self.execute_all(
+            &program.operators,
+            Some(&program.source_map),
+            Some(program),
+            context,
+        )
+    }
+
+    fn execute_all(
+        &self,
+        operators: &[OperatorCode],
+        source_map: Option<&SourceMap>,
+        program: Option<&Program>,
+        context: &mut RuntimeContext,
+    ) -> Result<Value, ExecuteError> {
+        self.execute(operators, source_map, program, context)
+            .map_err(|(pc, error)| error.with_range(source_map.and_then(|map| map.range(pc))))?;
 
         match context.value_stack.len() {
             0 => return Err(ExecuteError::result_count_mismatch(0)),
@@ -30,52 +89,202 @@ impl Runner {
     }
 }
 
-pub trait Runnable {
-    fn run(&self, context: &mut RuntimeContext) -> Result<(), ExecuteError>;
-}
+impl Runner {
+    // 以 item 作为 $ 执行 lambda，使用独立的值栈，执行完恢复外层的 $
+    pub fn call_lambda(
+        &self,
+        lambda: &[OperatorCode],
+        item: Value,
+        ctx: &mut RuntimeContext,
+    ) -> Result<Value, ExecuteError> {
+        let outer_stack = core::mem::take(&mut ctx.value_stack);
+        let outer_item = ctx.heap.insert("$".to_string(), item);
 
-impl Runnable for OperatorCode {
-    fn run(&self, ctx: &mut RuntimeContext) -> Result<(), ExecuteError> {
-        match self {
-            OperatorCode::Add => {
-                let rhs = ctx.value_stack.pop().unwrap();
-                let lhs = ctx.value_stack.pop().unwrap();
+        // 查找 lambda 的源码位置比较慢，只在出错或者记录执行过程时查找
+        let program = ctx.program.clone();
+        let lambda_map = || {
+            program
+                .as_ref()
+                .and_then(|program| program.source_map.lambda(&program.operators, lambda))
+        };
+        let source_map = match ctx.observer {
+            Some(_) => lambda_map(),
+            None => None,
+        };
+        let mut result = self
+            .execute(lambda, source_map, program.as_deref(), ctx)
+            .map_err(|(pc, error)| {
+                let range = source_map.or_else(lambda_map).and_then(|map| map.range(pc));
+                error.with_range(range)
+            });
+        let value = ctx.value_stack.pop();
+        if result.is_ok() && !ctx.value_stack.is_empty() {
+            result = Err(ExecuteError::result_count_mismatch(
+                ctx.value_stack.len() + 1,
+            ));
+        }
 
-                ctx.value_stack.push(lhs.add(rhs)?);
-            }
-            OperatorCode::Subtract => {
-                let rhs = ctx.value_stack.pop().unwrap();
-                let lhs = ctx.value_stack.pop().unwrap();
+        ctx.value_stack = outer_stack;
+        match outer_item {
+            Some(outer_item) => ctx.heap.insert("$".to_string(), outer_item),
+            None => ctx.heap.remove("$"),
+        };
+
+        result?;
+        value.ok_or_else(|| ExecuteError::result_count_mismatch(0))
+    }
+}
 
-                ctx.value_stack.push(lhs.sub(rhs)?);
+impl Runner {
+    // 按顺序执行指令，跳转指令在这里修改执行位置，出错时返回出错指令的位置
+    // 设置了 ctx.observer 时在每条指令执行前后回调，source_map 用于告诉 observer 指令的源码位置
+    // program 在整个执行过程中只借用一次，引用常量池和槽位的指令从中读取
+    fn execute(
+        &self,
+        operators: &[OperatorCode],
+        source_map: Option<&SourceMap>,
+        program: Option<&Program>,
+        ctx: &mut RuntimeContext,
+    ) -> Result<(), (usize, ExecuteError)> {
+        let mut pc = 0;
+        while pc < operators.len() {
+            let current = pc;
+            let operator = &operators[pc];
+            ctx.count_instruction().map_err(|error| (current, error))?;
+            let step = ctx.observer.as_ref().map(|_| Step {
+                pc: current,
+                operator,
+                range: source_map.and_then(|map| map.range(current)),
+            });
+            if let Some(step) = &step {
+                ctx.observe(|observer, ctx| observer.before(step, ctx));
             }
-            OperatorCode::Multiply => {
-                let rhs = ctx.value_stack.pop().unwrap();
-                let lhs = ctx.value_stack.pop().unwrap();
 
-                ctx.value_stack.push(lhs.mul(rhs)?);
+            pc += 1;
+            let result = match operator {
+                OperatorCode::SkipIfNull(count) => {
+                    if ctx.value_stack.last() == Some(&Value::Null) {
+                        pc += *count as usize;
+                    }
+                    Ok(())
+                }
+                OperatorCode::Coalesce(count) => {
+                    match ctx.value_stack.last() {
+                        Some(Value::Null) => {
+                            ctx.value_stack.pop();
+                        }
+                        _ => pc += *count as usize,
+                    }
+                    Ok(())
+                }
+                operator => operator.run_with(ctx, program),
             }
-            OperatorCode::Divide => {
-                let rhs = ctx.value_stack.pop().unwrap();
-                let lhs = ctx.value_stack.pop().unwrap();
+            .and_then(|_| ctx.check_limits());
 
-                ctx.value_stack.push(lhs.div(rhs)?);
+            if let Some(step) = &step {
+                ctx.observe(|observer, ctx| observer.after(step, ctx, result.as_ref().err()));
             }
-            OperatorCode::Modulo => {
-                let rhs = ctx.value_stack.pop().unwrap();
-                let lhs = ctx.value_stack.pop().unwrap();
+            result.map_err(|error| (current, error))?;
+        }
+        Ok(())
+    }
+}
+
+pub trait Runnable {
+    fn run(&self, context: &mut RuntimeContext) -> Result<(), ExecuteError> {
+        self.run_with(context, None)
+    }
+
+    // program 是 Runner::run_program 执行的 Program，没有时不能执行引用常量池和槽位的指令
+    fn run_with(
+        &self,
+        context: &mut RuntimeContext,
+        program: Option<&Program>,
+    ) -> Result<(), ExecuteError>;
+}
+
+impl Runnable for OperatorCode {
+    fn run_with(
+        &self,
+        ctx: &mut RuntimeContext,
+        program: Option<&Program>,
+    ) -> Result<(), ExecuteError> {
+        match self {
+            OperatorCode::Add
+            | OperatorCode::Subtract
+            | OperatorCode::Multiply
+            | OperatorCode::Divide
+            | OperatorCode::Modulo
+            | OperatorCode::Power
+            | OperatorCode::IntDivide
+            | OperatorCode::ShiftLeft
+            | OperatorCode::ShiftRight
+            | OperatorCode::BitAnd
+            | OperatorCode::BitOr
+            | OperatorCode::BitXor => {
+                let rhs = ctx.pop()?;
+                let lhs = ctx.pop()?;
 
-                ctx.value_stack.push(lhs.modulo(rhs)?);
+                if ctx.null_mode == NullMode::Lenient && (lhs == Value::Null || rhs == Value::Null)
+                {
+                    ctx.value_stack.push(Value::Null);
+                    return Ok(());
+                }
+
+                ctx.value_stack.push(match self {
+                    OperatorCode::Add => lhs.add(rhs)?,
+                    OperatorCode::Subtract => lhs.sub(rhs)?,
+                    OperatorCode::Multiply => lhs.mul(rhs)?,
+                    OperatorCode::Divide => lhs.div(rhs)?,
+                    OperatorCode::Modulo => lhs.modulo(rhs)?,
+                    OperatorCode::Power => lhs.pow(rhs)?,
+                    OperatorCode::IntDivide => lhs.int_div(rhs)?,
+                    OperatorCode::ShiftLeft => lhs.shift_left(rhs)?,
+                    OperatorCode::ShiftRight => lhs.shift_right(rhs)?,
+                    OperatorCode::BitAnd => lhs.integer_operation("&", rhs, |a, b| Some(a & b))?,
+                    OperatorCode::BitOr => lhs.integer_operation("|", rhs, |a, b| Some(a | b))?,
+                    _ => lhs.integer_operation("xor", rhs, |a, b| Some(a ^ b))?,
+                });
             }
-            OperatorCode::Factorial => {
-                let lhs = ctx.value_stack.pop().unwrap();
-                ctx.value_stack.push(lhs.factorial()?);
+            OperatorCode::Factorial | OperatorCode::Negate => {
+                let lhs = ctx.pop()?;
+                match lhs {
+                    Value::Null if ctx.null_mode == NullMode::Lenient => {
+                        ctx.value_stack.push(Value::Null)
+                    }
+                    lhs if *self == OperatorCode::Negate => ctx.value_stack.push(lhs.negate()?),
+                    Value::Number(n) if n > Rational64::from_integer(ctx.limits.max_factorial) => {
+                        return Err(ExecuteError::factorial_limit_exceeded(
+                            ctx.limits.max_factorial,
+                            &n.to_string(),
+                        ))
+                    }
+                    lhs => ctx.value_stack.push(lhs.factorial()?),
+                }
             }
-            OperatorCode::Power => {
-                let rhs = ctx.value_stack.pop().unwrap();
-                let lhs = ctx.value_stack.pop().unwrap();
 
-                ctx.value_stack.push(lhs.pow(rhs)?);
+            OperatorCode::Equal
+            | OperatorCode::NotEqual
+            | OperatorCode::GreaterThan
+            | OperatorCode::GreaterThanOrEqual
+            | OperatorCode::LessThan
+            | OperatorCode::LessThanOrEqual => {
+                let rhs = ctx.pop()?;
+                let lhs = ctx.pop()?;
+
+                let op = match self {
+                    OperatorCode::Equal => "==",
+                    OperatorCode::NotEqual => "!=",
+                    OperatorCode::GreaterThan => ">",
+                    OperatorCode::GreaterThanOrEqual => ">=",
+                    OperatorCode::LessThan => "<",
+                    _ => "<=",
+                };
+                let result = match ctx.null_filter && (lhs == Value::Null || rhs == Value::Null) {
+                    true => false,
+                    false => lhs.compare(&op.to_string(), &rhs)?,
+                };
+                ctx.value_stack.push(Value::Bool(result));
             }
 
             OperatorCode::PushNumber(val) => {
@@ -85,99 +294,220 @@ impl Runnable for OperatorCode {
             OperatorCode::PushString(val) => {
                 ctx.value_stack.push(Value::String(val.clone()));
             }
+            OperatorCode::PushBool(val) => ctx.value_stack.push(Value::Bool(*val)),
+            OperatorCode::PushNull => ctx.value_stack.push(Value::Null),
             OperatorCode::LoadIdentifier(name) => {
-                let val = ctx.heap.get(name);
+                let val = match ctx.locals.get(name) {
+                    Some(local) => local.as_ref(),
+                    None => ctx.heap.get(name),
+                };
                 match val {
                     Some(val) => ctx.value_stack.push(val.clone()),
                     None => return Err(ExecuteError::identifier_not_found(name)),
                 }
             }
             OperatorCode::Call(arg_count) => {
-                let mut args = Vec::new();
-                for _ in 0..*arg_count {
-                    args.push(ctx.value_stack.pop().unwrap());
-                }
-                let func = ctx.value_stack.pop().unwrap();
+                let args = ctx.pop_many(*arg_count as usize)?;
+                let func = ctx.pop()?;
                 match func {
                     Value::Function(name) => {
-                        let result = run_runtime_function(&name, &args)?;
+                        let result = run_runtime_function(&name, &args, ctx)?;
                         ctx.value_stack.push(result);
                     }
                     _ => return Err(ExecuteError::not_a_function()),
                 }
             }
-            OperatorCode::LoadPropertyAccess(property) => {
-                let val = ctx.value_stack.pop().unwrap();
-                match val {
-                    Value::Array(arr) => {
-                        if arr.len() == 0 {
-                            // 空数组
-                            ctx.value_stack.push(Value::Array(Vec::new()));
-                            return Ok(());
-                        }
+            OperatorCode::MakeArray(count) => {
+                let items = ctx.pop_many(*count as usize)?;
+                ctx.value_stack.push(Value::Array(items));
+            }
+            OperatorCode::StoreLocal(name) => {
+                let value = ctx.pop()?;
+                ctx.locals.insert(name.clone(), Some(value));
+            }
+            OperatorCode::Pop => {
+                ctx.pop()?;
+            }
+            OperatorCode::Concat(count) => {
+                let text = ctx
+                    .pop_many(*count as usize)?
+                    .iter()
+                    .map(|value| match value {
+                        Value::Null => String::new(),
+                        value => value.to_string(),
+                    })
+                    .collect::<String>();
+                ctx.value_stack.push(Value::String(text));
+            }
+            OperatorCode::Index => {
+                let index = ctx.pop()?;
+                let object = ctx.pop()?;
 
-                        let mut result = Vec::new();
-                        for item in arr {
-                            match item {
-                                Value::Object(obj) => {
-                                    let val = obj.get(property);
-                                    match val {
-                                        Some(val) => result.push(val.clone()),
-                                        None => result.push(Value::Null),
-                                    }
-                                }
-                                _ => {
-                                    return Err(ExecuteError::dot_input_not_object_array(property))
-                                }
-                            }
-                        }
+                match object {
+                    Value::Null if ctx.propagates_null() => ctx.value_stack.push(Value::Null),
+                    object => ctx.value_stack.push(object.index(index)?),
+                }
+            }
+            OperatorCode::Slice(has_start, has_end) => {
+                let end = match has_end {
+                    true => Some(ctx.pop()?),
+                    false => None,
+                };
+                let start = match has_start {
+                    true => Some(ctx.pop()?),
+                    false => None,
+                };
+                let object = ctx.pop()?;
 
-                        ctx.value_stack.push(Value::Array(result));
+                match object {
+                    Value::Null if ctx.propagates_null() => ctx.value_stack.push(Value::Null),
+                    object => ctx.value_stack.push(object.slice(start, end)?),
+                }
+            }
+            // 由 Runner::execute 处理跳转
+            OperatorCode::SkipIfNull(_) | OperatorCode::Coalesce(_) => {}
+            OperatorCode::PushLambda(lambda) => {
+                ctx.value_stack.push(Value::Lambda(lambda.clone()));
+            }
+            OperatorCode::LoadPropertyAccess(property) => load_property(ctx, property)?,
+            OperatorCode::FilterExpression(left, op, value) => filter(ctx, left, op, value)?,
+            OperatorCode::PushConstant(index) => {
+                let value = match loaded(program)?.constant(*index)? {
+                    Constant::Number(value) => Value::Number(*value),
+                    Constant::String(value) => Value::String(value.clone()),
+                };
+                ctx.value_stack.push(value);
+            }
+            OperatorCode::LoadSlot(index) => {
+                let program = loaded(program)?;
+                match ctx.slots.get(*index as usize) {
+                    Some(Some(value)) => ctx.value_stack.push(value.clone()),
+                    _ => {
+                        let name = program.slots.get(*index as usize).cloned();
+                        return Err(ExecuteError::identifier_not_found(
+                            &name.unwrap_or_default(),
+                        ));
                     }
-                    _ => return Err(ExecuteError::dot_input_not_object_array(property)),
                 }
             }
-            OperatorCode::FilterExpression(left, op, value) => {
-                let val = ctx.value_stack.pop().unwrap();
-                match val {
-                    Value::Array(arr) => {
-                        if arr.len() == 0 {
-                            // 空数组
-                            ctx.value_stack.push(Value::Array(Vec::new()));
-                            return Ok(());
+            OperatorCode::StoreSlot(index) => {
+                let value = ctx.pop()?;
+                match ctx.slots.get_mut(*index as usize) {
+                    Some(slot) => *slot = Some(value),
+                    None => {
+                        return Err(ExecuteError::new(ExecuteErrorType::InvalidBytecode)
+                            .with_message(format!("slot {} out of range", index)))
+                    }
+                }
+            }
+            OperatorCode::LoadProperty(index) => {
+                load_property(ctx, loaded(program)?.constant_str(*index)?)?
+            }
+            OperatorCode::Filter(left, op, value) => {
+                let program = loaded(program)?;
+                filter(
+                    ctx,
+                    program.constant_str(*left)?,
+                    program.constant_str(*op)?,
+                    program.constant_str(*value)?,
+                )?
+            }
+            OperatorCode::Throw(type_, message) => {
+                return Err(ExecuteError {
+                    type_: *type_,
+                    message: message.clone(),
+                    range: None,
+                })
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn loaded(program: Option<&Program>) -> Result<&Program, ExecuteError> {
+    program.ok_or_else(|| {
+        ExecuteError::new(ExecuteErrorType::InvalidBytecode)
+            .with_message("constant pool is not loaded".to_string())
+    })
+}
+
+fn load_property(ctx: &mut RuntimeContext, property: &str) -> Result<(), ExecuteError> {
+    let val = ctx.pop()?;
+    match val {
+        // 单个对象直接取属性，例如 $.status、parent.assignee
+        Value::Object(obj) => {
+            ctx.value_stack
+                .push(obj.get(property).cloned().unwrap_or(Value::Null));
+        }
+        Value::Null if ctx.propagates_null() => ctx.value_stack.push(Value::Null),
+        Value::Array(arr) => {
+            if arr.is_empty() {
+                // 空数组
+                ctx.value_stack.push(Value::Array(Vec::new()));
+                return Ok(());
+            }
+
+            let mut result = Vec::new();
+            for item in arr {
+                match item {
+                    Value::Object(obj) => {
+                        let val = obj.get(property);
+                        match val {
+                            Some(val) => result.push(val.clone()),
+                            None => result.push(Value::Null),
                         }
+                    }
+                    Value::Null if ctx.propagates_null() => result.push(Value::Null),
+                    _ => return Err(ExecuteError::dot_input_not_object_array(property)),
+                }
+            }
+
+            ctx.value_stack.push(Value::Array(result));
+        }
+        _ => return Err(ExecuteError::dot_input_not_object_array(property)),
+    }
+    Ok(())
+}
+
+fn filter(ctx: &mut RuntimeContext, left: &str, op: &str, value: &str) -> Result<(), ExecuteError> {
+    let val = ctx.pop()?;
+    match val {
+        Value::Array(arr) => {
+            if arr.is_empty() {
+                // 空数组
+                ctx.value_stack.push(Value::Array(Vec::new()));
+                return Ok(());
+            }
 
-                        let mut result = Vec::new();
-
-                        let rhs = match value.parse::<i64>() {
-                            Ok(val) => Value::Number(Rational64::from_integer(val.into())),
-                            Err(_) => Value::String(value.clone()),
-                        };
-
-                        for item in arr {
-                            match item {
-                                Value::Object(ref obj) => {
-                                    let val = obj.get(left);
-                                    match val {
-                                        Some(val) => {
-                                            if val.compare(op, &rhs)? {
-                                                result.push(item.clone());
-                                            }
-                                        }
-                                        None => continue,
-                                    }
+            let mut result = Vec::new();
+
+            let rhs = match value.parse::<i64>() {
+                Ok(val) => Value::Number(Rational64::from_integer(val)),
+                Err(_) => Value::String(value.to_string()),
+            };
+
+            for item in arr {
+                match item {
+                    Value::Object(ref obj) => {
+                        let val = obj.get(left);
+                        // 和 where 一样跳过属性为 Null 的元素
+                        match val {
+                            Some(Value::Null) | None => continue,
+                            Some(val) => {
+                                if val.compare(&op.to_string(), &rhs)? {
+                                    result.push(item.clone());
                                 }
-                                _ => return Err(ExecuteError::dot_input_not_object_array(left)),
                             }
                         }
-
-                        ctx.value_stack.push(Value::Array(result));
                     }
                     _ => return Err(ExecuteError::dot_input_not_object_array(left)),
                 }
             }
-        }
 
-        Ok(())
+            ctx.value_stack.push(Value::Array(result));
+        }
+        _ => return Err(ExecuteError::dot_input_not_object_array(left)),
     }
+    Ok(())
 }
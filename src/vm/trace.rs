@@ -0,0 +1,128 @@
+use alloc::{
+    boxed::Box,
+    rc::Rc,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::cell::RefCell;
+
+use super::{context::RuntimeContext, error::ExecuteError, runner::Runner, value::Value};
+use crate::{
+    parse::ast::Range,
+    share::{operator::OperatorCode, program::Program},
+};
+
+// 执行每条指令前后的回调，ctx.value_stack 是当前的值栈
+// lambda 中的指令也会回调，发生在调用它的 Call 的 before 和 after 之间
+pub trait Observer {
+    fn before(&mut self, _step: &Step, _ctx: &RuntimeContext) {}
+    fn after(&mut self, _step: &Step, _ctx: &RuntimeContext, _error: Option<&ExecuteError>) {}
+}
+
+// 正在执行的指令，range 是指令的源码位置，执行 Runner::run 时没有
+pub struct Step<'a> {
+    pub pc: usize,
+    pub operator: &'a OperatorCode,
+    pub range: Option<&'a Range>,
+}
+
+// 放进 ctx 之后调用方仍然可以读取记录的结果
+impl<T: Observer> Observer for Rc<RefCell<T>> {
+    fn before(&mut self, step: &Step, ctx: &RuntimeContext) {
+        self.borrow_mut().before(step, ctx)
+    }
+
+    fn after(&mut self, step: &Step, ctx: &RuntimeContext, error: Option<&ExecuteError>) {
+        self.borrow_mut().after(step, ctx, error)
+    }
+}
+
+// 执行完一条指令后的状态
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceStep {
+    pub depth: usize, // lambda 的嵌套层数，顶层为 0
+    pub pc: usize,
+    pub operator: OperatorCode,
+    pub range: Option<Range>,
+    pub stack: Vec<Value>,   // 执行后的值栈，lambda 中是 lambda 自己的值栈
+    pub item: Option<Value>, // lambda 中的 $
+    pub store: Option<(String, Value)>, // let 绑定的变量和值
+    pub error: Option<ExecuteError>,
+}
+
+// 记录每条指令执行后的值栈
+#[derive(Default)]
+pub struct Tracer {
+    pub steps: Vec<TraceStep>,
+    pending: Vec<Option<(String, Value)>>, // 已经回调 before 还没有回调 after 的指令
+}
+
+impl Observer for Tracer {
+    fn before(&mut self, step: &Step, ctx: &RuntimeContext) {
+        let name = match step.operator {
+            OperatorCode::StoreLocal(name) => Some(name.clone()),
+            OperatorCode::StoreSlot(index) => ctx
+                .program
+                .as_ref()
+                .and_then(|program| program.slots.get(*index as usize).cloned()),
+            _ => None,
+        };
+        let store = name.zip(ctx.value_stack.last().cloned());
+        self.pending.push(store);
+    }
+
+    fn after(&mut self, step: &Step, ctx: &RuntimeContext, error: Option<&ExecuteError>) {
+        let store = self.pending.pop().flatten();
+        let depth = self.pending.len();
+        self.steps.push(TraceStep {
+            depth,
+            pc: step.pc,
+            operator: step.operator.clone(),
+            range: step.range.cloned(),
+            stack: ctx.value_stack.clone(),
+            item: match depth {
+                0 => None,
+                _ => ctx.heap.get("$").cloned(),
+            },
+            store: store.filter(|_| error.is_none()),
+            error: error.map(|error| error.clone().with_range(step.range)),
+        });
+    }
+}
+
+// 一次完整的执行记录，用于排查线上公式的结果
+pub struct Trace {
+    pub program: Arc<Program>,
+    pub inputs: Vec<(String, Value)>, // 执行前从 Issue 中读取的字段，按名字排序
+    pub steps: Vec<TraceStep>,
+    pub result: Result<Value, ExecuteError>,
+}
+
+impl Trace {
+    // 和 Runner::run_program 相同，ctx 中需要先设置好依赖的字段
+    pub fn record(program: &Arc<Program>, ctx: &mut RuntimeContext) -> Trace {
+        let mut inputs = program
+            .dependencies
+            .iter()
+            .filter_map(|name| match ctx.get(name) {
+                Some(Value::Function(_)) | None => None,
+                Some(value) => Some((name.to_string(), value.clone())),
+            })
+            .collect::<Vec<_>>();
+        inputs.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let tracer = Rc::new(RefCell::new(Tracer::default()));
+        let outer = ctx.observer.replace(Box::new(tracer.clone()));
+        let result = Runner.run_program(program, ctx);
+        ctx.observer = outer;
+
+        let steps = core::mem::take(&mut tracer.borrow_mut().steps);
+        Trace {
+            program: program.clone(),
+            inputs,
+            steps,
+            result,
+        }
+    }
+}
@@ -5,10 +5,11 @@ use alloc::{
     vec::Vec,
 };
 use hashbrown::HashMap;
-use num::{FromPrimitive, Rational64, ToPrimitive, Zero};
+use num::{checked_pow, FromPrimitive, Rational64, ToPrimitive, Zero};
 use serde_json::Value as JsonValue;
 
-use super::error::ExecuteError;
+use super::{error::ExecuteError, number};
+use crate::share::{decimal::parse_decimal, operator::OperatorCode};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Value {
@@ -20,6 +21,7 @@ pub enum Value {
     Array(Vec<Value>),
     Object(HashMap<String, Value>),
     Function(String),
+    Lambda(Vec<OperatorCode>), // 作为参数传给 where、sum 等函数的 $ 表达式
 
     Null,
 }
@@ -29,9 +31,15 @@ impl Value {
         match json {
             JsonValue::Null => Value::Null,
             JsonValue::Bool(b) => Value::Bool(*b),
-            JsonValue::Number(n) => {
-                Value::Number(Rational64::from_f64(n.as_f64().unwrap()).unwrap())
-            }
+            // serde_json 输出 f64 时使用最短的十进制文本，0.1 仍然是 0.1，按文本转换成 1/10
+            // 有效数字太多放不进 Rational64 时使用最接近的值，仍然放不进时为 Null
+            JsonValue::Number(n) => match parse_decimal(&n.to_string()) {
+                Ok(n) => Value::Number(n),
+                Err(_) => n
+                    .as_f64()
+                    .and_then(Rational64::from_f64)
+                    .map_or(Value::Null, Value::Number),
+            },
             JsonValue::String(s) => Value::String(s.to_string()),
             JsonValue::Array(arr) => {
                 let mut vec = Vec::new();
@@ -51,10 +59,20 @@ impl Value {
     }
 }
 
+// 整数的移位位数不在 0 到 63 之间时报错，不是整数的由 integer_operation 报错
+fn check_shift_count(rhs: &Value) -> Result<(), ExecuteError> {
+    match rhs {
+        Value::Number(count) if count.is_integer() && !(0..64).contains(count.numer()) => {
+            Err(ExecuteError::shift_out_of_range(*count.numer()))
+        }
+        _ => Ok(()),
+    }
+}
+
 impl Value {
     pub fn add(self, rhs: Value) -> Result<Value, ExecuteError> {
         match (&self, &rhs) {
-            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(number::add(a, b)?)),
             (Value::String(a), Value::String(b)) => Ok(Value::String(a.clone() + &b)),
 
             (Value::Number(a), Value::String(b)) => Ok(Value::String(a.to_string() + &b)),
@@ -78,7 +96,7 @@ impl Value {
 
     pub fn sub(self, rhs: Value) -> Result<Value, ExecuteError> {
         match (&self, &rhs) {
-            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a - b)),
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(number::sub(a, b)?)),
 
             (Value::DateTime(a), Value::Duration(b)) => {
                 Ok(Value::DateTime(a - b.to_u64().unwrap()))
@@ -94,13 +112,15 @@ impl Value {
 
     pub fn mul(self, rhs: Value) -> Result<Value, ExecuteError> {
         match (&self, &rhs) {
-            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a * b)),
-            (Value::Duration(a), Value::Number(b)) => Ok(Value::Duration(
-                (a.to_f64().unwrap() * b.to_f64().unwrap())
-                    .floor()
-                    .to_i64()
-                    .unwrap(),
-            )),
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(number::mul(a, b)?)),
+            (Value::Duration(a), Value::Number(b)) | (Value::Number(b), Value::Duration(a)) => {
+                Ok(Value::Duration(
+                    (a.to_f64().unwrap() * b.to_f64().unwrap())
+                        .floor()
+                        .to_i64()
+                        .unwrap(),
+                ))
+            }
             (Value::Null, Value::Null) => Err(ExecuteError::operator_mismatch(
                 "*".to_string(),
                 self.to_string(),
@@ -120,7 +140,7 @@ impl Value {
                 if b == &Rational64::zero() {
                     Err(ExecuteError::divide_by_zero())
                 } else {
-                    Ok(Value::Number(a / b))
+                    Ok(Value::Number(number::div(a, b)?))
                 }
             }
 
@@ -145,6 +165,18 @@ impl Value {
         }
     }
 
+    pub fn negate(self) -> Result<Value, ExecuteError> {
+        match &self {
+            Value::Number(a) => Ok(Value::Number(number::neg(a)?)),
+            Value::Duration(a) => Ok(Value::Duration(-a)),
+            _ => Err(ExecuteError::operator_mismatch(
+                "-".to_string(),
+                self.to_string(),
+                None,
+            )),
+        }
+    }
+
     pub fn factorial(self) -> Result<Value, ExecuteError> {
         match &self {
             Value::Number(a) => {
@@ -153,11 +185,14 @@ impl Value {
                         return Err(ExecuteError::factorial_not_negative());
                     }
 
-                    let mut result = Rational64::from_integer(1);
+                    // 超过 20 时溢出，报错而不是 panic
+                    let mut result: i64 = 1;
                     for i in 1..a.to_i64().unwrap() + 1 {
-                        result *= Rational64::from_integer(i);
+                        result = result
+                            .checked_mul(i)
+                            .ok_or_else(ExecuteError::number_overflow)?;
                     }
-                    Ok(Value::Number(result))
+                    Ok(Value::Number(Rational64::from_integer(result)))
                 } else {
                     Err(ExecuteError::factorial_not_integer())
                 }
@@ -176,9 +211,20 @@ impl Value {
                 if !b.is_integer() {
                     return Err(ExecuteError::pow_not_rational());
                 }
-                match b.to_i32() {
-                    Some(power) => Ok(Value::Number(a.pow(power))),
-                    None => Err(ExecuteError::number_conversion_error()),
+                let power = b
+                    .to_i32()
+                    .ok_or_else(ExecuteError::number_conversion_error)?;
+                // 分子分母互质，分别乘方后仍然互质，溢出时报错而不是 panic
+                let exponent = power.unsigned_abs() as usize;
+                let result = checked_pow(*a.numer(), exponent)
+                    .zip(checked_pow(*a.denom(), exponent))
+                    .map(|(numer, denom)| Rational64::new_raw(numer, denom))
+                    .ok_or_else(ExecuteError::number_overflow)?;
+                match power < 0 {
+                    false => Ok(Value::Number(result)),
+                    true if result.is_zero() => Err(ExecuteError::divide_by_zero()),
+                    true if *result.numer() == i64::MIN => Err(ExecuteError::number_overflow()),
+                    true => Ok(Value::Number(result.recip())),
                 }
             }
             _ => Err(ExecuteError::operator_mismatch(
@@ -191,7 +237,10 @@ impl Value {
 
     pub fn modulo(self, rhs: Value) -> Result<Value, ExecuteError> {
         match (&self, &rhs) {
-            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a % b)),
+            (Value::Number(_), Value::Number(b)) if b.is_zero() => {
+                Err(ExecuteError::divide_by_zero())
+            }
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(number::rem(a, b)?)),
             _ => Err(ExecuteError::operator_mismatch(
                 "FormulaOperator::Modulo".to_string(),
                 self.to_string(),
@@ -200,11 +249,82 @@ impl Value {
         }
     }
 
+    // 整除、移位、位运算只能用于整数，结果超出 i64 时报错
+    pub fn integer_operation(
+        self,
+        operator: &str,
+        rhs: Value,
+        operation: impl Fn(i64, i64) -> Option<i64>,
+    ) -> Result<Value, ExecuteError> {
+        match (&self, &rhs) {
+            (Value::Number(a), Value::Number(b)) => {
+                if let Some(actual) = [a, b].iter().find(|n| !n.is_integer()) {
+                    return Err(ExecuteError::operand_not_integer(
+                        operator,
+                        actual.to_string(),
+                    ));
+                }
+                // 和其他检查溢出的运算一样报 NumberOverflow
+                match operation(*a.numer(), *b.numer()) {
+                    Some(result) => Ok(Value::Number(Rational64::from_integer(result))),
+                    None => Err(ExecuteError::number_overflow()),
+                }
+            }
+            _ => Err(ExecuteError::operator_mismatch(
+                operator.to_string(),
+                self.to_string(),
+                Some(rhs.to_string()),
+            )),
+        }
+    }
+
+    pub fn int_div(self, rhs: Value) -> Result<Value, ExecuteError> {
+        if let (Value::Number(_), Value::Number(b)) = (&self, &rhs) {
+            if b.is_zero() {
+                return Err(ExecuteError::divide_by_zero());
+            }
+        }
+        self.integer_operation("div", rhs, |a, b| a.checked_div(b))
+    }
+
+    // 移位的位数必须在 0 到 63 之间，超出范围或者左移溢出时报 NumberOverflow
+    pub fn shift_left(self, rhs: Value) -> Result<Value, ExecuteError> {
+        check_shift_count(&rhs)?;
+        self.integer_operation("<<", rhs, |a, b| {
+            let count = b.to_u32()?;
+            let result = a.checked_shl(count)?;
+            match result >> count == a {
+                true => Some(result),
+                false => None,
+            }
+        })
+    }
+
+    pub fn shift_right(self, rhs: Value) -> Result<Value, ExecuteError> {
+        check_shift_count(&rhs)?;
+        self.integer_operation(">>", rhs, |a, b| a.checked_shr(b.to_u32()?))
+    }
+
     pub fn compare(&self, op: &String, rhs: &Value) -> Result<bool, ExecuteError> {
         match (self, rhs) {
+            // Null 只等于 Null，大小比较的结果都是 false；where 和旧的过滤写法中另有规则，见 RuntimeContext.null_filter
+            (Value::Null, _) | (_, Value::Null) => match op.as_str() {
+                "=" | "==" => Ok(self == rhs),
+                "!=" | "<>" => Ok(self != rhs),
+                _ => Ok(false),
+            },
             (Value::String(lhs), Value::String(rhs)) => match op.as_str() {
                 "=" | "==" => return Ok(lhs == rhs),
-                "!=" => return Ok(lhs != rhs),
+                "!=" | "<>" => return Ok(lhs != rhs),
+                _ => Err(ExecuteError::operator_mismatch(
+                    op.clone(),
+                    self.to_string(),
+                    Some(rhs.to_string()),
+                )),
+            },
+            (Value::Bool(lhs), Value::Bool(rhs)) => match op.as_str() {
+                "=" | "==" => Ok(lhs == rhs),
+                "!=" | "<>" => Ok(lhs != rhs),
                 _ => Err(ExecuteError::operator_mismatch(
                     op.clone(),
                     self.to_string(),
@@ -217,7 +337,7 @@ impl Value {
                 "<" => Ok(lhs.lt(&rhs)),
                 "<=" => Ok(lhs.le(&rhs)),
                 "=" | "==" => Ok(lhs.eq(&rhs)),
-                "!=" => Ok(lhs.ne(&rhs)),
+                "!=" | "<>" => Ok(lhs.ne(&rhs)),
                 _ => Err(ExecuteError::operator_mismatch(
                     op.clone(),
                     self.to_string(),
@@ -232,6 +352,67 @@ impl Value {
         }
     }
 
+    // 负数下标从末尾开始，-1 是最后一个元素，越界得到 Null
+    pub fn index(self, index: Value) -> Result<Value, ExecuteError> {
+        let items = match self {
+            Value::Array(items) => items,
+            _ => return Err(ExecuteError::index_input_not_array(self.get_type())),
+        };
+        let index = index.to_index()?;
+        let position = match index < 0 {
+            true => items.len() as i64 + index,
+            false => index,
+        };
+
+        match position >= 0 && (position as usize) < items.len() {
+            true => Ok(items[position as usize].clone()),
+            false => Ok(Value::Null),
+        }
+    }
+
+    // 切片包含 start、不包含 end，负数从末尾开始，越界的部分会被截掉
+    pub fn slice(self, start: Option<Value>, end: Option<Value>) -> Result<Value, ExecuteError> {
+        let items = match self {
+            Value::Array(items) => items,
+            _ => return Err(ExecuteError::index_input_not_array(self.get_type())),
+        };
+        let (start, end) = Self::slice_range(items.len(), start, end)?;
+        Ok(Value::Array(items[start..end].to_vec()))
+    }
+
+    // 切片在长度为 len 的序列中对应的范围，start 不会大于 end
+    pub(crate) fn slice_range(
+        len: usize,
+        start: Option<Value>,
+        end: Option<Value>,
+    ) -> Result<(usize, usize), ExecuteError> {
+        let len = len as i64;
+        let clamp = |bound: Option<Value>, default: i64| -> Result<usize, ExecuteError> {
+            let bound = match bound {
+                Some(bound) => bound.to_index()?,
+                None => default,
+            };
+            let bound = match bound < 0 {
+                true => len + bound,
+                false => bound,
+            };
+            Ok(bound.clamp(0, len) as usize)
+        };
+        let start = clamp(start, 0)?;
+        let end = clamp(end, len)?;
+        Ok((start, end.max(start)))
+    }
+
+    fn to_index(&self) -> Result<i64, ExecuteError> {
+        match self {
+            Value::Number(n) if n.is_integer() => n
+                .to_integer()
+                .to_i64()
+                .ok_or_else(ExecuteError::number_conversion_error),
+            _ => Err(ExecuteError::index_not_integer(&self.to_string())),
+        }
+    }
+
     pub fn is_array(&self) -> bool {
         match self {
             Value::Array(_) => true,
@@ -283,6 +464,7 @@ impl Value {
             Value::DateTime(_) => "DateTime",
             Value::Duration(_) => "Duration",
             Value::Function(_) => "Function",
+            Value::Lambda(_) => "Lambda",
             Value::Object(_) => "Object",
             Value::Null => "Null",
         }
@@ -316,6 +498,7 @@ impl Display for Value {
             Value::DateTime(_) => write!(f, "{:?}", self),
             Value::Duration(_) => write!(f, "{:?}", self),
             Value::Function(name) => write!(f, "Func {}()", name),
+            Value::Lambda(_) => write!(f, "Lambda"),
             Value::Object(_) => write!(f, "{:?}", self),
             Value::Null => write!(f, "null"),
         }
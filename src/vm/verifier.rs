@@ -0,0 +1,190 @@
+use alloc::{format, vec::Vec};
+
+use super::error::ExecuteError;
+use crate::share::{
+    operator::OperatorCode,
+    program::{Constant, Program},
+};
+
+// 执行前静态检查指令：栈深度是否平衡、Call(n) 等指令是否有足够的值、跳转目标是否越界
+// 从存储中读出的字节码或者编译器的 bug 都可能产生不合法的指令，检查通过后执行时不会出现栈下溢
+pub fn verify(codes: &[OperatorCode]) -> Result<(), ExecuteError> {
+    match verify_depth(codes)? {
+        1 => Ok(()),
+        // 只有 let 语句时最后一条指令保存绑定的值，栈中没有结果
+        0 if matches!(
+            codes.last(),
+            Some(OperatorCode::StoreLocal(_) | OperatorCode::StoreSlot(_))
+        ) =>
+        {
+            Err(ExecuteError::no_result_expression())
+        }
+        depth => Err(ExecuteError::result_count_mismatch(depth)),
+    }
+}
+
+// 在 verify 的基础上检查常量池、槽位的下标是否越界，属性名、过滤条件是否是字符串常量
+pub fn verify_program(program: &Program) -> Result<(), ExecuteError> {
+    verify(&program.operators)?;
+    verify_indexes(program, &program.operators)
+}
+
+fn verify_indexes(program: &Program, codes: &[OperatorCode]) -> Result<(), ExecuteError> {
+    let constant = |pc: usize, index: u32| match program.constants.get(index as usize) {
+        Some(_) => Ok(()),
+        None => Err(ExecuteError::invalid_bytecode(
+            pc,
+            format!("constant {} out of range", index),
+        )),
+    };
+    let string = |pc: usize, index: u32| match program.constants.get(index as usize) {
+        Some(Constant::String(_)) => Ok(()),
+        _ => Err(ExecuteError::invalid_bytecode(
+            pc,
+            format!("constant {} is not a string", index),
+        )),
+    };
+
+    for (pc, code) in codes.iter().enumerate() {
+        match code {
+            OperatorCode::PushConstant(index) => constant(pc, *index)?,
+            OperatorCode::LoadProperty(index) => string(pc, *index)?,
+            OperatorCode::Filter(left, op, value) => {
+                string(pc, *left)?;
+                string(pc, *op)?;
+                string(pc, *value)?;
+            }
+            OperatorCode::LoadSlot(index) | OperatorCode::StoreSlot(index)
+                if *index as usize >= program.slots.len() =>
+            {
+                return Err(ExecuteError::invalid_bytecode(
+                    pc,
+                    format!("slot {} out of range", index),
+                ))
+            }
+            OperatorCode::PushLambda(lambda) => verify_indexes(program, lambda)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// 返回执行完所有指令之后栈的深度，跳转都是向后的，所以按顺序扫描一遍就够了
+fn verify_depth(codes: &[OperatorCode]) -> Result<usize, ExecuteError> {
+    let mut depth = 0;
+    // 跳转目标的位置和跳转时栈的深度，不管是否跳转，到达目标时的深度必须相同
+    let mut targets: Vec<(usize, usize)> = Vec::new();
+
+    for (pc, code) in codes.iter().enumerate() {
+        check_targets(&targets, pc, depth)?;
+
+        let (pops, pushes) = stack_effect(code);
+        if depth < pops {
+            return Err(ExecuteError::invalid_bytecode(
+                pc,
+                format!("{:?} needs {} values, stack has {}", code, pops, depth),
+            ));
+        }
+
+        match code {
+            OperatorCode::SkipIfNull(count) | OperatorCode::Coalesce(count) => {
+                let target = pc + 1 + *count as usize;
+                if target > codes.len() {
+                    return Err(ExecuteError::invalid_bytecode(
+                        pc,
+                        format!("jump target {} out of range", target),
+                    ));
+                }
+                targets.push((target, depth));
+            }
+            // lambda 对每个元素单独执行，结果必须正好是一个值
+            OperatorCode::PushLambda(lambda) => match verify_depth(lambda) {
+                Ok(1) => {}
+                Ok(depth) => {
+                    return Err(ExecuteError::invalid_bytecode(
+                        pc,
+                        format!("lambda leaves {} values", depth),
+                    ))
+                }
+                Err(error) => {
+                    return Err(ExecuteError::invalid_bytecode(
+                        pc,
+                        format!("in lambda, {}", error.message.unwrap_or_default()),
+                    ))
+                }
+            },
+            _ => {}
+        }
+
+        depth = depth - pops + pushes;
+    }
+
+    check_targets(&targets, codes.len(), depth)?;
+    Ok(depth)
+}
+
+fn check_targets(targets: &[(usize, usize)], pc: usize, depth: usize) -> Result<(), ExecuteError> {
+    match targets
+        .iter()
+        .find(|(target, expected)| *target == pc && *expected != depth)
+    {
+        Some((_, expected)) => Err(ExecuteError::invalid_bytecode(
+            pc,
+            format!(
+                "stack depth {} after jump, {} without jump",
+                expected, depth
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+// 指令弹出和压入的值的个数
+pub(crate) fn stack_effect(code: &OperatorCode) -> (usize, usize) {
+    match code {
+        OperatorCode::Add
+        | OperatorCode::Subtract
+        | OperatorCode::Multiply
+        | OperatorCode::Divide
+        | OperatorCode::Modulo
+        | OperatorCode::Power
+        | OperatorCode::IntDivide
+        | OperatorCode::ShiftLeft
+        | OperatorCode::ShiftRight
+        | OperatorCode::BitAnd
+        | OperatorCode::BitOr
+        | OperatorCode::BitXor
+        | OperatorCode::Equal
+        | OperatorCode::NotEqual
+        | OperatorCode::GreaterThan
+        | OperatorCode::GreaterThanOrEqual
+        | OperatorCode::LessThan
+        | OperatorCode::LessThanOrEqual
+        | OperatorCode::Index => (2, 1),
+        OperatorCode::Factorial
+        | OperatorCode::Negate
+        | OperatorCode::LoadPropertyAccess(_)
+        | OperatorCode::FilterExpression(_, _, _)
+        | OperatorCode::LoadProperty(_)
+        | OperatorCode::Filter(_, _, _) => (1, 1),
+        OperatorCode::PushNumber(_)
+        | OperatorCode::PushString(_)
+        | OperatorCode::PushBool(_)
+        | OperatorCode::PushNull
+        | OperatorCode::PushLambda(_)
+        | OperatorCode::LoadIdentifier(_)
+        | OperatorCode::PushConstant(_)
+        | OperatorCode::LoadSlot(_) => (0, 1),
+        // 执行时直接报错，按替换掉的表达式的结果计算
+        OperatorCode::Throw(_, _) => (0, 1),
+        // 函数本身和 n 个参数
+        OperatorCode::Call(count) => (*count as usize + 1, 1),
+        OperatorCode::MakeArray(count) | OperatorCode::Concat(count) => (*count as usize, 1),
+        OperatorCode::Slice(has_start, has_end) => (1 + *has_start as usize + *has_end as usize, 1),
+        // 栈顶为 Null 时保留 Null 跳转，否则继续取属性
+        OperatorCode::SkipIfNull(_) => (1, 1),
+        // 不跳转时弹出 Null，右边的指令再压入一个值
+        OperatorCode::Coalesce(_) => (1, 0),
+        OperatorCode::StoreLocal(_) | OperatorCode::StoreSlot(_) | OperatorCode::Pop => (1, 0),
+    }
+}
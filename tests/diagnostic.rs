@@ -0,0 +1,279 @@
+#[cfg(test)]
+mod formula_diagnostic {
+    use formula_rs_wasm::parse::{
+        ast::Range,
+        diagnostic::{diagnose, DiagnosticCode, Language},
+    };
+
+    fn check(expr: &str, code: DiagnosticCode, range: Range, message: &str) {
+        let diagnostic = diagnose(expr, Language::En).unwrap();
+        assert_eq!(diagnostic.code, code, "{}", expr);
+        assert_eq!(diagnostic.range, range, "{}", expr);
+        assert_eq!(diagnostic.message, message, "{}", expr);
+    }
+
+    #[test]
+    fn diagnostic_ok() {
+        assert_eq!(
+            diagnose("SUM(subtask.estimatePoint;status=2)", Language::En),
+            None
+        );
+        assert_eq!(
+            diagnose("// it's (\nlet a = 1; /* ; ) */ a", Language::En),
+            None
+        );
+    }
+
+    #[test]
+    fn diagnostic_messages() {
+        check(
+            "(1 + 2",
+            DiagnosticCode::MissingClosingParenthesis,
+            Range(0, 6),
+            "missing closing parenthesis",
+        );
+        check(
+            "1 + 2)",
+            DiagnosticCode::UnexpectedClosingParenthesis,
+            Range(5, 6),
+            "unexpected closing parenthesis",
+        );
+        check(
+            "a => b",
+            DiagnosticCode::UnknownOperator,
+            Range(2, 4),
+            "unknown operator `=>`",
+        );
+        check(
+            "1 +",
+            DiagnosticCode::UnexpectedEnd,
+            Range(3, 3),
+            "unexpected end of formula, expected number or string, identifier, array",
+        );
+        check(
+            "SUM(a, ",
+            DiagnosticCode::UnexpectedEnd,
+            Range(6, 6),
+            "unexpected end of formula, expected argument",
+        );
+        check(
+            "f(1,,2)",
+            DiagnosticCode::UnexpectedToken,
+            Range(4, 5),
+            "unexpected `,`, expected argument",
+        );
+        check(
+            "'abc",
+            DiagnosticCode::UnclosedString,
+            Range(0, 4),
+            "unclosed string",
+        );
+        check(
+            "dueDate > #2024-02-30#",
+            DiagnosticCode::InvalidDate,
+            Range(10, 22),
+            "invalid date `#2024-02-30#`",
+        );
+        check(
+            "date('2023-02-29')",
+            DiagnosticCode::InvalidDate,
+            Range(5, 17),
+            "invalid date `'2023-02-29'`",
+        );
+        check(
+            r"title + 'a\qb'",
+            DiagnosticCode::InvalidEscape,
+            Range(10, 12),
+            r"invalid escape `\q`",
+        );
+        check(
+            "estimatePoint * 1.5e20",
+            DiagnosticCode::NumberOutOfRange,
+            Range(16, 22),
+            "number `1.5e20` is out of range",
+        );
+        check(
+            r"'\u{D800}' + `\u{1F600}`",
+            DiagnosticCode::InvalidEscape,
+            Range(1, 9),
+            r"invalid escape `\u{D800}`",
+        );
+        check(
+            r"'it\'s",
+            DiagnosticCode::UnclosedString,
+            Range(0, 6),
+            "unclosed string",
+        );
+        check(
+            "1 + 2 /* it's",
+            DiagnosticCode::UnclosedComment,
+            Range(6, 13),
+            "unclosed comment",
+        );
+        check(
+            "1 + // (\n(2",
+            DiagnosticCode::MissingClosingParenthesis,
+            Range(9, 11),
+            "missing closing parenthesis",
+        );
+        check(
+            " ",
+            DiagnosticCode::EmptyFormula,
+            Range(0, 1),
+            "formula is empty",
+        );
+    }
+
+    #[test]
+    fn diagnostic_expected_and_hint() {
+        let diagnostic = diagnose("a => b", Language::En).unwrap();
+        assert_eq!(diagnostic.hint, Some("did you mean `>=`?".to_string()));
+
+        let diagnostic = diagnose("1 2", Language::En).unwrap();
+        assert_eq!(diagnostic.code, DiagnosticCode::UnexpectedToken);
+        assert!(diagnostic.expected.contains(&"`+`".to_string()));
+        assert!(diagnostic.expected.contains(&"end of formula".to_string()));
+    }
+
+    #[test]
+    fn diagnostic_chinese() {
+        let diagnostic = diagnose("(1 + 2", Language::Zh).unwrap();
+        assert_eq!(diagnostic.message, "缺少右括号");
+
+        let diagnostic = diagnose("a => b", Language::Zh).unwrap();
+        assert_eq!(diagnostic.message, "未知的运算符 `=>`");
+        assert_eq!(diagnostic.hint, Some("是不是想输入 `>=`？".to_string()));
+
+        let diagnostic = diagnose("1 +", Language::Zh).unwrap();
+        assert_eq!(
+            diagnostic.message,
+            "公式不完整，缺少数字或字符串、标识符、数组"
+        );
+    }
+}
+
+#[cfg(test)]
+mod formula_parse_tolerant {
+    use expect_test::{expect, Expect};
+    use formula_rs_wasm::parse::{
+        ast::Range,
+        beautify::Beautify,
+        diagnostic::{DiagnosticCode, Language},
+        recover::parse_tolerant,
+    };
+
+    fn check(expr: &str, expected: Expect, diagnostics: Vec<(DiagnosticCode, Range)>) {
+        let (ast, actual) = parse_tolerant(expr, Language::En);
+        expected.assert_eq(ast.beautify(0).as_str());
+        assert_eq!(
+            actual
+                .into_iter()
+                .map(|d| (d.code, d.range))
+                .collect::<Vec<_>>(),
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn tolerant_statements() {
+        check(
+            "1 + ; a.b; 2 *",
+            expect![[r#"
+                FormulaBody
+                    ExpressionStatement
+                        ErrorExpression (1 +)
+                    ExpressionStatement
+                        PropertyAccessExpression
+                            object
+                                Identifier a
+                            property
+                                Identifier b
+                    ExpressionStatement
+                        ErrorExpression (2 *)"#]],
+            vec![
+                (DiagnosticCode::UnexpectedEnd, Range(3, 3)),
+                (DiagnosticCode::UnexpectedEnd, Range(14, 14)),
+            ],
+        );
+
+        check(
+            "1 + 1",
+            expect![[r#"
+                FormulaBody
+                    ExpressionStatement
+                        BinaryExpression
+                            left
+                                NumberLiteral (1)
+                            operator +
+                            right
+                                NumberLiteral (1)"#]],
+            vec![],
+        );
+    }
+
+    #[test]
+    fn tolerant_arguments() {
+        check(
+            "SUM(subtask.estimatePoint;status=)",
+            expect![[r#"
+                FormulaBody
+                    ExpressionStatement
+                        CallExpression
+                            callee
+                                Identifier SUM
+                            arguments
+                                PropertyAccessExpression
+                                    object
+                                        Identifier subtask
+                                    property
+                                        Identifier estimatePoint
+                                ErrorExpression (status=)"#]],
+            vec![(DiagnosticCode::UnexpectedEnd, Range(33, 33))],
+        );
+
+        check(
+            "COUNT(subtask,, a => 1",
+            expect![[r#"
+                FormulaBody
+                    ExpressionStatement
+                        CallExpression
+                            callee
+                                Identifier COUNT
+                            arguments
+                                Identifier subtask
+                                ErrorExpression (a => 1)"#]],
+            vec![
+                (DiagnosticCode::MissingArgument, Range(14, 15)),
+                (DiagnosticCode::UnknownOperator, Range(18, 20)),
+                (DiagnosticCode::MissingClosingParenthesis, Range(5, 22)),
+            ],
+        );
+    }
+
+    #[test]
+    fn parse_tolerant_comments() {
+        check(
+            "let a = 1; // 注释 (;\nSUM(a,",
+            expect![[r#"
+                FormulaBody
+                    ExpressionStatement
+                        LetBinding a
+                            NumberLiteral (1)
+                    ExpressionStatement
+                        CallExpression
+                            callee
+                                Identifier SUM
+                            arguments
+                                Identifier a"#]],
+            vec![(DiagnosticCode::MissingClosingParenthesis, Range(27, 30))],
+        );
+        check(
+            "1 /* ",
+            expect![[r#"
+                FormulaBody
+                    ExpressionStatement
+                        NumberLiteral (1)"#]],
+            vec![(DiagnosticCode::UnclosedComment, Range(2, 5))],
+        );
+    }
+}
@@ -0,0 +1,419 @@
+#[cfg(test)]
+mod formula_editor {
+    use formula_rs_wasm::{
+        editor::{
+            completion::{complete, CompletionKind},
+            definition::definition,
+            diagnostics::diagnostics,
+            hover::hover,
+            position::{byte_to_utf16_offset, utf16_to_byte_offset, LineIndex, Position},
+            signature::signature_help,
+        },
+        parse::{
+            ast::Range,
+            diagnostic::{DiagnosticCode, Language},
+        },
+        types::schema::Schema,
+    };
+    use serde_json::json;
+
+    fn schema() -> Schema {
+        let mut schema = Schema::from_json(&json!({
+            "estimatePoint": "Number",
+            "subtask": {
+                "type": "Array",
+                "fields": { "status": "Number", "estimatePoint": "Number" },
+            },
+            "owner": { "type": "Object", "fields": { "name": "String" } },
+        }))
+        .unwrap();
+        schema.inject_builtins();
+        schema
+    }
+
+    fn labels(expr: &str, offset: usize) -> Vec<(String, CompletionKind)> {
+        complete(expr, offset, &schema(), Language::En)
+            .into_iter()
+            .map(|item| (item.label, item.kind))
+            .collect()
+    }
+
+    #[test]
+    fn completion_identifiers_and_functions() {
+        assert_eq!(
+            labels("1 + es", 6),
+            vec![("estimatePoint".to_string(), CompletionKind::Field)]
+        );
+        assert_eq!(
+            labels("su", 2),
+            vec![
+                ("subtask".to_string(), CompletionKind::Field),
+                ("SUM".to_string(), CompletionKind::Function),
+                ("sum".to_string(), CompletionKind::Function),
+                ("substring".to_string(), CompletionKind::Function)
+            ]
+        );
+
+        let item = complete("CO", 2, &schema(), Language::Zh).remove(0);
+        assert_eq!(item.range, Range(0, 2));
+        assert_eq!(item.detail, "COUNT(values: Array | Number, ...): Number");
+        assert_eq!(
+            item.documentation.as_deref(),
+            Some("返回数组中元素的个数，或者参数的个数。")
+        );
+    }
+
+    #[test]
+    fn completion_properties() {
+        assert_eq!(
+            labels("SUM(subtask.", 12),
+            vec![
+                ("estimatePoint".to_string(), CompletionKind::Property),
+                ("status".to_string(), CompletionKind::Property)
+            ]
+        );
+        assert_eq!(
+            labels("owner.na", 8),
+            vec![("name".to_string(), CompletionKind::Property)]
+        );
+        assert_eq!(
+            labels("owner?.na", 9),
+            vec![("name".to_string(), CompletionKind::Property)]
+        );
+        assert_eq!(labels("estimatePoint.", 14), vec![]);
+        assert_eq!(labels("'subtask.", 9), vec![]);
+
+        assert_eq!(
+            labels("sum(where(subtask, $.st", 23),
+            vec![("status".to_string(), CompletionKind::Property)]
+        );
+
+        assert_eq!(
+            labels("sum(where(subtask, $.status == 2), $.e", 38),
+            vec![("estimatePoint".to_string(), CompletionKind::Property)]
+        );
+
+        assert_eq!(
+            labels("subtask[a[0]].st", 16),
+            vec![("status".to_string(), CompletionKind::Property)]
+        );
+        assert_eq!(labels("[subtask.", 9).len(), 2);
+        assert_eq!(
+            labels("estimatePoint + owner.", 22),
+            vec![("name".to_string(), CompletionKind::Property)]
+        );
+
+        // let 绑定的名字和它的属性，注释中不补全
+        let expr = "let done = where(subtask, $.status == 2); done.st + don";
+        assert_eq!(
+            labels(expr, 49),
+            vec![("status".to_string(), CompletionKind::Property)]
+        );
+        assert_eq!(
+            labels(expr, expr.len()),
+            vec![("done".to_string(), CompletionKind::Field)]
+        );
+        assert_eq!(labels("1 // su", 7), vec![]);
+        assert_eq!(labels("/* su */ su", 5), vec![]);
+
+        // 旧的过滤写法中可以直接使用数组元素的属性
+        assert_eq!(
+            labels("COUNT(subtask.estimatePoint; st", 31),
+            vec![("status".to_string(), CompletionKind::Property)]
+        );
+    }
+
+    #[test]
+    fn hover_types_and_docs() {
+        let schema = schema();
+        let expr = "SUM(subtask.estimatePoint) + estimatePoint";
+
+        let function = hover(expr, 1, &schema, Language::En).unwrap();
+        assert_eq!(function.range, Range(0, 3));
+        assert_eq!(
+            function.contents,
+            "```formula\nSUM(values: Number | Number[], ...): Number\n```\n\nReturns the sum of the numbers, empty values count as 0."
+        );
+
+        let property = hover(expr, 15, &schema, Language::En).unwrap();
+        assert_eq!(property.range, Range(12, 25));
+        assert_eq!(property.contents, "```formula\nestimatePoint: Array\n```");
+
+        let field = hover(expr, 30, &schema, Language::En).unwrap();
+        assert_eq!(field.range, Range(29, 42));
+        assert_eq!(field.contents, "```formula\nestimatePoint: Number\n```");
+
+        let filter = hover("COUNT(subtask; status=2)", 16, &schema, Language::En).unwrap();
+        assert_eq!(filter.contents, "```formula\nstatus: Number\n```");
+
+        assert_eq!(hover("unknown", 2, &schema, Language::En), None);
+
+        let expr = "let total = SUM(subtask.estimatePoint); total";
+        let binding = hover(expr, 5, &schema, Language::En).unwrap();
+        assert_eq!(binding.range, Range(4, 9));
+        assert_eq!(binding.contents, "```formula\ntotal: Number\n```");
+        let usage = hover(expr, 42, &schema, Language::En).unwrap();
+        assert_eq!(usage.contents, "```formula\ntotal: Number\n```");
+    }
+
+    #[test]
+    fn signature_help_active_parameter() {
+        let help = signature_help("SUM(1, ", 7, Language::En).unwrap();
+        assert_eq!(help.label, "SUM(values: Number | Number[], ...): Number");
+        assert_eq!(help.active_parameter, 0);
+
+        let help = signature_help(
+            "func f(Number, String) -> Number; f(1, 'a')",
+            38,
+            Language::En,
+        )
+        .unwrap();
+        assert_eq!(help.label, "f(Number, String) -> Number");
+        assert_eq!(help.active_parameter, 1);
+
+        // 字符串中的逗号和分组括号不影响参数位置
+        let help = signature_help("COUNT((1 + 2), ',', ", 20, Language::En).unwrap();
+        assert_eq!(help.active_parameter, 0);
+        assert_eq!(signature_help("(1 + 2", 6, Language::En), None);
+        assert_eq!(signature_help("SUM(1) ", 6, Language::En), None);
+        assert_eq!(signature_help("SUM(1)", 3, Language::En), None);
+
+        // 数组字面量中的逗号也不影响
+        let help = signature_help("in(status, [1, 2, ", 18, Language::En).unwrap();
+        assert_eq!(help.active_parameter, 1);
+
+        // 转义的引号不会结束字符串，模板字符串中的逗号也不影响
+        let expr = r"in('it\'s, (', `${a}, `, ";
+        let help = signature_help(expr, expr.len(), Language::En).unwrap();
+        assert_eq!(help.active_parameter, 2);
+    }
+
+    #[test]
+    fn definition_of_declarations() {
+        let expr = "type Task = { point: Number }; func f(Task) -> Number; f(1)";
+        assert_eq!(definition(expr, 56), Some(Range(36, 37)));
+        assert_eq!(definition(expr, 40), Some(Range(5, 9)));
+        assert_eq!(definition(expr, 23), None);
+
+        // 重复绑定时跳转到前面最近的一个
+        let expr = "let a = 1; let a = a + 1; a";
+        assert_eq!(definition(expr, 19), Some(Range(4, 5)));
+        assert_eq!(definition(expr, 26), Some(Range(15, 16)));
+        assert_eq!(definition(expr, 15), Some(Range(15, 16)));
+    }
+
+    #[test]
+    fn diagnostics_with_ranges() {
+        let schema = schema();
+        assert_eq!(
+            diagnostics("SUM(subtask.estimatePoint;status=2)", &schema, Language::En),
+            vec![]
+        );
+
+        let result = diagnostics("estimatePoint + foo", &schema, Language::En);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].code, DiagnosticCode::TypeError);
+        assert_eq!(result[0].range, Range(16, 19));
+        assert_eq!(result[0].message, "type error: identifier not found: foo");
+
+        let result = diagnostics("let a = foo; let b = 1; b + 1", &schema, Language::En);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].range, Range(8, 11));
+        assert_eq!(
+            diagnostics("let a = 1; a + estimatePoint", &schema, Language::En),
+            vec![]
+        );
+
+        let result = diagnostics("SUM(subtask.foo); 1 +", &schema, Language::En);
+        let codes = result
+            .iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.range.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            codes,
+            vec![
+                (DiagnosticCode::UnexpectedEnd, Range(21, 21)),
+                (DiagnosticCode::TypeError, Range(12, 15))
+            ]
+        );
+    }
+
+    #[test]
+    fn position_conversion() {
+        let text = "a\n中b😀c";
+        let index = LineIndex::new(text);
+        assert_eq!(
+            index.position(5),
+            Position {
+                line: 1,
+                character: 1
+            }
+        );
+        assert_eq!(
+            index.offset(Position {
+                line: 1,
+                character: 4
+            }),
+            10
+        );
+        assert_eq!(
+            index.offset(Position {
+                line: 5,
+                character: 0
+            }),
+            text.len()
+        );
+        assert_eq!(byte_to_utf16_offset(text, 10), 6);
+        assert_eq!(utf16_to_byte_offset(text, 6), 10);
+    }
+}
+
+#[cfg(test)]
+mod formula_editor_monaco {
+    use formula_rs_wasm::{complete, evaluate, hover, signature_help, tokens, trace};
+    use serde_json::{json, Value as JsonValue};
+
+    fn parse(result: String) -> JsonValue {
+        serde_json::from_str(&result).unwrap()
+    }
+
+    #[test]
+    fn monaco_completion() {
+        let schema = r#"{ "subtask": { "type": "Array", "fields": { "status": "Number" } } }"#;
+        // 中文占一个 UTF-16 单元、三个字节
+        let result = parse(complete(
+            "'中' + subtask.st".to_string(),
+            16,
+            schema.to_string(),
+            None,
+        ));
+        assert_eq!(
+            result,
+            json!({ "suggestions": [{
+                "label": "status",
+                "kind": 9,
+                "detail": "Number",
+                "documentation": null,
+                "insertText": "status",
+                "range": { "startLineNumber": 1, "startColumn": 15, "endLineNumber": 1, "endColumn": 17 },
+            }]})
+        );
+    }
+
+    #[test]
+    fn monaco_hover_and_signature_help() {
+        let result = parse(hover(
+            "COUNT(a)".to_string(),
+            2,
+            None,
+            Some("zh".to_string()),
+        ));
+        assert_eq!(
+            result["contents"][0]["value"],
+            "```formula\nCOUNT(values: Array | Number, ...): Number\n```\n\n返回数组中元素的个数，或者参数的个数。"
+        );
+        assert_eq!(
+            result["range"],
+            json!({ "startLineNumber": 1, "startColumn": 1, "endLineNumber": 1, "endColumn": 6 })
+        );
+        assert_eq!(
+            parse(hover("a".to_string(), 0, None, None)),
+            JsonValue::Null
+        );
+
+        let result = parse(signature_help("SUM(1; 2".to_string(), 8, None));
+        assert_eq!(result["activeParameter"], 0);
+        assert_eq!(
+            result["signatures"][0]["parameters"][0]["label"],
+            "values: Number | Number[]"
+        );
+    }
+
+    #[test]
+    fn monaco_evaluate() {
+        let data = r#"{ "subtask": [] }"#.to_string();
+        let result = parse(evaluate("COUNT(subtask)".to_string(), data.clone(), 0, 0));
+        assert_eq!(result, json!({ "ok": true, "value": "0", "error": null }));
+
+        // 出错的子表达式可以直接用来标记，中文占一个 UTF-16 单元
+        let result = parse(evaluate(
+            "'中文' +\nSUM(subtask.estimatePoint) / COUNT(subtask)".to_string(),
+            data,
+            0,
+            0,
+        ));
+        assert_eq!(result["ok"], false);
+        assert_eq!(result["error"]["type"], "DivideByZero");
+        assert_eq!(
+            result["error"]["range"],
+            json!({ "startLineNumber": 2, "startColumn": 1, "endLineNumber": 2, "endColumn": 44 })
+        );
+
+        let result = parse(evaluate("1 +".to_string(), "{}".to_string(), 0, 0));
+        assert_eq!(result["error"]["type"], "SyntaxError");
+    }
+
+    #[test]
+    fn monaco_trace() {
+        let data = r#"{ "s": [{ "p": 1 }, { "p": 2 }] }"#.to_string();
+        let result = parse(trace("sum(s, $.p * 2)".to_string(), data, 0, 0));
+        assert_eq!(result["ok"], true);
+        assert_eq!(result["value"], "6");
+        assert_eq!(
+            result["inputs"],
+            json!({ "s": "[Object({\"p\": Number(Ratio { numer: 1, denom: 1 })}), Object({\"p\": Number(Ratio { numer: 2, denom: 1 })})]" })
+        );
+
+        let steps = result["steps"].as_array().unwrap();
+        assert_eq!(steps.len(), 12);
+        assert_eq!(
+            steps[6],
+            json!({
+                "depth": 1,
+                "pc": 3,
+                "instruction": "Multiply",
+                "range": { "startLineNumber": 1, "startColumn": 8, "endLineNumber": 1, "endColumn": 15 },
+                "source": "$.p * 2",
+                "stack": ["2"],
+                "item": "Object({\"p\": Number(Ratio { numer: 1, denom: 1 })})",
+                "store": null,
+                "error": null,
+            })
+        );
+        assert_eq!(steps[11]["instruction"], "Call(2)");
+        assert_eq!(steps[11]["stack"], json!(["6"]));
+
+        let result = parse(trace("1 +".to_string(), "{}".to_string(), 0, 0));
+        assert_eq!(result["error"]["type"], "SyntaxError");
+        assert_eq!(result["steps"], json!([]));
+    }
+
+    #[test]
+    fn monaco_semantic_tokens() {
+        let result = parse(tokens(
+            "type T = { a: Number };\nSUM(subtask.x) + 'a'".to_string(),
+        ));
+        assert_eq!(
+            result["legend"]["tokenTypes"],
+            json!([
+                "keyword", "type", "function", "variable", "property", "number", "string",
+                "operator"
+            ])
+        );
+        #[rustfmt::skip]
+        assert_eq!(
+            result["data"],
+            json!([
+                0, 0, 4, 0, 0, // type
+                0, 5, 1, 1, 0, // T
+                0, 6, 1, 4, 0, // a
+                0, 3, 6, 1, 0, // Number
+                1, 0, 3, 2, 0, // SUM
+                0, 4, 7, 3, 0, // subtask
+                0, 8, 1, 4, 0, // x
+                0, 3, 1, 7, 0, // +
+                0, 2, 3, 6, 0, // 'a'
+            ])
+        );
+    }
+}
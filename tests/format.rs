@@ -0,0 +1,135 @@
+#[cfg(test)]
+mod formula_format {
+    use formula_rs_wasm::parse::{
+        ast::{to_ast, FormulaBody},
+        beautify::Beautify,
+        format::Format,
+        parse::Formula,
+    };
+
+    fn parse(expr: &str) -> FormulaBody {
+        let formula = Formula::parse(expr).unwrap();
+        to_ast(formula.paris).1
+    }
+
+    fn format(expr: &str) -> String {
+        parse(expr).format()
+    }
+
+    // Range 会随着空格变化，所以用 Beautify 比较 AST
+    fn assert_round_trip(expr: &str) {
+        let formatted = format(expr);
+        assert_eq!(
+            parse(&formatted).beautify(0),
+            parse(expr).beautify(0),
+            "{} => {}",
+            expr,
+            formatted
+        );
+        assert_eq!(format(&formatted), formatted, "{}", expr);
+    }
+
+    #[test]
+    fn format_spacing_and_aliases() {
+        assert_eq!(format("1+2*3"), "1 + 2 * 3");
+        assert_eq!(format("a plus b times c"), "a + b * c");
+        assert_eq!(format("a divide by b mod 3"), "a / b % 3");
+        assert_eq!(format("a <> b"), "a != b");
+        assert_eq!(format("a = 'x'"), "a == 'x'");
+        assert_eq!(
+            format("SUM( subtask.estimatePoint ;status=2 )"),
+            "SUM(subtask.estimatePoint, status == 2)"
+        );
+        assert_eq!(format("COUNT(subtask;)"), "COUNT(subtask)");
+        assert_eq!(format("\"a\" + 'b'"), "\"a\" + 'b'");
+        assert_eq!(format("1.50+2"), "1.50 + 2");
+        assert_eq!(
+            format("type A = {x:Number,y:List<A>};func f(A)->Number;f(1)"),
+            "type A = { x: Number, y: List<A> };\nfunc f(A) -> Number;\nf(1)"
+        );
+    }
+
+    #[test]
+    fn format_minimal_parentheses() {
+        assert_eq!(format("(1 + 2) * 3"), "(1 + 2) * 3");
+        assert_eq!(format("1 + (2 * 3)"), "1 + 2 * 3");
+        assert_eq!(format("(1 + 2) + 3"), "1 + 2 + 3");
+        assert_eq!(format("1 - (2 - 3)"), "1 - (2 - 3)");
+        assert_eq!(format("(2 ^ 3) ^ 4"), "(2 ^ 3) ^ 4");
+        assert_eq!(format("2 ^ (3 ^ 4)"), "2 ^ 3 ^ 4");
+        assert_eq!(format("(a * b) % c"), "(a * b) % c");
+        assert_eq!(format("a * (b % c)"), "a * b % c");
+        assert_eq!(format("(1 + 2)!"), "(1 + 2)!");
+        assert_eq!(format("2 ^ 3!"), "2 ^ 3!");
+        assert_eq!(format("(a == b) == c"), "(a == b) == c");
+        assert_eq!(format("(a + 1) >= ((b - 1) * 2)"), "a + 1 >= (b - 1) * 2");
+        assert_eq!(format("SUM((a + b))"), "SUM(a + b)");
+        assert_eq!(format("SUM((a == b))"), "SUM(a == b)");
+        assert_eq!(format("( a+b )[ 0 ]"), "(a + b)[0]");
+        assert_eq!(format("[1,2 ,4][-1:]"), "[1, 2, 4][-1:]");
+        assert_eq!(format("(a ?? b) ?? c"), "(a ?? b) ?? c");
+        assert_eq!(format("a ?? (b ?? c)"), "a ?? b ?? c");
+        assert_eq!(format("(a ?? 1) + 2"), "(a ?? 1) + 2");
+        assert_eq!(format("(a ?? 1) > 2"), "a ?? 1 > 2");
+        assert_eq!(format("(a??b)?.c"), "(a ?? b)?.c");
+        assert_eq!(format("-( a+1 )"), "-(a + 1)");
+        assert_eq!(format("(-a)^2"), "-a ^ 2");
+        assert_eq!(format("-(a^2)"), "-(a ^ 2)");
+        assert_eq!(format("-(a!)"), "-a!");
+        assert_eq!(format("(-a)!"), "(-a)!");
+        assert_eq!(format("2*(-a)"), "2 * -a");
+        assert_eq!(format("- 1"), "- 1");
+        assert_eq!(format("-(3!)"), "- 3!");
+        assert_eq!(format("-(-a)"), "--a");
+        assert_eq!(format("(a | b) & c"), "(a | b) & c");
+        assert_eq!(format("a | (b & c)"), "a | b & c");
+        assert_eq!(format("(a << 1) + 1"), "(a << 1) + 1");
+        assert_eq!(format("a << (1 + 1)"), "a << 1 + 1");
+        assert_eq!(format("(a xor b) | c"), "a xor b | c");
+        assert_eq!(format("a div (b * c)"), "a div (b * c)");
+    }
+
+    #[test]
+    fn format_round_trip() {
+        for expr in [
+            "1 + 2 * 3 - 4 / 5",
+            "(1 + 2) * (3 - 4) % 5",
+            "1 - (2 - 3) - 4",
+            "2 ^ 3 ^ 4",
+            "(2 ^ 3) ^ 4",
+            "((1 + 2)!)! + 3!",
+            "a.b.c >= GET_NOW - GET_CREATE_TIME * 2",
+            "subtask[0].name + subtask[a - 1].name",
+            "in(status, [1, 2, 4]) == [[1], []][0]",
+            "subtask[1:3][:-1][0]",
+            "parent?.assignee.name ?? subtask[0]?.name ?? 'none'",
+            "where(subtask, $.status == 2)[0].estimatePoint!",
+            "(a == b) != (c < d)",
+            "a - -1",
+            "- 3!",
+            "- 2 ^ 2",
+            "(flags & 1 << 2 != 0) == (a xor b div 2 | c >> 1)",
+            "a - -b * -(c + 1) ^ -d!",
+            "+a.b - -subtask[0]",
+            r#"'it\'s' + "\u{1F600}\n""#,
+            r"`完成 ${count(where(subtask, $.status == 2))} 个\n\${x}` + length(title)",
+            "((a ?? null) == true) != (#2024-01-31# < #2024-02-01T08:30:00Z#)",
+            "SUM(subtask.estimatePoint, a + 1, 'x')",
+            "sum(where(subtask, $.status == 2), $.estimatePoint * 2)",
+            "type T = { a: Number, b: Array<String> }; func f(T, Number) -> T; f(1, 2)",
+            "let done = where(subtask, $.status == 2); let total = sum(done.estimatePoint); total / 2",
+        ] {
+            assert_round_trip(expr);
+        }
+
+        // 线上公式
+        let data = include_str!("data/data.txt");
+        for expr in data.lines() {
+            let formatted = format(expr);
+            let expected = parse(expr)
+                .beautify(0)
+                .replace("operator =\n", "operator ==\n");
+            assert_eq!(parse(&formatted).beautify(0), expected, "{}", expr);
+        }
+    }
+}
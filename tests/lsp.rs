@@ -0,0 +1,178 @@
+#[cfg(test)]
+mod formula_lsp {
+    use serde_json::{json, Value as JsonValue};
+    use std::{
+        io::Write,
+        process::{Command, Stdio},
+    };
+
+    fn frame(message: &JsonValue) -> String {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    fn session(messages: &[JsonValue]) -> (Vec<JsonValue>, Option<i32>) {
+        raw_session(&messages.iter().map(frame).collect::<String>())
+    }
+
+    // 把所有请求一次性写入 stdin，再按 Content-Length 拆分 stdout 中的消息
+    fn raw_session(input: &str) -> (Vec<JsonValue>, Option<i32>) {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_formula-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+
+        let mut rest = String::from_utf8(output.stdout).unwrap();
+        let mut responses = Vec::new();
+        while let Some(header_end) = rest.find("\r\n\r\n") {
+            let length = rest["Content-Length: ".len()..header_end]
+                .parse::<usize>()
+                .unwrap();
+            let body_start = header_end + 4;
+            responses.push(serde_json::from_str(&rest[body_start..body_start + length]).unwrap());
+            rest = rest[body_start + length..].to_string();
+        }
+        (responses, output.status.code())
+    }
+
+    fn request(id: i64, method: &str, params: JsonValue) -> JsonValue {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn notification(method: &str, params: JsonValue) -> JsonValue {
+        json!({ "jsonrpc": "2.0", "method": method, "params": params })
+    }
+
+    fn at(line: usize, character: usize) -> JsonValue {
+        json!({
+            "textDocument": { "uri": "file:///formula" },
+            "position": { "line": line, "character": character },
+        })
+    }
+
+    fn result(responses: &[JsonValue], id: i64) -> &JsonValue {
+        &responses
+            .iter()
+            .find(|response| response["id"] == id)
+            .unwrap()["result"]
+    }
+
+    #[test]
+    fn lsp_stdio_session() {
+        let text = "type Task = { point: Number };\nSUM(subtask.estimatePoint, )";
+        let (responses, code) = session(&[
+            request(
+                1,
+                "initialize",
+                json!({
+                    "capabilities": {},
+                    "initializationOptions": {
+                        "language": "en",
+                        "schema": {
+                            "subtask": { "type": "Array", "fields": { "estimatePoint": "Number" } },
+                        },
+                    },
+                }),
+            ),
+            notification("initialized", json!({})),
+            notification(
+                "textDocument/didOpen",
+                json!({ "textDocument": {
+                    "uri": "file:///formula", "languageId": "formula", "version": 1, "text": text,
+                }}),
+            ),
+            request(2, "textDocument/completion", at(1, 12)),
+            request(3, "textDocument/hover", at(1, 1)),
+            request(4, "textDocument/signatureHelp", at(1, 27)),
+            request(5, "textDocument/definition", at(0, 6)),
+            notification(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": "file:///formula", "version": 2 },
+                    "contentChanges": [{ "text": "SUM(foo)" }],
+                }),
+            ),
+            request(6, "unknown/method", json!({})),
+            request(7, "shutdown", JsonValue::Null),
+            notification("exit", JsonValue::Null),
+        ]);
+
+        assert_eq!(code, Some(0));
+
+        let capabilities = &result(&responses, 1)["capabilities"];
+        assert_eq!(capabilities["hoverProvider"], true);
+        assert_eq!(capabilities["definitionProvider"], true);
+
+        let diagnostics = responses
+            .iter()
+            .filter(|message| message["method"] == "textDocument/publishDiagnostics")
+            .map(|message| message["params"]["diagnostics"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0], json!([]));
+        assert_eq!(diagnostics[1][0]["code"], "type-error");
+        assert_eq!(
+            diagnostics[1][0]["range"],
+            json!({ "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 7 } })
+        );
+
+        let items = &result(&responses, 2)["items"];
+        assert_eq!(items[0]["label"], "estimatePoint");
+        assert_eq!(items[0]["kind"], 10);
+
+        let hover = result(&responses, 3);
+        assert_eq!(
+            hover["contents"]["value"],
+            "```formula\nSUM(values: Number | Number[], ...): Number\n```\n\nReturns the sum of the numbers, empty values count as 0."
+        );
+
+        let signature = result(&responses, 4);
+        assert_eq!(signature["activeParameter"], 0);
+        assert_eq!(
+            signature["signatures"][0]["label"],
+            "SUM(values: Number | Number[], ...): Number"
+        );
+
+        assert_eq!(
+            result(&responses, 5)["range"],
+            json!({ "start": { "line": 0, "character": 5 }, "end": { "line": 0, "character": 9 } })
+        );
+
+        let error = &responses
+            .iter()
+            .find(|response| response["id"] == 6)
+            .unwrap()["error"];
+        assert_eq!(error["code"], -32601);
+    }
+
+    #[test]
+    fn lsp_parse_error() {
+        // 内容不是 JSON、消息头没有长度的消息都回复 Parse error，服务继续处理后面的消息
+        let input = [
+            "Content-Length: 9\r\n\r\n{not json".to_string(),
+            "Content-Length: abc\r\n\r\n".to_string(),
+            frame(&request(1, "shutdown", JsonValue::Null)),
+            frame(&notification("exit", JsonValue::Null)),
+        ]
+        .concat();
+        let (responses, code) = raw_session(&input);
+
+        assert_eq!(code, Some(0));
+        assert_eq!(responses.len(), 3);
+        for response in &responses[..2] {
+            assert_eq!(response["id"], JsonValue::Null);
+            assert_eq!(response["error"]["code"], -32700);
+        }
+        assert_eq!(result(&responses, 1), &JsonValue::Null);
+        assert_eq!(responses[2]["id"], 1);
+    }
+}
@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod formula_migrate {
+    use formula_rs_wasm::migrate::{
+        migrate,
+        verify::{evaluate, verify},
+        MigrateErrorType,
+    };
+    use num::Rational64;
+    use serde_json::Value as JsonValue;
+
+    fn issues() -> Vec<JsonValue> {
+        let sit2: JsonValue = serde_json::from_str(include_str!("./data/sit2.json")).unwrap();
+        let issue = serde_json::json!({
+            "estimatePoint": 3,
+            "createTime": 1000,
+            "updateTime": 2000,
+            "subtask": [
+                { "status": 2, "estimatePoint": 5, "actualLabour": 1.5 },
+                { "status": 4, "estimatePoint": 8 },
+                { "status": 4, "estimatePoint": 1, "remainingLabour": 2 }
+            ],
+            "relationship": [
+                { "issueTypeId": 1848788, "relationship": "CHILD" },
+                { "issueTypeId": 1848766, "relationship": "RELATES_TO" },
+                { "issueTypeId": 1848788, "relationship": "CHILD" },
+                { "relationship": "CHILD" }
+            ]
+        });
+        vec![sit2, issue, serde_json::json!({})]
+    }
+
+    #[test]
+    fn migrate_legacy_filter() {
+        assert_eq!(
+            migrate("SUM(subtask.estimatePoint;status=2)"),
+            Ok("sum(where(subtask, $.status == 2), $.estimatePoint)".to_string())
+        );
+        assert_eq!(
+            migrate("COUNT(relationship;)"),
+            Ok("count(relationship)".to_string())
+        );
+        assert_eq!(
+            migrate("COUNT(relationship;relationship=CHILD)"),
+            Ok("count(where(relationship, $.relationship == 'CHILD'))".to_string())
+        );
+        assert_eq!(
+            migrate("COUNT(subtask;status>=2;estimatePoint<>1)"),
+            Ok("count(where(where(subtask, $.status >= 2), $.estimatePoint != 1))".to_string())
+        );
+        assert_eq!(
+            migrate("SUM(subtask.estimatePoint;status=4)/SUM(subtask.estimatePoint;)"),
+            Ok("sum(where(subtask, $.status == 4), $.estimatePoint) / sum(subtask, $.estimatePoint)"
+                .to_string())
+        );
+        assert_eq!(migrate("SUM(1, 2)"), Ok("sum(1, 2)".to_string()));
+        assert_eq!(
+            migrate("`已完成 ${COUNT(subtask;status=4)} 个`"),
+            Ok("`已完成 ${count(where(subtask, $.status == 4))} 个`".to_string())
+        );
+        assert_eq!(
+            migrate("estimatePoint*2"),
+            Ok("estimatePoint * 2".to_string())
+        );
+    }
+
+    #[test]
+    fn migrate_error() {
+        assert_eq!(
+            migrate("SUM(1 + 2;status=2)").unwrap_err().type_,
+            MigrateErrorType::UnsupportedArgument
+        );
+        let error = migrate("COUNT(subtask;2=status)").unwrap_err();
+        assert_eq!(error.type_, MigrateErrorType::UnsupportedArgument);
+        assert_eq!(
+            error.message,
+            Some("COUNT: unsupported argument `2 == status`, expected a field path followed by filters like status=2".to_string())
+        );
+        assert_eq!(
+            migrate("SUM(").unwrap_err().type_,
+            MigrateErrorType::ParseError
+        );
+    }
+
+    #[test]
+    fn verify_data() {
+        let issues = issues();
+        let now = 1_700_000_000_000;
+        let today = now - now % 86_400_000;
+
+        for legacy in include_str!("./data/data.txt").lines() {
+            let migrated = migrate(legacy).unwrap();
+            assert_eq!(
+                verify(legacy, &migrated, &issues, now, today),
+                vec![],
+                "{} -> {}",
+                legacy,
+                migrated
+            );
+        }
+
+        assert_eq!(
+            evaluate(
+                &migrate("COUNT(relationship;relationship=CHILD)").unwrap(),
+                &issues[1],
+                now,
+                today
+            ),
+            Ok(formula_rs_wasm::vm::value::Value::Number(
+                Rational64::from_integer(3)
+            ))
+        );
+    }
+
+    #[test]
+    fn verify_mismatch() {
+        // 旧写法中字符串字面量连同引号一起比较，改写后才能匹配，所以结果不一致
+        let legacy = "COUNT(relationship;relationship='CHILD')";
+        let migrated = migrate(legacy).unwrap();
+        let mismatches = verify(legacy, &migrated, &issues(), 0, 0);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].issue, 1);
+        assert_eq!(mismatches[0].describe(), "issue #1: legacy 0 / migrated 3");
+    }
+}
@@ -50,8 +50,8 @@ mod formula_parse_literal_num {
     #[test]
     fn num_allow_value() {
         vec![
-            "1", "1.1", "0", "+0", "2.2", "2.0", "2e20", "-1", "+1", "-1.1", "+1.1", "-1.0",
-            "+1.0", "-1e20", "+1e20",
+            "1", "1.1", "0", "+0", "2.2", "2.0", "2e18", "-1", "+1", "-1.1", "+1.1", "-1.0",
+            "+1.0", "-1e18", "+1e18", "1e-18",
         ]
         .iter()
         .for_each(|s| {
@@ -67,7 +67,6 @@ mod formula_parse_literal_num {
     fn num_illegal_value() {
         vec![
             "1.", "0.", "0..1", "1.1.1", "++", "1e", "1e+", "1e-", "1e-+", "1e++", "1e--", "1e+-",
-            "+-1", "-+1",
         ]
         .iter()
         .for_each(|s| {
@@ -114,6 +113,60 @@ mod formula_parse_literal_string {
             assert!(result.is_err());
         });
     }
+
+    #[test]
+    fn string_escape() {
+        vec![
+            r"'it\'s'",
+            r#""say \"hi\"""#,
+            r"'a\\b'",
+            r"'line\nbreak\t\r'",
+            r"'\u{4E2D}\u{1F600}'",
+            r"'\`\$'",
+        ]
+        .iter()
+        .for_each(|s| {
+            let rules = get_first_expression_rules(Formula::parse(s));
+            match_rules(rules.clone(), vec![Rule::literal]);
+        });
+
+        vec![
+            r"'\q'",
+            r"'\u{D800}'",
+            r"'\u{110000}'",
+            r"'\u{}'",
+            r"'\u4E2D'",
+            r"'end\'",
+        ]
+        .iter()
+        .for_each(|s| {
+            assert!(Formula::parse(s).is_err(), "{}", s);
+        });
+    }
+
+    #[test]
+    fn template_value() {
+        vec![
+            "``",
+            "`abc`",
+            "`Done: ${count(subtask)} tasks`",
+            "`${ a + 1 }${b}`",
+            "`$ and {} are text`",
+            r"`\${a} \` ${'`'}`",
+            "`a ${`b ${c}`} d`",
+        ]
+        .iter()
+        .for_each(|s| {
+            let rules = get_first_expression_rules(Formula::parse(s));
+            match_rules(rules, vec![Rule::template]);
+        });
+
+        vec!["`abc", "`${a`", "`${}`", r"`\u{D800}`"]
+            .iter()
+            .for_each(|s| {
+                assert!(Formula::parse(s).is_err(), "{}", s);
+            });
+    }
 }
 
 #[cfg(test)]
@@ -152,6 +205,61 @@ mod formula_parse_identifier {
     }
 }
 
+#[cfg(test)]
+mod formula_parse_literal_keyword {
+    use formula_rs_wasm::parse::parse::{Formula, Rule};
+
+    use crate::{get_first_expression_rules, match_rules};
+
+    #[test]
+    fn keyword_allow_value() {
+        vec![
+            ("true", Rule::boolean),
+            ("false", Rule::boolean),
+            ("null", Rule::null),
+            ("#2024-01-31#", Rule::date),
+            ("#2024-02-29T08:30#", Rule::date),
+            ("#2024-01-31 08:30:59Z#", Rule::date),
+        ]
+        .iter()
+        .for_each(|(s, rule)| {
+            let mut rules = get_first_expression_rules(Formula::parse(s));
+            assert_eq!(rules.clone().count(), 1);
+            match_rules(rules.clone(), vec![Rule::literal]);
+            match_rules(rules.next().unwrap().into_inner(), vec![*rule]);
+        });
+    }
+
+    #[test]
+    fn keyword_prefix_identifier() {
+        vec!["trueValue", "false_", "nullable", "null1"]
+            .iter()
+            .for_each(|s| {
+                let rules = get_first_expression_rules(Formula::parse(s));
+                match_rules(rules, vec![Rule::variable]);
+            });
+    }
+
+    #[test]
+    fn date_illegal_value() {
+        vec![
+            "#2024-02-30#",
+            "#2023-02-29#",
+            "#1969-12-31#",
+            "#2024-1-31#",
+            "#2024-01-31T24:00#",
+            "##",
+            "#2024-01-31",
+            "date('2024-13-01')",
+        ]
+        .iter()
+        .for_each(|s| {
+            let result = Formula::parse(s);
+            assert!(result.is_err(), "{}", s);
+        });
+    }
+}
+
 #[cfg(test)]
 mod formula_parse_operation {
     use formula_rs_wasm::parse::parse::{Formula, Rule};
@@ -170,6 +278,13 @@ mod formula_parse_operation {
             "-1! + 2",
             "2 ^ -2",
             "2 >> 1",
+            "-a",
+            "+a",
+            "-(1 + 2)",
+            "2 * -x",
+            "- 1",
+            "+-1",
+            "-+1",
         ]
         .iter()
         .for_each(|s| {
@@ -0,0 +1,590 @@
+#[cfg(test)]
+mod formula_program {
+    use expect_test::expect;
+    use formula_rs_wasm::{
+        parse::{
+            ast::{to_ast, Range},
+            parse::Formula,
+            to_operator::ToOperator,
+        },
+        share::{
+            operator::OperatorCode::{self, *},
+            program::{
+                CompileOptions, Constant, LoadErrorType, Program, COMPILER_VERSION, FORMAT_VERSION,
+                MAGIC,
+            },
+        },
+        types::types::FormulaValueType,
+        vm::{
+            context::RuntimeContext,
+            debugger::Debugger,
+            disassembler::disassemble,
+            error::{ExecuteError, ExecuteErrorType},
+            optimizer::optimize,
+            runner::Runner,
+            trace::{Observer, Step, Trace},
+            value::Value,
+            verifier::verify,
+        },
+    };
+    use num::Rational64;
+    use std::{cell::RefCell, rc::Rc, sync::Arc};
+
+    fn operators(expr: &str) -> Vec<OperatorCode> {
+        let (_, ast) = to_ast(Formula::parse(expr).unwrap().paris);
+        ast.to_operator()
+    }
+
+    fn compile(expr: &str, optimize: bool) -> Program {
+        let (_, ast) = to_ast(Formula::parse(expr).unwrap().paris);
+        Program::compile(&ast, None, &CompileOptions { optimize })
+    }
+
+    fn run(program: Program) -> Value {
+        let mut context = RuntimeContext::new();
+        context.inject_functions();
+        for dependency in &program.dependencies {
+            context.set(dependency.clone(), Value::Number(3.into()));
+        }
+        Runner
+            .run_program(&Arc::new(program), &mut context)
+            .unwrap()
+    }
+
+    #[test]
+    fn program_round_trip() {
+        let expr = "let a = estimatePoint * 2; sum([a, b])";
+        let program = Program::new(operators(expr), Some(FormulaValueType::Number));
+        assert_eq!(program.compiler_version, COMPILER_VERSION);
+        assert_eq!(
+            program.dependencies,
+            vec!["estimatePoint".to_string(), "b".to_string()]
+        );
+
+        let bytes = program.to_bytes();
+        assert_eq!(&bytes[..4], &MAGIC);
+        assert_eq!(&bytes[4..6], &FORMAT_VERSION.to_le_bytes());
+        let loaded = Program::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded, program);
+        assert_eq!(run(loaded), Value::Number(9.into()));
+    }
+
+    #[test]
+    fn program_constants_and_slots() {
+        let program = Program::new(
+            operators("let a = 'x' + 'y'; a + 'x' + title + COUNT(subtask; status=2) + count(where(subtask, $.title == a))"),
+            None,
+        );
+        assert_eq!(
+            program.constants,
+            ["x", "y", "status", "=", "2", "title"]
+                .iter()
+                .map(|text| Constant::String(text.to_string()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            program.slots,
+            vec!["a", "title", "COUNT", "subtask", "count", "where"]
+        );
+        assert_eq!(program.dependencies, vec!["title", "subtask"]);
+        assert_eq!(
+            &program.operators[..5],
+            &[
+                PushConstant(0),
+                PushConstant(1),
+                Add,
+                StoreSlot(0),
+                LoadSlot(0)
+            ]
+        );
+        assert!(program.operators.contains(&PushLambda(vec![
+            LoadIdentifier("$".to_string()),
+            LoadProperty(5),
+            LoadSlot(0),
+            Equal
+        ])));
+
+        let mut context = RuntimeContext::new();
+        context.inject_functions();
+        context.set("title".to_string(), Value::String("xy".to_string()));
+        context.set(
+            "subtask".to_string(),
+            Value::from_json(&serde_json::json!([
+                { "status": 2, "title": "xy" },
+                { "status": 1, "title": "z" }
+            ])),
+        );
+        let program = Arc::new(program);
+        assert_eq!(
+            Runner.run_program(&program, &mut context),
+            Ok(Value::String("xyxxy11".to_string()))
+        );
+        // 同一个 Program 可以对不同的 Issue 重复执行
+        context.set("title".to_string(), Value::String("-".to_string()));
+        assert_eq!(
+            Runner.run_program(&program, &mut context),
+            Ok(Value::String("xyx-11".to_string()))
+        );
+
+        context.heap.remove("title");
+        assert_eq!(
+            Runner
+                .run_program(&program, &mut context)
+                .unwrap_err()
+                .type_,
+            ExecuteErrorType::IdentifierNotFound
+        );
+    }
+
+    #[test]
+    fn program_upgrade_raw_bincode() {
+        // 旧版本 compile 直接保存的 bincode(Vec<OperatorCode>)
+        let raw = bincode::serialize(&operators("SUM(subtask.estimatePoint) + a")).unwrap();
+        let program = Program::from_bytes(&raw).unwrap();
+        assert_eq!(program.compiler_version, "");
+        assert_eq!(program.result_type, None);
+        assert_eq!(
+            program.dependencies,
+            vec!["subtask".to_string(), "a".to_string()]
+        );
+        assert_eq!(
+            program.operators,
+            vec![
+                LoadSlot(0),
+                LoadSlot(1),
+                LoadProperty(0),
+                Call(1),
+                LoadSlot(2),
+                Add
+            ]
+        );
+    }
+
+    #[test]
+    fn program_optimize() {
+        let optimized = |expr: &str| optimize(operators(expr));
+
+        assert_eq!(
+            optimized("2 ^ 10 * 1000 / 60"),
+            vec![PushNumber(Rational64::new(51200, 3))]
+        );
+        assert_eq!(
+            optimized("'prefix-' + 'x'"),
+            vec![PushString("prefix-x".to_string())]
+        );
+        assert_eq!(
+            optimized("`${1 + 1}-${null}`"),
+            vec![PushString("2-".to_string())]
+        );
+        assert_eq!(
+            optimized("a + (1 < 2)"),
+            vec![LoadIdentifier("a".to_string()), PushBool(true), Add]
+        );
+
+        // 只有一定是数字的值才化简，a 可能是字符串或者 Null
+        assert_eq!(
+            optimized("count(subtask) * 1 + 0"),
+            vec![
+                LoadIdentifier("count".to_string()),
+                LoadIdentifier("subtask".to_string()),
+                Call(1)
+            ]
+        );
+        assert_eq!(optimized("0 + sum(a) / 1"), optimized("sum(a)"));
+        assert_eq!(optimized("a * 1"), operators("a * 1"));
+        assert_eq!(
+            optimized("let count = 'x'; count * 1"),
+            operators("let count = 'x'; count * 1")
+        );
+        // Null 参与运算的结果取决于 NullMode
+        assert_eq!(optimized("null + 1"), operators("null + 1"));
+
+        // 不会执行的分支
+        assert_eq!(optimized("1 ?? a"), vec![PushNumber(1.into())]);
+        assert_eq!(
+            optimized("null ?? a"),
+            vec![LoadIdentifier("a".to_string())]
+        );
+        assert_eq!(optimized("null?.a"), vec![PushNull]);
+        // 跳转的条数按折叠后的指令修正
+        assert_eq!(
+            optimized("a ?? 2 * 3 + b"),
+            vec![
+                LoadIdentifier("a".to_string()),
+                Coalesce(3),
+                PushNumber(6.into()),
+                LoadIdentifier("b".to_string()),
+                Add
+            ]
+        );
+
+        let program = compile("1 + 2", false);
+        assert_eq!(
+            program.operators,
+            Program::new(operators("1 + 2"), None).operators
+        );
+        let program = compile("1 + 2", true);
+        assert_eq!(program.operators, vec![PushConstant(0)]);
+        assert_eq!(program.constants, vec![Constant::Number(3.into())]);
+
+        // 优化后的指令也要通过执行前的检查
+        for line in include_str!("data/data.txt").lines() {
+            if let Ok(formula) = Formula::parse(line) {
+                let (_, ast) = to_ast(formula.paris);
+                assert_eq!(verify(&optimize(ast.to_operator())), Ok(()), "{}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn program_optimize_deferred_errors() {
+        // 出错的常量表达式不影响编译，执行到的时候才报错
+        assert_eq!(
+            optimize(operators("1 / 0")),
+            vec![Throw(ExecuteErrorType::DivideByZero, None)]
+        );
+        let program = compile("a ?? 1 / 0", true);
+        let bytes = program.to_bytes();
+        let program = Program::from_bytes(&bytes).unwrap();
+        assert_eq!(run(program.clone()), Value::Number(3.into()));
+
+        let mut context = RuntimeContext::new();
+        context.set("a".to_string(), Value::Null);
+        assert_eq!(
+            Runner
+                .run_program(&Arc::new(program), &mut context)
+                .unwrap_err()
+                .type_,
+            ExecuteErrorType::DivideByZero
+        );
+        let program = compile("3! + 1.5!", true);
+        assert_eq!(
+            Runner
+                .run_program(&Arc::new(program), &mut RuntimeContext::new())
+                .unwrap_err()
+                .type_,
+            ExecuteErrorType::FactorialNotInteger
+        );
+    }
+
+    #[test]
+    fn program_source_map() {
+        let program = compile("let a = x * 2; a + 1; sum(s, $.p * 2)", false);
+        assert_eq!(
+            program.source_map.ranges,
+            vec![
+                Range(8, 10),  // x
+                Range(12, 13), // 2
+                Range(8, 13),  // x * 2
+                Range(0, 13),  // let a = x * 2
+                Range(15, 17), // a
+                Range(19, 20), // 1
+                Range(15, 20), // a + 1
+                Range(15, 20), // 丢弃 a + 1 的值
+                Range(22, 25), // sum
+                Range(26, 27), // s
+                Range(29, 36), // $.p * 2
+                Range(22, 37), // sum(...)
+            ]
+        );
+        assert_eq!(
+            program.source_map.lambdas[0].ranges,
+            vec![Range(29, 30), Range(29, 32), Range(35, 36), Range(29, 36)]
+        );
+        let loaded = Program::from_bytes(&program.to_bytes()).unwrap();
+        assert_eq!(loaded.source_map, program.source_map);
+
+        // 折叠后的指令对应所有参与计算的表达式
+        let program = compile("a ?? 2 * 3 + (1 / 0)", true);
+        assert_eq!(
+            program.operators,
+            vec![
+                LoadSlot(0),
+                Coalesce(3),
+                PushConstant(0),
+                Throw(ExecuteErrorType::DivideByZero, None),
+                Add
+            ]
+        );
+        assert_eq!(
+            program.source_map.ranges,
+            vec![
+                Range(0, 2),
+                Range(0, 19),
+                Range(5, 10),
+                Range(14, 19),
+                Range(5, 19)
+            ]
+        );
+    }
+
+    #[test]
+    fn program_error_ranges() {
+        let run_error = |expr: &str, data: serde_json::Value| {
+            let program = Arc::new(compile(expr, true));
+            let mut context = RuntimeContext::new();
+            context.inject_functions();
+            for (key, value) in data.as_object().unwrap() {
+                context.set(key.clone(), Value::from_json(value));
+            }
+            let error = Runner.run_program(&program, &mut context).unwrap_err();
+            (error.type_, error.range)
+        };
+
+        assert_eq!(
+            run_error(
+                "SUM(subtask.estimatePoint) / COUNT(subtask) + 1",
+                serde_json::json!({ "subtask": [] })
+            ),
+            (ExecuteErrorType::DivideByZero, Some(Range(0, 43)))
+        );
+        // lambda 中的错误对应 lambda 中的子表达式，而不是调用函数的位置
+        assert_eq!(
+            run_error(
+                "1 + sum(s, $.a * 2)",
+                serde_json::json!({ "s": [{ "a": 1 }, { "a": "x" }] })
+            ),
+            (ExecuteErrorType::OperatorMismatchError, Some(Range(11, 18)))
+        );
+        // 折叠出的 Throw 对应原来的表达式
+        assert_eq!(
+            run_error("a ?? 1 / 0", serde_json::json!({ "a": null })),
+            (ExecuteErrorType::DivideByZero, Some(Range(5, 10)))
+        );
+        // let 绑定之前使用不会读取同名的字段
+        assert_eq!(
+            run_error("y+1; let y = 2", serde_json::json!({ "y": 1 })),
+            (ExecuteErrorType::IdentifierNotFound, Some(Range(0, 1)))
+        );
+        assert_eq!(
+            compile("y + 1; let y = 2; z", true).dependencies,
+            vec!["z".to_string()]
+        );
+        // 只有 let 语句时指向最后一条语句
+        assert_eq!(
+            run_error("let a = 1; let b = 2", serde_json::json!({})),
+            (ExecuteErrorType::NoResultExpression, Some(Range(11, 20)))
+        );
+
+        // 升级上来的字节码没有源码位置
+        let raw = bincode::serialize(&operators("1 / a")).unwrap();
+        let program = Arc::new(Program::from_bytes(&raw).unwrap());
+        let mut context = RuntimeContext::new();
+        context.set("a".to_string(), Value::Number(0.into()));
+        let error = Runner.run_program(&program, &mut context).unwrap_err();
+        assert_eq!(error.type_, ExecuteErrorType::DivideByZero);
+        assert_eq!(error.range, None);
+    }
+
+    #[test]
+    fn program_trace() {
+        let trace = |expr: &str, data: serde_json::Value| {
+            let program = Arc::new(compile(expr, true));
+            let mut context = RuntimeContext::new();
+            context.inject_functions();
+            for (key, value) in data.as_object().unwrap() {
+                context.set(key.clone(), Value::from_json(value));
+            }
+            Trace::record(&program, &mut context)
+        };
+        let number = |value: i64| Value::Number(value.into());
+
+        let expr = "let a = x * 2; sum(s, $.p + a)";
+        let data = serde_json::json!({ "x": 3, "s": [{ "p": 1 }, { "p": 2 }] });
+        let result = trace(expr, data.clone());
+        assert_eq!(result.result, Ok(number(15)));
+        assert_eq!(
+            result.inputs,
+            vec![
+                ("s".to_string(), Value::from_json(&data["s"])),
+                ("x".to_string(), number(3)),
+            ]
+        );
+        // lambda 中的指令在 Call 之前，每个元素执行一遍
+        let steps = result
+            .steps
+            .iter()
+            .map(|step| {
+                (
+                    step.depth,
+                    step.operator.clone(),
+                    step.stack.last().cloned(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(steps.iter().filter(|step| step.0 == 1).count(), 8);
+        assert_eq!(steps[steps.len() - 2].1, Add);
+        assert_eq!(steps[steps.len() - 2].2, Some(number(8)));
+        assert_eq!(steps.last().unwrap().1, Call(2));
+        assert_eq!(steps.last().unwrap().2, Some(number(15)));
+
+        let mut debugger = Debugger::new(result);
+        assert_eq!(debugger.current(), None);
+        // $.p + a
+        debugger.add_breakpoint(Range(22, 29));
+        // 先停在创建 lambda 的指令，再停在 lambda 中的指令
+        let step = debugger.resume().unwrap();
+        assert!(matches!((step.depth, &step.operator), (0, PushLambda(_))));
+        let step = debugger.resume().unwrap();
+        assert_eq!((step.depth, step.pc), (1, 0));
+        assert_eq!(step.item, Some(Value::from_json(&data["s"][0])));
+        let heap = debugger.heap();
+        assert!(heap.contains(&("a".to_string(), number(6))));
+        assert!(heap.contains(&("$".to_string(), Value::from_json(&data["s"][0]))));
+
+        debugger.step();
+        debugger.step();
+        assert_eq!(debugger.stack(), &[number(1), number(6)]);
+        let step = debugger.step().unwrap();
+        assert_eq!(
+            (&step.operator, step.range.clone()),
+            (&Add, Some(Range(22, 29)))
+        );
+        assert_eq!(debugger.stack(), &[number(7)]);
+
+        assert!(debugger.remove_breakpoint(&Range(22, 29)));
+        assert_eq!(debugger.resume(), None);
+        assert!(debugger.is_finished());
+        debugger.restart();
+        assert_eq!(debugger.stack(), &[] as &[Value]);
+
+        // 出错的指令带有错误和源码位置，之后不再有记录
+        let result = trace("1 + x / 0", serde_json::json!({ "x": 1 }));
+        let step = result.steps.last().unwrap();
+        assert_eq!(step.operator, Divide);
+        let error = step.error.as_ref().unwrap();
+        assert_eq!(error.type_, ExecuteErrorType::DivideByZero);
+        assert_eq!(error.range, Some(Range(4, 9)));
+        assert_eq!(result.result.unwrap_err().range, Some(Range(4, 9)));
+
+        // 自定义的 observer，before 和 after 成对出现
+        #[derive(Default)]
+        struct Depth {
+            current: usize,
+            max: usize,
+            steps: usize,
+        }
+        impl Observer for Depth {
+            fn before(&mut self, _step: &Step, _ctx: &RuntimeContext) {
+                self.current += 1;
+                self.max = self.max.max(self.current);
+            }
+            fn after(
+                &mut self,
+                _step: &Step,
+                _ctx: &RuntimeContext,
+                _error: Option<&ExecuteError>,
+            ) {
+                self.current -= 1;
+                self.steps += 1;
+            }
+        }
+        let observer = Rc::new(RefCell::new(Depth::default()));
+        let mut context = RuntimeContext::new();
+        context.inject_functions();
+        context.set("s".to_string(), Value::from_json(&data["s"]));
+        context.observer = Some(Box::new(observer.clone()));
+        let value = Runner.run(operators("count(where(s, $.p > 1))"), &mut context);
+        assert_eq!(value, Ok(number(1)));
+        assert_eq!(observer.borrow().current, 0);
+        assert_eq!(observer.borrow().max, 2);
+        assert_eq!(observer.borrow().steps, 14);
+    }
+
+    #[test]
+    fn program_disassemble() {
+        let expr = "let a = x * 2; sum(s, $.p + 0) ?? a";
+        let (_, ast) = to_ast(Formula::parse(expr).unwrap().paris);
+        let program = Program::compile(
+            &ast,
+            Some(FormulaValueType::Number),
+            &CompileOptions::default(),
+        );
+        expect![[r#"
+            ; compiler 0.1.0, result Number
+            ; constant 0 = 2
+            ; constant 1 = "p"
+            ; constant 2 = 0
+            ; slot 0 = x
+            ; slot 1 = a
+            ; slot 2 = sum
+            ; slot 3 = s
+            ; dependencies: x, s
+                 #  instruction                              depth  source
+                 0  LoadSlot(0)  x                               1  8..10  x
+                 1  PushConstant(0)  2                           2  12..13  2
+                 2  Multiply                                     1  8..13  x * 2
+                 3  StoreSlot(1)  a                              0  0..13  let a = x * 2
+                 4  LoadSlot(2)  sum                             1  15..18  sum
+                 5  LoadSlot(3)  s                               2  19..20  s
+                 6  PushLambda(4)                                3  22..29  $.p + 0
+               6.0  LoadIdentifier("$")                          1  22..23  $
+               6.1  LoadProperty(1)  .p                          1  22..25  $.p
+               6.2  PushConstant(2)  0                           2  28..29  0
+               6.3  Add                                          1  22..29  $.p + 0
+                 7  Call(2)                                      1  15..30  sum(s, $.p + 0)
+                 8  Coalesce(1)  -> 10                           0  15..35  sum(s, $.p + 0) ?? a
+                 9  LoadSlot(1)  a                               1  34..35  a"#]]
+        .assert_eq(&disassemble(&program, Some(expr)));
+
+        // 保存的字节码没有公式的文本
+        let loaded = Program::from_bytes(&program.to_bytes()).unwrap();
+        expect![[r#"
+            ; compiler 0.1.0, result Number
+            ; constant 0 = 2
+            ; constant 1 = "p"
+            ; constant 2 = 0
+            ; slot 0 = x
+            ; slot 1 = a
+            ; slot 2 = sum
+            ; slot 3 = s
+            ; dependencies: x, s
+                 #  instruction                              depth  source
+                 0  LoadSlot(0)  x                               1  8..10
+                 1  PushConstant(0)  2                           2  12..13
+                 2  Multiply                                     1  8..13
+                 3  StoreSlot(1)  a                              0  0..13
+                 4  LoadSlot(2)  sum                             1  15..18
+                 5  LoadSlot(3)  s                               2  19..20
+                 6  PushLambda(4)                                3  22..29
+               6.0  LoadIdentifier("$")                          1  22..23
+               6.1  LoadProperty(1)  .p                          1  22..25
+               6.2  PushConstant(2)  0                           2  28..29
+               6.3  Add                                          1  22..29
+                 7  Call(2)                                      1  15..30
+                 8  Coalesce(1)  -> 10                           0  15..35
+                 9  LoadSlot(1)  a                               1  34..35"#]]
+        .assert_eq(&disassemble(&loaded, None));
+    }
+
+    #[test]
+    fn program_load_errors() {
+        let bytes = Program::new(operators("1 + 2"), None).to_bytes();
+        let error_type = |bytes: &[u8]| Program::from_bytes(bytes).unwrap_err().type_;
+
+        let mut corrupted = bytes.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        assert_eq!(error_type(&corrupted), LoadErrorType::ChecksumMismatch);
+        assert_eq!(
+            error_type(&bytes[..bytes.len() - 1]),
+            LoadErrorType::ChecksumMismatch
+        );
+        assert_eq!(error_type(&bytes[..7]), LoadErrorType::DecodeError);
+
+        let mut newer = bytes.clone();
+        newer[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let error = Program::from_bytes(&newer).unwrap_err();
+        assert_eq!(error.type_, LoadErrorType::UnsupportedVersion);
+        assert_eq!(
+            error.message,
+            Some(format!(
+                "format version {} is newer than {}",
+                FORMAT_VERSION + 1,
+                FORMAT_VERSION
+            ))
+        );
+
+        assert_eq!(error_type(&[1, 2, 3]), LoadErrorType::DecodeError);
+    }
+}
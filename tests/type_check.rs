@@ -23,3 +23,269 @@
 //         // );
 //     }
 // }
+
+#[cfg(test)]
+mod formula_check_formula {
+    use formula_rs_wasm::types::{
+        check::check_formula, error::TypeErrorType, schema::Schema, target::TargetType,
+        types::FormulaValueType,
+    };
+
+    fn schema() -> Schema {
+        let json = serde_json::json!({
+            "estimatePoint": "Number",
+            "dueDate": "DateTime",
+            "startDate": "DateTime",
+            "title": "String",
+            "subtask": {
+                "type": "Array",
+                "fields": { "estimatePoint": "Number", "status": "Number" }
+            },
+            "relationship": "Array",
+            "parent": {
+                "type": "Object",
+                "fields": {
+                    "estimatePoint": "Number",
+                    "assignee": { "type": "Object", "fields": { "name": "String" } }
+                }
+            }
+        });
+        Schema::from_json(&json).unwrap()
+    }
+
+    fn check(expr: &str, target: &str, expected: FormulaValueType) {
+        assert_eq!(check_formula(expr, target, &schema()), Ok(expected));
+    }
+
+    fn check_mismatch(expr: &str, target: &str, result: FormulaValueType) {
+        let error = check_formula(expr, target, &schema()).unwrap_err();
+        assert_eq!(
+            error.type_,
+            TypeErrorType::TargetMismatch(result, TargetType::from_name(target).unwrap())
+        );
+        assert!(!error.suggestions.is_empty());
+    }
+
+    #[test]
+    fn check_success() {
+        check(
+            "let done = where(subtask, $.status == 2);\nsum(done.estimatePoint) // 已完成",
+            "decimal",
+            FormulaValueType::Number,
+        );
+        check(
+            "let a = title; let a = length(a); a * 2",
+            "integer",
+            FormulaValueType::Number,
+        );
+        check("estimatePoint * 2", "integer", FormulaValueType::Number);
+        check(
+            "COUNT(relationship;)",
+            "percentage_number",
+            FormulaValueType::Number,
+        );
+        check(
+            "SUM(subtask.estimatePoint;status=2)",
+            "decimal",
+            FormulaValueType::Number,
+        );
+        check(
+            "sum(where(subtask, $.status == 2), $.estimatePoint)",
+            "decimal",
+            FormulaValueType::Number,
+        );
+        check(
+            "count(where(subtask, $.status >= 2))",
+            "integer",
+            FormulaValueType::Number,
+        );
+        check(
+            "subtask[0].estimatePoint",
+            "decimal",
+            FormulaValueType::Number,
+        );
+        check(
+            "subtask.estimatePoint[-1]",
+            "decimal",
+            FormulaValueType::Number,
+        );
+        check("[1, 2, 4][0] * 2", "integer", FormulaValueType::Number);
+        check("count(subtask[1:])", "integer", FormulaValueType::Number);
+        assert_eq!(
+            check_formula("in(estimatePoint, [1, 2, 4])", "integer", &schema())
+                .unwrap_err()
+                .type_,
+            TypeErrorType::TargetMismatch(FormulaValueType::Bool, TargetType::Integer)
+        );
+        check(
+            "parent.estimatePoint * 2",
+            "integer",
+            FormulaValueType::Number,
+        );
+        check(
+            "(parent?.estimatePoint ?? 0) * 2",
+            "integer",
+            FormulaValueType::Number,
+        );
+        check(
+            "parent?.estimatePoint ?? subtask[0].estimatePoint",
+            "integer",
+            FormulaValueType::Number,
+        );
+        check("-estimatePoint * 2", "integer", FormulaValueType::Number);
+        check(
+            "estimatePoint & 4 | 1 << 3",
+            "integer",
+            FormulaValueType::Number,
+        );
+        check("GET_NOW", "datetime", FormulaValueType::DateTime);
+        check("#2024-01-31#", "datetime", FormulaValueType::DateTime);
+        check(
+            "length(`${title}：${estimatePoint}`)",
+            "integer",
+            FormulaValueType::Number,
+        );
+        assert_eq!(
+            check_formula("upper(substring(title, 0, 2))", "integer", &schema())
+                .unwrap_err()
+                .type_,
+            TypeErrorType::TargetMismatch(FormulaValueType::String, TargetType::Integer)
+        );
+        check(
+            "date('2024-01-31') - dueDate",
+            "integer",
+            FormulaValueType::Duration,
+        );
+        check("estimatePoint ?? null", "integer", FormulaValueType::Number);
+        assert_eq!(
+            check_formula("dueDate < #2024-01-31#", "integer", &schema())
+                .unwrap_err()
+                .type_,
+            TypeErrorType::TargetMismatch(FormulaValueType::Bool, TargetType::Integer)
+        );
+        check(
+            "GET_NOW-GET_UPDATE_TIME",
+            "integer",
+            FormulaValueType::Duration,
+        );
+        check(
+            "GET_CREATE_TIME + (GET_NOW-GET_UPDATE_TIME)",
+            "datetime",
+            FormulaValueType::DateTime,
+        );
+        check(
+            "GET_CREATE_TIME + 2 * (GET_NOW-GET_UPDATE_TIME)",
+            "datetime",
+            FormulaValueType::DateTime,
+        );
+    }
+
+    #[test]
+    fn check_target_mismatch() {
+        let error = check_formula("GET_NOW-GET_UPDATE_TIME", "datetime", &schema()).unwrap_err();
+        assert_eq!(
+            error.message,
+            Some("Duration produced but field expects datetime".to_string())
+        );
+        assert_eq!(
+            error.suggestions[1],
+            "add the duration to a datetime to get a datetime, e.g. `GET_CREATE_TIME + (GET_NOW-GET_UPDATE_TIME)`"
+        );
+
+        check_mismatch("dueDate-startDate", "datetime", FormulaValueType::Duration);
+        check_mismatch("estimatePoint", "date", FormulaValueType::Number);
+        check_mismatch("dueDate", "integer", FormulaValueType::DateTime);
+        check_mismatch("subtask.estimatePoint", "decimal", FormulaValueType::Array);
+    }
+
+    #[test]
+    fn check_error() {
+        let check_error = |expr: &str, target: &str, type_: TypeErrorType| {
+            assert_eq!(
+                check_formula(expr, target, &schema()).unwrap_err().type_,
+                type_
+            );
+        };
+
+        check_error(
+            "unknownField + 1",
+            "integer",
+            TypeErrorType::IdentifierNotFound,
+        );
+        check_error("subtask.name", "integer", TypeErrorType::PropertyNotFound);
+        check_error(
+            "COUNT(subtask;name=2)",
+            "integer",
+            TypeErrorType::PropertyNotFound,
+        );
+        check_error(
+            "count(where(subtask, $.name == 2))",
+            "integer",
+            TypeErrorType::PropertyNotFound,
+        );
+        check_error(
+            "subtask[0].name",
+            "integer",
+            TypeErrorType::PropertyNotFound,
+        );
+        check_error(
+            "title[0]",
+            "integer",
+            TypeErrorType::OperatorMismatchError(
+                formula_rs_wasm::types::operator::FormulaOperator::Index,
+                FormulaValueType::String,
+                Some(FormulaValueType::Number),
+            ),
+        );
+        check_error(
+            "title >> 1",
+            "integer",
+            TypeErrorType::OperatorMismatchError(
+                formula_rs_wasm::types::operator::FormulaOperator::ShiftRight,
+                FormulaValueType::String,
+                Some(FormulaValueType::Number),
+            ),
+        );
+        check_error(
+            "-title",
+            "integer",
+            TypeErrorType::OperatorMismatchError(
+                formula_rs_wasm::types::operator::FormulaOperator::Neg,
+                FormulaValueType::String,
+                None,
+            ),
+        );
+        check_error(
+            "in(title, estimatePoint)",
+            "integer",
+            TypeErrorType::FunctionInvalidArgument,
+        );
+        check_error(
+            "parent?.assignee.age ?? 0",
+            "integer",
+            TypeErrorType::PropertyNotFound,
+        );
+        check_error(
+            "parent.assignee.age",
+            "integer",
+            TypeErrorType::PropertyNotFound,
+        );
+        check_error("AVG(subtask)", "integer", TypeErrorType::FunctionNotFound);
+        check_error(
+            "SUM(title)",
+            "integer",
+            TypeErrorType::FunctionInvalidArgument,
+        );
+        check_error("1 +", "integer", TypeErrorType::ParseError);
+        check_error("1", "text", TypeErrorType::UnknownTargetType);
+        check_error(
+            "title - 1",
+            "integer",
+            TypeErrorType::OperatorMismatchError(
+                formula_rs_wasm::types::operator::FormulaOperator::Sub,
+                FormulaValueType::String,
+                Some(FormulaValueType::Number),
+            ),
+        );
+    }
+}
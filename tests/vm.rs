@@ -2,8 +2,15 @@
 mod formula_parse_ast {
     use formula_rs_wasm::{
         parse::{ast::to_ast, parse::Formula, to_operator::ToOperator},
-        vm::{context::RuntimeContext, error::ExecuteError, runner::Runner, value::Value},
+        vm::{
+            context::{NullMode, RuntimeContext},
+            error::{ExecuteError, ExecuteErrorType},
+            runner::Runner,
+            value::Value,
+        },
     };
+    use num::Rational64;
+
     #[test]
     fn vm_demo() {
         fn run(expr: &str) -> Result<Value, ExecuteError> {
@@ -106,6 +113,65 @@ mod formula_parse_ast {
         check("a ^ a", Value::Number(4.into()));
         check("b + 1", Value::String("abc1".to_string()));
         check("GET_NOW", Value::DateTime(0));
+        check("-a", Value::Number((-2).into()));
+        check("+a", Value::Number(2.into()));
+        check("-(1 + 2)", Value::Number((-3).into()));
+        check("2 * -a", Value::Number((-4).into()));
+        check("a - -a", Value::Number(4.into()));
+        check("- -a", Value::Number(2.into()));
+        check("-a ^ 2", Value::Number(4.into()));
+        check("-a!", Value::Number((-2).into()));
+        check("(1-2)*-3/4", Value::Number(Rational64::new(3, 4)));
+        check("2 ^ -1", Value::Number(Rational64::new(1, 2)));
+
+        check("1 << 4", Value::Number(16.into()));
+        check("-16 >> 2", Value::Number((-4).into()));
+        check("7 div 2", Value::Number(3.into()));
+        check("-7 div 2", Value::Number((-3).into()));
+        check("6 & 3", Value::Number(2.into()));
+        check("6 | 3", Value::Number(7.into()));
+        check("6 xor 3", Value::Number(5.into()));
+        check("1 | 2 & 6 xor 4", Value::Number(7.into()));
+        check("a << 1 + 1", Value::Number(8.into()));
+        check("(a | 4) & 4 == 4", Value::Bool(true));
+        check("4 / 2 div 2", Value::Number(1.into()));
+        check_error(
+            "1.5 & 1",
+            ExecuteError::operand_not_integer("&", "3/2".to_string()),
+        );
+        check_error(
+            "3 >> 0.5",
+            ExecuteError::operand_not_integer(">>", "1/2".to_string()),
+        );
+        check_error("1 div 0", ExecuteError::divide_by_zero());
+        check_error("1 << 64", ExecuteError::shift_out_of_range(64));
+        check_error("1 << -1", ExecuteError::shift_out_of_range(-1));
+        check_error("16 >> 64", ExecuteError::shift_out_of_range(64));
+        assert_eq!(
+            run("1 << 64").unwrap_err().message,
+            Some("shift count 64 out of range 0..64".to_string())
+        );
+        check_error("4611686018427387904 << 1", ExecuteError::number_overflow());
+        check_error(
+            "-9223372036854775808 div -1",
+            ExecuteError::number_overflow(),
+        );
+        check_error(
+            "b | 1",
+            ExecuteError::operator_mismatch(
+                "|".to_string(),
+                Value::String("abc".to_string()).to_string(),
+                Some(Value::Number(1.into()).to_string()),
+            ),
+        );
+        check_error(
+            "-b",
+            ExecuteError::operator_mismatch(
+                "-".to_string(),
+                Value::String("abc".to_string()).to_string(),
+                None,
+            ),
+        );
         check_error(
             "c + 1",
             ExecuteError::identifier_not_found(&"c".to_string()),
@@ -144,6 +210,763 @@ mod formula_parse_ast {
         check("COUNT(subtask)", Value::Number(4.into()));
 
         check("COUNT(subtask; status == 1)", Value::Number(2.into()));
-        check("COUNT(relationship;relationship=CHILD)", Value::Number(2.into()));
+        check(
+            "COUNT(relationship;relationship=CHILD)",
+            Value::Number(2.into()),
+        );
+
+        check("1 < 2", Value::Bool(true));
+        check("a == 3", Value::Bool(false));
+        check("count(subtask)", Value::Number(4.into()));
+        check(
+            "count(where(subtask, $.status == 1))",
+            Value::Number(2.into()),
+        );
+        check(
+            "count(where(relationship, $.relationship == 'CHILD'))",
+            Value::Number(2.into()),
+        );
+        // 没有旧的过滤写法时，大写和小写的函数结果相同
+        for (upper, lower) in [
+            (
+                "SUM(where(subtask, $.status == 1).estimatePoint)",
+                "sum(where(subtask, $.status == 1).estimatePoint)",
+            ),
+            (
+                "COUNT(where(subtask, $.status == 2))",
+                "count(where(subtask, $.status == 2))",
+            ),
+            (
+                "SUM(subtask, $.estimatePoint * 2)",
+                "sum(subtask, $.estimatePoint * 2)",
+            ),
+        ] {
+            assert_eq!(run(upper), run(lower), "{}", upper);
+            assert!(run(upper).is_ok(), "{}", upper);
+        }
+        check(
+            "sum(where(subtask, $.status == 2), $.estimatePoint)",
+            Value::Number(7.into()),
+        );
+        check(
+            "sum(subtask, $.estimatePoint * a)",
+            Value::Number(20.into()),
+        );
+        check(
+            "count(where(where(subtask, $.status == 2), $.estimatePoint > 3))",
+            Value::Number(1.into()),
+        );
+        check_error(
+            "where(subtask, $.status)",
+            ExecuteError::function_invalid_argument(vec!["Bool"], vec!["Number"]),
+        );
+
+        let numbers = |items: &[i64]| {
+            Value::Array(items.iter().map(|n| Value::Number((*n).into())).collect())
+        };
+        check("[1, 2, 1 + 2]", numbers(&[1, 2, 3]));
+        check("[]", numbers(&[]));
+        check("[1, [2]][1]", numbers(&[2]));
+        check("arr[0]", Value::Number(1.into()));
+        check("arr[-1]", Value::Number(2.into()));
+        check("arr[2]", Value::Null);
+        check("arr[-3]", Value::Null);
+        check("subtask[a - 1].estimatePoint", Value::Number(2.into()));
+        check("subtask[-1].status == 2", Value::Bool(true));
+        check("subtask.estimatePoint[1:3]", numbers(&[2, 3]));
+        check("subtask.estimatePoint[-2:]", numbers(&[3, 4]));
+        check("subtask.estimatePoint[:1]", numbers(&[1]));
+        check("subtask.estimatePoint[3:1]", numbers(&[]));
+        check("count(subtask[1:10])", Value::Number(3.into()));
+        check("in(a, [1, 2, 4])", Value::Bool(true));
+        check("in(b, ['x', 'y'])", Value::Bool(false));
+        check(
+            "count(where(subtask, in($.estimatePoint, [1, 3, 4])))",
+            Value::Number(3.into()),
+        );
+        check_error("arr[0.5]", ExecuteError::index_not_integer("0.5"));
+        check_error("a[0]", ExecuteError::index_input_not_array("Number"));
+    }
+
+    #[test]
+    fn vm_object_access() {
+        fn run(expr: &str, null_mode: NullMode) -> Result<Value, ExecuteError> {
+            let formula = Formula::parse(expr).unwrap();
+            let (_, ast) = to_ast(formula.paris);
+
+            let mut context = RuntimeContext::new();
+            context.inject_functions();
+            context.null_mode = null_mode;
+
+            let issue = serde_json::json!({
+                "parent": {
+                    "estimatePoint": 5,
+                    "assignee": { "name": "Alice", "department": { "name": "R&D" } }
+                },
+                "orphan": null,
+                "subtask": [
+                    { "assignee": { "name": "Bob" } },
+                    { "assignee": null },
+                    null
+                ]
+            });
+            for (key, value) in issue.as_object().unwrap() {
+                context.set(key.to_string(), Value::from_json(value));
+            }
+
+            Runner.run(ast.to_operator(), &mut context)
+        }
+
+        let check = |expr: &str, target: Value| {
+            assert_eq!(run(expr, NullMode::Propagate), Ok(target), "{}", expr);
+        };
+        let string = |s: &str| Value::String(s.to_string());
+
+        check("parent.estimatePoint * 2", Value::Number(10.into()));
+        check("parent.assignee.name", string("Alice"));
+        check("parent.assignee.department.name", string("R&D"));
+        check("parent.reporter", Value::Null);
+        check("parent.reporter.name", Value::Null);
+        check("orphan.assignee.name", Value::Null);
+        check("orphan[0]", Value::Null);
+        check(
+            "subtask.assignee.name",
+            Value::Array(vec![string("Bob"), Value::Null, Value::Null]),
+        );
+
+        let check_strict = |expr: &str, err: ExecuteError| {
+            assert_eq!(run(expr, NullMode::Strict), Err(err), "{}", expr);
+        };
+        assert_eq!(
+            run("parent.assignee.name", NullMode::Strict),
+            Ok(string("Alice"))
+        );
+        check_strict(
+            "orphan.assignee",
+            ExecuteError::dot_input_not_object_array(&"assignee".to_string()),
+        );
+        check_strict(
+            "parent.reporter.name",
+            ExecuteError::dot_input_not_object_array(&"name".to_string()),
+        );
+        check_strict(
+            "subtask.assignee",
+            ExecuteError::dot_input_not_object_array(&"assignee".to_string()),
+        );
+        check_strict("orphan[0]", ExecuteError::index_input_not_array("Null"));
+    }
+
+    #[test]
+    fn vm_null_operators() {
+        fn run(expr: &str, null_mode: NullMode) -> Result<Value, ExecuteError> {
+            let formula = Formula::parse(expr).unwrap();
+            let (_, ast) = to_ast(formula.paris);
+
+            let mut context = RuntimeContext::new();
+            context.inject_functions();
+            context.null_mode = null_mode;
+
+            let issue = serde_json::json!({
+                "parent": { "estimatePoint": 5, "assignee": null },
+                "orphan": null,
+                "subtask": [{ "estimatePoint": 3 }, { "estimatePoint": null }]
+            });
+            for (key, value) in issue.as_object().unwrap() {
+                context.set(key.to_string(), Value::from_json(value));
+            }
+
+            Runner.run(ast.to_operator(), &mut context)
+        }
+
+        let number = |n: i64| Value::Number(n.into());
+
+        // ?. 和 ?? 不受模式影响
+        for null_mode in [NullMode::Strict, NullMode::Propagate, NullMode::Lenient] {
+            let check = |expr: &str, target: Value| {
+                assert_eq!(run(expr, null_mode), Ok(target), "{} {:?}", expr, null_mode);
+            };
+            check("orphan?.assignee", Value::Null);
+            check("orphan?.assignee.name[0]", Value::Null);
+            check("parent?.estimatePoint", number(5));
+            check("parent.assignee?.name", Value::Null);
+            check("orphan?.estimatePoint ?? 0", number(0));
+            check("parent?.estimatePoint ?? 0", number(5));
+            check("orphan ?? parent.assignee ?? 1 + 2", number(3));
+            check("(orphan ?? 2) * 3", number(6));
+            check("orphan ?? 0 > 3", Value::Bool(false));
+            check(
+                "count(where(subtask, ($.estimatePoint ?? 0) > 1))",
+                number(1),
+            );
+            // 右边只在左边为 Null 时执行
+            check("parent.estimatePoint ?? missing", number(5));
+            // Null 只等于 Null，大小比较的结果是 false
+            check("orphan == null", Value::Bool(true));
+            check("orphan != null", Value::Bool(false));
+            check("parent != null", Value::Bool(true));
+            check("parent.assignee == null", Value::Bool(true));
+            check("null == null", Value::Bool(true));
+            check("orphan != 1", Value::Bool(true));
+            check("orphan == 1", Value::Bool(false));
+            check("orphan > 1", Value::Bool(false));
+            // where 的条件中和 Null 比较的结果都是 false，跳过属性为 Null 的元素
+            check("count(where(subtask, $.estimatePoint != 3))", number(0));
+            check("count(where(subtask, $.estimatePoint == null))", number(0));
+            check(
+                "count(where(subtask, ($.estimatePoint ?? 0) == 0))",
+                number(1),
+            );
+            check("COUNT(subtask; estimatePoint != 3)", number(0));
+            // 旧的 SUM、COUNT 中的跳转不能被打乱
+            check("SUM(orphan ?? parent.estimatePoint)", number(5));
+            check("SUM(parent?.estimatePoint, 1)", number(6));
+            check("COUNT(parent?.estimatePoint ?? subtask)", number(1));
+            check("COUNT(orphan?.subtask ?? subtask)", number(2));
+        }
+
+        assert_eq!(
+            run("orphan ?? missing", NullMode::Propagate),
+            Err(ExecuteError::identifier_not_found(&"missing".to_string()))
+        );
+        assert_eq!(
+            run("orphan.assignee", NullMode::Strict),
+            Err(ExecuteError::dot_input_not_object_array(
+                &"assignee".to_string()
+            ))
+        );
+
+        // 只有 Lenient 模式下算术运算的 Null 结果为 Null
+        for expr in [
+            "orphan + 1",
+            "2 * parent.assignee",
+            "orphan!",
+            "subtask[1].estimatePoint ^ 2",
+        ] {
+            assert_eq!(run(expr, NullMode::Lenient), Ok(Value::Null), "{}", expr);
+            assert!(run(expr, NullMode::Propagate).is_err(), "{}", expr);
+        }
+        assert_eq!(
+            run("orphan + 1 ?? 0", NullMode::Lenient),
+            Ok(Value::Number(0.into()))
+        );
+        assert_eq!(
+            run("orphan == 1", NullMode::Lenient),
+            Ok(Value::Bool(false))
+        );
+    }
+
+    #[test]
+    fn vm_literals() {
+        fn run(expr: &str) -> Result<Value, ExecuteError> {
+            let formula = Formula::parse(expr).unwrap();
+            let (_, ast) = to_ast(formula.paris);
+
+            let mut context = RuntimeContext::new();
+            context.inject_functions();
+            context.set(
+                "dueDate".to_string(),
+                Value::String("2024-02-01".to_string()),
+            );
+            context.set("remark".to_string(), Value::String("tomorrow".to_string()));
+            // 和 Issue 数据一样，时间字段和 GET_NOW 是毫秒时间戳数字
+            context.set(
+                "createTime".to_string(),
+                Value::from_json(&serde_json::json!(1706659200000i64)),
+            );
+            context.set(
+                "GET_NOW".to_string(),
+                Value::Number(1706745600000i64.into()),
+            );
+
+            Runner.run(ast.to_operator(), &mut context)
+        }
+
+        let check = |expr: &str, target: Value| {
+            assert_eq!(run(expr), Ok(target), "{}", expr);
+        };
+        check("true", Value::Bool(true));
+        check("false", Value::Bool(false));
+        check("null", Value::Null);
+        check("true == false", Value::Bool(false));
+        check("true != false", Value::Bool(true));
+        check("null ?? 1", Value::Number(1.into()));
+        check("#1970-01-02#", Value::Number(86_400_000.into()));
+        check("#1970-01-01T01:00:01Z#", Value::Number(3_601_000.into()));
+        check("#2024-01-31# < #2024-02-01#", Value::Bool(true));
+        check("date('2024-01-31') == #2024-01-31#", Value::Bool(true));
+        check("date(dueDate) > #2024-01-31T23:59:59#", Value::Bool(true));
+        check("date(null)", Value::Null);
+        check("createTime == #2024-01-31#", Value::Bool(true));
+        check("createTime < #2024-02-01#", Value::Bool(true));
+        check("GET_NOW >= #2024-02-01#", Value::Bool(true));
+        check("GET_NOW - #2024-01-31#", Value::Number(86_400_000.into()));
+        check(
+            "GET_NOW - createTime == date(dueDate) - #2024-01-31#",
+            Value::Bool(true),
+        );
+        check(
+            "#2024-01-31# - #2024-01-01#",
+            Value::Number(2_592_000_000i64.into()),
+        );
+
+        // 数字字面量按十进制精确转换，包括科学计数法
+        let ratio = |numer: i64, denom: i64| Value::Number(Rational64::new(numer, denom));
+        check("1.2", ratio(6, 5));
+        check("0.1 + 0.2 == 0.3", Value::Bool(true));
+        check("2e3", ratio(2000, 1));
+        check("1E-3", ratio(1, 1000));
+        check("-1.5e+2", ratio(-150, 1));
+        check("2.50e1", ratio(25, 1));
+        check(
+            "123456789.123456789",
+            ratio(123456789123456789, 1_000_000_000),
+        );
+        check("9223372036854775807", ratio(i64::MAX, 1));
+        for expr in [
+            "1e19",
+            "9223372036854775808",
+            "1 + 0.0000000000000000001",
+            "1e-99999",
+        ] {
+            assert!(Formula::parse(expr).is_err(), "{}", expr);
+        }
+
+        assert_eq!(
+            run("date(remark)"),
+            Err(ExecuteError::invalid_date("tomorrow"))
+        );
+    }
+
+    #[test]
+    fn vm_strings() {
+        fn run(expr: &str) -> Result<Value, ExecuteError> {
+            let formula = Formula::parse(expr).unwrap();
+            let (_, ast) = to_ast(formula.paris);
+
+            let mut context = RuntimeContext::new();
+            context.inject_functions();
+            let issue = serde_json::json!({
+                "title": "修复登录页面😀",
+                "assignee": null,
+                "estimatePoint": 1.5,
+                "subtask": [{ "status": 2 }, { "status": 4 }, { "status": 2 }]
+            });
+            for (key, value) in issue.as_object().unwrap() {
+                context.set(key.to_string(), Value::from_json(value));
+            }
+
+            Runner.run(ast.to_operator(), &mut context)
+        }
+
+        let check = |expr: &str, target: &str| {
+            assert_eq!(run(expr), Ok(Value::String(target.to_string())), "{}", expr);
+        };
+        check(r"'it\'s'", "it's");
+        check(r#""say \"hi\"""#, "say \"hi\"");
+        check(r"'a\\b\n\t'", "a\\b\n\t");
+        check(r"'\u{4E2D}\u{6587}' + '\u{1F600}'", "中文😀");
+
+        check("``", "");
+        check("`plain`", "plain");
+        check("`Done: ${count(subtask)} tasks`", "Done: 3 tasks");
+        check(
+            "`${count(where(subtask, $.status == 2))}/${count(subtask)} 完成`",
+            "2/3 完成",
+        );
+        check("`估时 ${estimatePoint * 2} 天`", "估时 3 天");
+        check("`负责人：${assignee}`", "负责人：");
+        check("`${estimatePoint / 2}|${true}`", "0.75|true");
+        check(r"`\${a} \` ${'`'}`", "${a} ` `");
+        check("`a ${`b ${1 + 1}`} c`", "a b 2 c");
+
+        let number = |n: i64| Ok(Value::Number(n.into()));
+        assert_eq!(run("length(title)"), number(7));
+        assert_eq!(run("length('')"), number(0));
+        assert_eq!(run("length(`${title}!`)"), number(8));
+        check("substring(title, 0, 2)", "修复");
+        check("substring(title, 2)", "登录页面😀");
+        check("substring(title, -3, -1)", "页面");
+        check("substring(title, 5, 2)", "");
+        check("substring(title, 6, 100)", "😀");
+        check("upper('ready 就绪')", "READY 就绪");
+        check("lower('ÀÉ Done')", "àé done");
+        assert_eq!(run("length(assignee)"), Ok(Value::Null));
+        assert_eq!(run("substring(assignee, 1)"), Ok(Value::Null));
+
+        assert_eq!(
+            run("length(1)").unwrap_err().type_,
+            ExecuteErrorType::FunctionInvalidArgument
+        );
+        assert_eq!(
+            run("substring(title, 0.5)").unwrap_err().type_,
+            ExecuteErrorType::IndexNotInteger
+        );
+    }
+
+    #[test]
+    fn vm_let_bindings() {
+        fn run(expr: &str) -> Result<Value, ExecuteError> {
+            let formula = Formula::parse(expr).unwrap();
+            let (_, ast) = to_ast(formula.paris);
+
+            let mut context = RuntimeContext::new();
+            context.inject_functions();
+            let issue = serde_json::json!({
+                "estimatePoint": 3,
+                "subtask": [
+                    { "status": 2, "estimatePoint": 1 },
+                    { "status": 4, "estimatePoint": 2 },
+                    { "status": 2, "estimatePoint": 5 }
+                ]
+            });
+            for (key, value) in issue.as_object().unwrap() {
+                context.set(key.to_string(), Value::from_json(value));
+            }
+
+            Runner.run(ast.to_operator(), &mut context)
+        }
+
+        let number = |n: i64| Ok(Value::Number(n.into()));
+        assert_eq!(run("let a = 1; a + 1"), number(2));
+        assert_eq!(
+            run("let done = where(subtask, $.status == 2);\nsum(done.estimatePoint)"),
+            number(6)
+        );
+        assert_eq!(
+            run("let total = sum(subtask.estimatePoint);\nlet done = sum(where(subtask, $.status == 2).estimatePoint);\ntotal - done"),
+            number(2)
+        );
+        // 后面的 let 覆盖前面的绑定，也覆盖 Issue 字段
+        assert_eq!(run("let a = 1; let a = a + 1; a"), number(2));
+        assert_eq!(run("let estimatePoint = 10; estimatePoint"), number(10));
+        // lambda 中可以使用 let 绑定
+        assert_eq!(
+            run("let min = 2; count(where(subtask, $.estimatePoint >= min))"),
+            number(2)
+        );
+        // 只有最后一个表达式是结果
+        assert_eq!(run("1; 2; 3"), number(3));
+        assert_eq!(run("type A = { a: number }; estimatePoint * 2"), number(6));
+
+        assert_eq!(run("// 已完成\n1 + /* 内联 */ 2 // 结尾"), number(3));
+        assert_eq!(run("/* 多行\n注释 */\nlet a = 1; // a\na"), number(1));
+        assert_eq!(
+            run("'// 不是注释'"),
+            Ok(Value::String("// 不是注释".to_string()))
+        );
+
+        assert_eq!(
+            run("let a = 1;").unwrap_err().type_,
+            ExecuteErrorType::NoResultExpression
+        );
+        // 绑定之前不能使用，即使 Issue 中有同名的字段
+        assert_eq!(
+            run("estimatePoint + 1; let estimatePoint = 2; 0"),
+            Err(ExecuteError::identifier_not_found(
+                &"estimatePoint".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn vm_numbers() {
+        use formula_rs_wasm::{
+            share::decimal::{parse_decimal, DecimalError},
+            vm::number,
+        };
+
+        fn run(expr: &str) -> Result<Value, ExecuteErrorType> {
+            let formula = Formula::parse(expr).unwrap();
+            let (_, ast) = to_ast(formula.paris);
+            let mut context = RuntimeContext::new();
+            context.inject_functions();
+            let issue = serde_json::json!({ "a": 0.1, "b": 0.2, "s": [0.1, 0.2, 0.3] });
+            for (key, value) in issue.as_object().unwrap() {
+                context.set(key.to_string(), Value::from_json(value));
+            }
+            Runner
+                .run(ast.to_operator(), &mut context)
+                .map_err(|error| error.type_)
+        }
+        let ratio = |numer: i64, denom: i64| Value::Number(Rational64::new(numer, denom));
+
+        // JSON 中的小数按十进制文本转换
+        assert_eq!(run("a"), Ok(ratio(1, 10)));
+        assert_eq!(run("a + b == 0.3"), Ok(Value::Bool(true)));
+        assert_eq!(run("sum(s)"), Ok(ratio(3, 5)));
+        assert_eq!(Value::from_json(&serde_json::json!(1.5e-3)), ratio(3, 2000));
+        assert_eq!(
+            Value::from_json(&serde_json::json!(12345678901234567890u64)),
+            Value::Null
+        );
+
+        // 溢出时报错，不会 panic 或者回绕
+        assert_eq!(
+            run("9223372036854775807 + 1"),
+            Err(ExecuteErrorType::NumberOverflow)
+        );
+        assert_eq!(
+            run("9223372036854775807 * 2"),
+            Err(ExecuteErrorType::NumberOverflow)
+        );
+        assert_eq!(run("0 - 9223372036854775807 - 1"), Ok(ratio(i64::MIN, 1)));
+        assert_eq!(run("5 % 0"), Err(ExecuteErrorType::DivideByZero));
+        assert_eq!(run("7.5 % 2"), Ok(ratio(3, 2)));
+
+        // 时间间隔和数字相乘与顺序无关
+        assert_eq!(Value::Duration(3).mul(ratio(3, 2)), Ok(Value::Duration(4)));
+        assert_eq!(ratio(3, 2).mul(Value::Duration(3)), Ok(Value::Duration(4)));
+
+        // 中间结果溢出，约分后能放进 Rational64 时得到精确的结果
+        let m = 1i64 << 61;
+        assert_eq!(
+            number::add(
+                &Rational64::new(3 * m + 1, 3),
+                &Rational64::new(3 * m + 2, 3)
+            ),
+            Ok(Rational64::from_integer(2 * m + 1))
+        );
+        assert_eq!(
+            number::mul(&Rational64::new(i64::MAX, 3), &Rational64::new(3, i64::MAX)),
+            Ok(Rational64::from_integer(1))
+        );
+        assert_eq!(
+            number::neg(&Rational64::from_integer(i64::MIN))
+                .unwrap_err()
+                .type_,
+            ExecuteErrorType::NumberOverflow
+        );
+
+        assert_eq!(parse_decimal("0.1"), Ok(Rational64::new(1, 10)));
+        assert_eq!(parse_decimal("1.5e-3"), Ok(Rational64::new(3, 2000)));
+        assert_eq!(parse_decimal("-2E3"), Ok(Rational64::from_integer(-2000)));
+        assert_eq!(parse_decimal("100e-2"), Ok(Rational64::from_integer(1)));
+        assert_eq!(parse_decimal("0.000"), Ok(Rational64::from_integer(0)));
+        assert_eq!(
+            parse_decimal("1e-18"),
+            Ok(Rational64::new(1, 1_000_000_000_000_000_000))
+        );
+        assert_eq!(
+            parse_decimal("0e99999999999999999999"),
+            Ok(Rational64::from_integer(0))
+        );
+        assert_eq!(parse_decimal("1e19"), Err(DecimalError::OutOfRange));
+        assert_eq!(
+            parse_decimal("0.1234567890123456789"),
+            Err(DecimalError::OutOfRange)
+        );
+        assert_eq!(
+            parse_decimal("1e99999999999999999999"),
+            Err(DecimalError::OutOfRange)
+        );
+        for text in ["", "abc", "e5", "1e", "1.2.3", "--1", "1e+-2"] {
+            assert_eq!(parse_decimal(text), Err(DecimalError::Invalid), "{}", text);
+        }
+    }
+
+    #[test]
+    fn vm_resource_limits() {
+        use formula_rs_wasm::{
+            share::program::{CompileOptions, Program},
+            vm::context::Limits,
+        };
+        use std::sync::Arc;
+
+        fn run(expr: &str, limits: Limits) -> Result<Value, ExecuteErrorType> {
+            let formula = Formula::parse(expr).unwrap();
+            let (_, ast) = to_ast(formula.paris);
+
+            let mut context = RuntimeContext::new();
+            context.inject_functions();
+            context.limits = limits;
+            let subtask = serde_json::json!([{ "status": 2 }, { "status": 4 }, { "status": 2 }]);
+            context.set("subtask".to_string(), Value::from_json(&subtask));
+
+            Runner
+                .run(ast.to_operator(), &mut context)
+                .map_err(|error| error.type_)
+        }
+        let defaults = Limits::default();
+        let number = |n: i64| Ok(Value::Number(n.into()));
+
+        assert_eq!(
+            run("99999!", defaults),
+            Err(ExecuteErrorType::FactorialLimitExceeded)
+        );
+        assert_eq!(run("20!", defaults), number(2432902008176640000));
+        // 调大限制后溢出报错，不会 panic
+        let factorial = Limits {
+            max_factorial: 100,
+            ..defaults
+        };
+        assert_eq!(run("21!", factorial), Err(ExecuteErrorType::NumberOverflow));
+
+        assert_eq!(
+            run("2^2147483647", defaults),
+            Err(ExecuteErrorType::NumberOverflow)
+        );
+        assert_eq!(run("2^62", defaults), number(1 << 62));
+        assert_eq!(
+            run("(0 - 2)^(0 - 2)", defaults),
+            Ok(Value::Number(Rational64::new(1, 4)))
+        );
+        assert_eq!(
+            run("0^(0 - 1)", defaults),
+            Err(ExecuteErrorType::DivideByZero)
+        );
+
+        // lambda 中的指令也计数，每次执行重新计数
+        let instructions = Limits {
+            max_instructions: 10,
+            ..defaults
+        };
+        assert_eq!(
+            run("count(where(subtask, $.status == 2))", instructions),
+            Err(ExecuteErrorType::InstructionLimitExceeded)
+        );
+        assert_eq!(run("count(subtask)", instructions), number(3));
+        assert_eq!(run("count(subtask)", instructions), number(3));
+
+        let stack = Limits {
+            max_stack_depth: 3,
+            ..defaults
+        };
+        assert_eq!(
+            run("[1, 2, 3, 4]", stack),
+            Err(ExecuteErrorType::StackLimitExceeded)
+        );
+        assert_eq!(
+            run("[1, 2, 3]", stack),
+            Ok(Value::Array(vec![
+                Value::Number(1.into()),
+                Value::Number(2.into()),
+                Value::Number(3.into())
+            ]))
+        );
+
+        let array = Limits {
+            max_array_length: 2,
+            ..defaults
+        };
+        assert_eq!(
+            run("[1, 2, 3]", array),
+            Err(ExecuteErrorType::ArrayLengthLimitExceeded)
+        );
+        assert_eq!(
+            run("count(subtask)", array),
+            Err(ExecuteErrorType::ArrayLengthLimitExceeded)
+        );
+        assert_eq!(
+            run("count(where(subtask, $.status == 4))", array),
+            Err(ExecuteErrorType::ArrayLengthLimitExceeded)
+        );
+
+        let string = Limits {
+            max_string_length: 5,
+            ..defaults
+        };
+        assert_eq!(
+            run("'abc' + 'def'", string),
+            Err(ExecuteErrorType::StringLengthLimitExceeded)
+        );
+        assert_eq!(
+            run("`${'abc'}de`", string),
+            Ok(Value::String("abcde".to_string()))
+        );
+
+        // 超过限制的常量表达式不折叠，执行时按 ctx 的限制检查
+        let (_, ast) = to_ast(Formula::parse("25!").unwrap().paris);
+        let program = Arc::new(Program::compile(&ast, None, &CompileOptions::default()));
+        let mut context = RuntimeContext::new();
+        assert_eq!(
+            Runner
+                .run_program(&program, &mut context)
+                .unwrap_err()
+                .type_,
+            ExecuteErrorType::FactorialLimitExceeded
+        );
+        context.limits = factorial;
+        assert_eq!(
+            Runner
+                .run_program(&program, &mut context)
+                .unwrap_err()
+                .type_,
+            ExecuteErrorType::NumberOverflow
+        );
+    }
+
+    #[test]
+    fn vm_verify_bytecode() {
+        use formula_rs_wasm::{
+            share::operator::OperatorCode::{self, *},
+            vm::{runner::Runnable, verifier::verify},
+        };
+
+        let check = |codes: Vec<OperatorCode>, expected: Result<(), ExecuteErrorType>| {
+            assert_eq!(
+                verify(&codes).map_err(|error| error.type_),
+                expected,
+                "{:?}",
+                codes
+            );
+        };
+        let invalid = Err(ExecuteErrorType::InvalidBytecode);
+
+        for expr in [
+            "sum(where(subtask, $.status == 2), $.estimatePoint * 2)",
+            "parent?.assignee.name ?? subtask[1:][0]?.name ?? `${a}`",
+            "let a = [1, 2]; a[0]; -a[1]!",
+            "COUNT(subtask; status=2)",
+        ] {
+            let (_, ast) = to_ast(Formula::parse(expr).unwrap().paris);
+            check(ast.to_operator(), Ok(()));
+        }
+
+        check(vec![PushNumber(1.into()), Add], invalid);
+        check(vec![LoadIdentifier("sum".to_string()), Call(2)], invalid);
+        check(vec![PushNull, MakeArray(2)], invalid);
+        check(vec![PushNull, Slice(true, false)], invalid);
+        check(vec![PushNull, SkipIfNull(2), Negate], invalid);
+        // 跳转和不跳转时栈的深度不同
+        check(vec![PushNull, Coalesce(2), PushNull, PushNull], invalid);
+        check(vec![PushNull, SkipIfNull(1), Pop, PushNull], invalid);
+        check(
+            vec![
+                LoadIdentifier("where".to_string()),
+                PushNull,
+                PushLambda(vec![PushNull, PushNull]),
+                Call(2),
+            ],
+            invalid,
+        );
+        check(
+            vec![PushNull, PushNull],
+            Err(ExecuteErrorType::ResultCountMismatchError),
+        );
+        check(vec![], Err(ExecuteErrorType::ResultCountMismatchError));
+
+        let mut context = RuntimeContext::new();
+        context.inject_functions();
+        assert_eq!(
+            Runner
+                .run(vec![PushNumber(1.into()), Subtract], &mut context)
+                .unwrap_err()
+                .type_,
+            ExecuteErrorType::InvalidBytecode
+        );
+        // 跳过检查直接执行时返回 StackUnderflow 而不是 panic
+        for code in [
+            Add,
+            Call(1),
+            MakeArray(3),
+            Concat(1),
+            Slice(true, true),
+            Pop,
+        ] {
+            assert_eq!(
+                code.run(&mut RuntimeContext::new()).unwrap_err().type_,
+                ExecuteErrorType::StackUnderflow
+            );
+        }
     }
 }